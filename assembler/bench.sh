@@ -1,8 +1,14 @@
 #!/bin/bash
+# Assembles and runs each program passed as an argument, recording the number
+# of clock cycles each one takes to halt in results.txt
 
 echo -n "" > results.txt
 
-for (( i = 0; i <= $1; i++ ))
+for program in "$@"
 do
-    cargo run -- perf_test.gf 2>/dev/null | awk '{print $1}' >> results.txt
+    cargo run -q -p giggle-flop-assembler -- "$program" -o bench_bin || exit 1
+    echo -n "$program " >> results.txt
+    cargo run -q -p giggle-flop --bin giggle-flop-headless -- bench_bin --quiet >> results.txt
 done
+
+rm -f bench_bin
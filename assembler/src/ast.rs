@@ -0,0 +1,111 @@
+#![warn(clippy::all, clippy::pedantic)]
+
+use std::fmt::Display;
+use std::ops::Range;
+
+#[derive(Debug, Clone, Eq, PartialEq, Copy)]
+pub enum RegisterGroup {
+    General,
+    FloatingPoint,
+}
+
+/// A name as written in the source
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ident {
+    pub name: String,
+    pub span: Range<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+}
+
+/// Binary operators, from lowest to highest precedence
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Or,
+    Xor,
+    And,
+    Shl,
+    Shr,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExprKind {
+    Int(i64),
+    Symbol(String), // label or `.equ` constant
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+/// Integer expression evaluated once every symbol's value is known
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Range<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OperandKind {
+    Register { group: RegisterGroup, index: usize },
+    Float(f64),
+    Expr(Expr),
+}
+
+impl Display for OperandKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Register {
+                group: RegisterGroup::General,
+                index,
+            } => write!(f, "register R{index}"),
+            Self::Register {
+                group: RegisterGroup::FloatingPoint,
+                index,
+            } => write!(f, "register F{index}"),
+            Self::Float(val) => write!(f, "floating point value {val}"),
+            Self::Expr(Expr {
+                kind: ExprKind::Int(val),
+                ..
+            }) => write!(f, "integer {val}"),
+            Self::Expr(Expr {
+                kind: ExprKind::Symbol(name),
+                ..
+            }) => write!(f, "symbol {name}"),
+            Self::Expr(_) => write!(f, "expression"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Operand {
+    pub kind: OperandKind,
+    pub span: Range<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StatementKind {
+    Label(Ident),
+    Instruction {
+        mnemonic: Ident,
+        operands: Vec<Operand>,
+    },
+    Directive {
+        name: Ident, // without the leading `.`
+        operands: Vec<Operand>,
+    },
+}
+
+/// A label, instruction, or directive, several of which may share a line
+#[derive(Debug, Clone, PartialEq)]
+pub struct Statement {
+    pub kind: StatementKind,
+    pub span: Range<usize>, // byte range within the source
+}
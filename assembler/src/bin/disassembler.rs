@@ -0,0 +1,41 @@
+#![warn(clippy::all, clippy::pedantic)]
+
+use std::path::PathBuf;
+
+use anyhow::Result;
+use clap::Parser;
+
+use giggle_flop_assembler::disassemble;
+
+/// Turns an assembled program image back into assembler source
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct DisassemblerArgs {
+    input_file: PathBuf,
+    #[arg(
+        long,
+        short,
+        help = "Path to store the recovered source, printed to stdout if omitted"
+    )]
+    output_path: Option<PathBuf>,
+}
+
+fn disassemble_file(args: &DisassemblerArgs) -> Result<()> {
+    let image = std::fs::read(&args.input_file)?;
+    let source = disassemble(&image)?;
+    match args.output_path {
+        Some(ref path) => std::fs::write(path, source)?,
+        None => print!("{source}"),
+    }
+
+    Ok(())
+}
+
+fn main() {
+    let args = DisassemblerArgs::parse();
+
+    if let Err(e) = disassemble_file(&args) {
+        eprintln!("Error: {e}");
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,82 @@
+#![warn(clippy::all, clippy::pedantic)]
+
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context, Result};
+use clap::Parser;
+
+use giggle_flop_assembler::{link, Object, OutputFormat};
+
+const DEFAULT_OUTPUT_PATH: &str = "a";
+
+/// Combines objects written by `giggle-flop-assembler --object` into a
+/// single program image. The first object's first instruction is where the
+/// program starts
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct LinkerArgs {
+    #[arg(required = true)]
+    input_files: Vec<PathBuf>,
+    #[arg(long, short, help = "Path to store the program image")]
+    output_path: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Path to store a map of where each section and symbol ended up"
+    )]
+    map: Option<PathBuf>,
+    #[arg(
+        long,
+        value_enum,
+        default_value_t,
+        help = "Format of the program written out"
+    )]
+    format: OutputFormat,
+}
+
+fn link_files(args: &LinkerArgs) -> Result<()> {
+    let objects = args
+        .input_files
+        .iter()
+        .map(|path| {
+            let json = std::fs::read_to_string(path)?;
+            Object::from_json(&json).with_context(|| format!("Couldn't read {}", path.display()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let (program, map) = match link(&objects) {
+        Ok(linked) => linked,
+        Err(diagnostics) => {
+            for diagnostic in &diagnostics {
+                eprintln!("{diagnostic}\n");
+            }
+            return Err(anyhow!(
+                "Could not link due to {} error(s)",
+                diagnostics.error_count()
+            ));
+        }
+    };
+    for warning in &program.warnings {
+        eprintln!("{warning}\n");
+    }
+
+    let output_path = args
+        .output_path
+        .clone()
+        .unwrap_or_else(|| DEFAULT_OUTPUT_PATH.into());
+    std::fs::write(output_path, args.format.render(&program))?;
+    if let Some(ref path) = args.map {
+        std::fs::write(path, map.to_string())?;
+    }
+
+    Ok(())
+}
+
+fn main() {
+    let args = LinkerArgs::parse();
+
+    if let Err(e) = link_files(&args) {
+        eprintln!("Error: {e:#}");
+        std::process::exit(1);
+    }
+}
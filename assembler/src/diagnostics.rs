@@ -0,0 +1,191 @@
+#![warn(clippy::all, clippy::pedantic)]
+
+use std::fmt::Display;
+
+use serde::Serialize;
+
+/// How serious a diagnostic is. Only errors keep a program from assembling,
+/// and notes only appear attached to another diagnostic
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Error => write!(f, "error"),
+            Self::Warning => write!(f, "warning"),
+            Self::Note => write!(f, "note"),
+        }
+    }
+}
+
+/// Location of a diagnostic within its file. Lines and columns count from 1,
+/// and `column_end` is exclusive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub struct Span {
+    pub line: usize,
+    pub column_start: usize,
+    pub column_end: usize,
+}
+
+/// A single problem found in the source
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub file: String,
+    pub span: Option<Span>,
+    #[serde(skip)]
+    pub source_line: Option<String>, // text of the line `span` points into
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<Box<Diagnostic>>, // related location, e.g. where a macro was invoked
+}
+
+impl Diagnostic {
+    /// Creates an error about `file` as a whole rather than a place in it
+    #[must_use]
+    pub fn file_error(file: &str, message: String) -> Self {
+        Self {
+            severity: Severity::Error,
+            message,
+            file: file.to_string(),
+            span: None,
+            source_line: None,
+            note: None,
+        }
+    }
+
+    #[must_use]
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+/// Renders the diagnostic the way rustc does, with the offending part of the
+/// source line underlined
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.severity, self.message)?;
+        let Some(span) = self.span else {
+            return write!(f, "\n --> {}", self.file);
+        };
+
+        let gutter = " ".repeat(span.line.to_string().len());
+        write!(
+            f,
+            "\n{gutter}--> {}:{}:{}",
+            self.file, span.line, span.column_start
+        )?;
+        if let Some(ref text) = self.source_line {
+            // tabs would throw off the carets' alignment
+            let text = text.replace('\t', " ");
+            let padding = " ".repeat(span.column_start.saturating_sub(1));
+            let carets = "^".repeat((span.column_end.saturating_sub(span.column_start)).max(1));
+            write!(
+                f,
+                "\n{gutter} |\n{} | {text}\n{gutter} | {padding}{carets}",
+                span.line
+            )?;
+        }
+        if let Some(ref note) = self.note {
+            write!(f, "\n{note}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Everything reported while assembling a program. When returned as an error
+/// it holds at least one error, otherwise only warnings
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Diagnostics {
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    pub fn iter(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics.iter()
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.diagnostics.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+
+    #[must_use]
+    pub fn error_count(&self) -> usize {
+        self.diagnostics.iter().filter(|d| d.is_error()).count()
+    }
+
+    #[must_use]
+    pub fn has_errors(&self) -> bool {
+        self.error_count() > 0
+    }
+}
+
+impl From<Vec<Diagnostic>> for Diagnostics {
+    fn from(diagnostics: Vec<Diagnostic>) -> Self {
+        Self { diagnostics }
+    }
+}
+
+impl<'a> IntoIterator for &'a Diagnostics {
+    type Item = &'a Diagnostic;
+    type IntoIter = std::slice::Iter<'a, Diagnostic>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.diagnostics.iter()
+    }
+}
+
+impl Display for Diagnostics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, diagnostic) in self.diagnostics.iter().enumerate() {
+            if i > 0 {
+                write!(f, "\n\n")?;
+            }
+            write!(f, "{diagnostic}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Diagnostics {}
+
+#[cfg(test)]
+mod test {
+    use crate::diagnostics::{Diagnostic, Severity, Span};
+
+    #[test]
+    fn renders_caret_excerpt() {
+        let diagnostic = Diagnostic {
+            severity: Severity::Error,
+            message: "Undefined label nowhere".to_string(),
+            file: "prog.gf".to_string(),
+            span: Some(Span {
+                line: 12,
+                column_start: 8,
+                column_end: 15,
+            }),
+            source_line: Some("    JE nowhere // skip".to_string()),
+            note: None,
+        };
+        assert_eq!(
+            diagnostic.to_string(),
+            "error: Undefined label nowhere
+  --> prog.gf:12:8
+   |
+12 |     JE nowhere // skip
+   |        ^^^^^^^"
+        );
+    }
+}
@@ -0,0 +1,445 @@
+#![warn(clippy::all, clippy::pedantic)]
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use anyhow::{anyhow, Result};
+
+use giggle_flop::instruction::instruction::{decode_raw_instr, sign_extend_immediate, Instruction};
+use giggle_flop::memory::memory_system::ADDRESS_SPACE_SIZE;
+use giggle_flop::register::register_system::ALL_INSTR_TYPES;
+use giggle_flop::system::image::{ProgramImage, Segment};
+
+use crate::{assemble, Address, Program, INSTR_START_ADDR, INSTR_WIDTH_BITS};
+
+const WORD_BYTES: usize = 4;
+// Width the instruction column is padded to before the address comment
+const INSTR_COLUMN_WIDTH: usize = 24;
+
+/// Label names by address, jumps referring to each address by its first
+type Labels = BTreeMap<Address, Vec<String>>;
+
+/// Recovers assembler source from a program image, either raw or in a
+/// container. For a container, the executable segments become instructions,
+/// the rest `.WORD` data, and its symbol table names the labels. For a raw
+/// image, words reachable from address 0 become instructions and everything
+/// after the last of them data. Labels are synthesized for any `CALL` and
+/// jump targets left unnamed
+///
+/// # Errors
+///
+/// Returns an error if `image` isn't a whole number of words or a valid
+/// container laid out the way the assembler lays programs out, or if the
+/// recovered source doesn't reassemble to `image`, apart from the symbols of
+/// any labels it synthesized
+pub fn disassemble(image: &[u8]) -> Result<String> {
+    if ProgramImage::is_container(image) {
+        return disassemble_container(image);
+    }
+    if !image.len().is_multiple_of(WORD_BYTES) {
+        return Err(anyhow!(
+            "Image length {} is not a whole number of 32-bit words",
+            image.len()
+        ));
+    }
+    if image.len() * 8 > ADDRESS_SPACE_SIZE {
+        return Err(anyhow!(
+            "Image length {} exceeds the address space",
+            image.len()
+        ));
+    }
+
+    let words: Vec<u32> = image
+        .chunks_exact(WORD_BYTES)
+        .map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .collect();
+    let instrs: Vec<Option<Instruction>> = words.iter().map(|word| decode_exact(*word)).collect();
+    let code_len = get_code_len(&instrs);
+    let labels = get_labels(&instrs[..code_len], words.len(), Labels::new());
+    let source = render_source(&words, &instrs, code_len, &labels)?;
+
+    if reassemble(&source)?.bytes != image {
+        return Err(anyhow!(
+            "Disassembly doesn't reassemble to the original image"
+        ));
+    }
+    Ok(source)
+}
+
+fn disassemble_container(container: &[u8]) -> Result<String> {
+    let image = ProgramImage::from_container(container)?;
+    let (words, code_len) = flatten(&image)?;
+    let instrs: Vec<Option<Instruction>> = words[..code_len]
+        .iter()
+        .map(|word| decode_exact(*word))
+        .collect();
+    if let Some(idx) = instrs.iter().position(Option::is_none) {
+        return Err(anyhow!(
+            "Word at 0x{:06X} in an executable segment isn't an instruction the assembler \
+             produces, so the program can't be disassembled",
+            word_addr(idx)
+        ));
+    }
+
+    let mut symbols = Labels::new();
+    for (name, &address) in &image.symbols {
+        if word_idx(address).is_none_or(|idx| idx > words.len()) {
+            return Err(anyhow!(
+                "Symbol {name} at 0x{address:08X} doesn't name a word of the program, so it \
+                 can't be written as a label"
+            ));
+        }
+        symbols.entry(address).or_default().push(name.clone());
+    }
+    let labels = get_labels(&instrs, words.len(), symbols);
+    let source = render_source(&words, &instrs, code_len, &labels)?;
+
+    // labels synthesized for unnamed jump targets aren't part of the original
+    let mut reassembled = reassemble(&source)?.image();
+    reassembled
+        .symbols
+        .retain(|name, _| image.symbols.contains_key(name));
+    if reassembled.to_bytes() != container {
+        return Err(anyhow!(
+            "Disassembly doesn't reassemble to the original container"
+        ));
+    }
+    Ok(source)
+}
+
+/// Returns the words the loader would place from address 0 onward, and how
+/// many of them come from executable segments, as long as the segments are
+/// laid out the way the assembler lays them out: back to back from address 0,
+/// instructions first, and starting at the beginning
+fn flatten(image: &ProgramImage) -> Result<(Vec<u32>, usize)> {
+    if image.entry.is_some_and(|entry| entry != INSTR_START_ADDR) {
+        return Err(anyhow!(
+            "Only programs starting at address 0x{INSTR_START_ADDR:08X} can be disassembled"
+        ));
+    }
+    let mut segments: Vec<&Segment> = image.segments.iter().collect();
+    segments.sort_by_key(|segment| segment.address);
+
+    let mut words = vec![];
+    let mut code_len = 0;
+    for segment in segments {
+        if segment.address != word_addr(words.len()) {
+            return Err(anyhow!(
+                "Segment at 0x{:08X} doesn't directly follow the one before it, so the \
+                 program can't be disassembled",
+                segment.address
+            ));
+        }
+        if segment.executable {
+            if code_len != words.len() {
+                return Err(anyhow!(
+                    "Executable segment at 0x{:08X} follows data, so the program can't be \
+                     disassembled",
+                    segment.address
+                ));
+            }
+            code_len += segment.words.len();
+        }
+        words.extend(&segment.words);
+    }
+    Ok((words, code_len))
+}
+
+/// Writes out `words`, the first `code_len` of them as `instrs` and the rest
+/// as `.WORD` data, with `labels` defined ahead of the words they name
+fn render_source(
+    words: &[u32],
+    instrs: &[Option<Instruction>],
+    code_len: usize,
+    labels: &Labels,
+) -> Result<String> {
+    let mut source = String::new();
+    for (idx, word) in words.iter().enumerate() {
+        let addr = word_addr(idx);
+        if idx == code_len {
+            writeln!(source, ".DATA")?;
+        }
+        for label in labels.get(&addr).into_iter().flatten() {
+            writeln!(source, "{label}:")?;
+        }
+        let line = match instrs.get(idx).copied().flatten() {
+            Some(instr) if idx < code_len => render(instr, labels),
+            _ => format!(".WORD {word}"),
+        };
+        writeln!(source, "    {line:<INSTR_COLUMN_WIDTH$} // 0x{addr:06X}")?;
+    }
+    // labels may also point just past the last word
+    for label in labels.get(&word_addr(words.len())).into_iter().flatten() {
+        writeln!(source, "{label}:")?;
+    }
+    Ok(source)
+}
+
+fn reassemble(source: &str) -> Result<Program> {
+    assemble(source).map_err(|e| anyhow!("Disassembly failed to reassemble: {e}"))
+}
+
+fn word_addr(idx: usize) -> Address {
+    Address::try_from(idx)
+        .unwrap_or(Address::MAX)
+        .saturating_mul(INSTR_WIDTH_BITS)
+}
+
+fn word_idx(addr: Address) -> Option<usize> {
+    if addr.is_multiple_of(INSTR_WIDTH_BITS) {
+        usize::try_from(addr / INSTR_WIDTH_BITS).ok()
+    } else {
+        None
+    }
+}
+
+/// Decodes `word`, as long as assembling the result gives back `word` exactly
+fn decode_exact(word: u32) -> Option<Instruction> {
+    decode_raw_instr(word)
+        .filter(Instruction::is_legal)
+        .filter(|instr| {
+            assemble(&render(*instr, &Labels::new()))
+                .is_ok_and(|program| program.bytes == word.to_be_bytes())
+        })
+}
+
+/// Returns the number of leading words that are treated as instructions,
+/// stopping after the last one reachable from address 0
+fn get_code_len(instrs: &[Option<Instruction>]) -> usize {
+    // the assembler places data after every instruction, so nothing past a
+    // word that can't be reassembled can be an instruction
+    let limit = instrs
+        .iter()
+        .position(Option::is_none)
+        .unwrap_or(instrs.len());
+    let mut reachable = vec![false; limit];
+    let mut pending = vec![0];
+
+    while let Some(mut idx) = pending.pop() {
+        while idx < limit && !reachable[idx] {
+            reachable[idx] = true;
+            let Some(instr) = instrs[idx] else {
+                break;
+            };
+            if let Some(target) = instr.direct_target(word_addr(idx)).and_then(word_idx) {
+                pending.push(target);
+            }
+            // RET and HALT don't fall through
+            if let Instruction::Type0 { .. } = instr {
+                break;
+            }
+            idx += 1;
+        }
+    }
+
+    reachable.iter().rposition(|r| *r).map_or(0, |idx| idx + 1)
+}
+
+/// Adds a name for every address within the image targeted by a `CALL` or
+/// jump to those already in `labels`
+fn get_labels(code: &[Option<Instruction>], n_words: usize, mut labels: Labels) -> Labels {
+    let mut synthesized = BTreeMap::new();
+    for (idx, instr) in code.iter().enumerate() {
+        let Some(instr) = instr else {
+            continue;
+        };
+        let Some(target) = instr
+            .direct_target(word_addr(idx))
+            .filter(|target| word_idx(*target).is_some_and(|target_idx| target_idx < n_words))
+        else {
+            continue;
+        };
+        // subroutine names win over plain jump targets, and PC relative jumps
+        // are written with their offset, so don't need one
+        if let Instruction::Type1 { opcode: 7..=12, .. } = instr {
+            continue;
+        }
+        if let Instruction::Type1 { opcode: 0, .. } = instr {
+            synthesized.insert(target, format!("sub_{target:06X}"));
+        } else {
+            synthesized
+                .entry(target)
+                .or_insert_with(|| format!("loc_{target:06X}"));
+        }
+    }
+    for (target, name) in synthesized {
+        labels.entry(target).or_insert_with(|| vec![name]);
+    }
+    labels
+}
+
+/// Returns the value an instruction sign-extending its immediate sees
+#[allow(clippy::cast_possible_wrap)]
+fn signed_immediate(immediate: u32) -> i32 {
+    sign_extend_immediate(immediate) as i32
+}
+
+/// Renders `instr` in the syntax the assembler accepts, referring to
+/// absolute targets in `labels` by name
+fn render(instr: Instruction, labels: &Labels) -> String {
+    let name = ALL_INSTR_TYPES[instr.instr_type()][instr.opcode() as usize];
+
+    match instr {
+        Instruction::Type0 { .. } => name.to_string(),
+        // CALL and the absolute jumps take their target as a label, PC
+        // relative jumps a signed offset
+        Instruction::Type1 {
+            opcode: 0..=6,
+            immediate,
+        } if labels.contains_key(&immediate) => format!("{name} {}", labels[&immediate][0]),
+        Instruction::Type1 {
+            opcode: 7..=12,
+            immediate,
+        } => format!("{name} {}", signed_immediate(immediate)),
+        Instruction::Type1 { immediate, .. } => format!("{name} {immediate}"),
+        Instruction::Type2 { reg_1, reg_2, .. } => format!("{name} R{reg_1}, R{reg_2}"),
+        Instruction::Type3 { freg_1, freg_2, .. } => format!("{name} F{freg_1}, F{freg_2}"),
+        // ADDIM
+        Instruction::Type4 {
+            opcode: 9,
+            reg_1,
+            immediate,
+        } => format!("{name} R{reg_1}, {}", signed_immediate(immediate)),
+        Instruction::Type4 {
+            reg_1, immediate, ..
+        } => format!("{name} R{reg_1}, {immediate}"),
+        Instruction::Type5 {
+            reg_1,
+            reg_2,
+            reg_3,
+            ..
+        } => format!("{name} R{reg_1}, R{reg_2}, R{reg_3}"),
+        Instruction::Type6 {
+            freg_1,
+            freg_2,
+            freg_3,
+            ..
+        } => format!("{name} F{freg_1}, F{freg_2}, F{freg_3}"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::assemble;
+    use crate::disassembler::disassemble;
+
+    #[test]
+    fn round_trips_with_synthesized_labels() {
+        let program = assemble(
+            "LDI32 R1, 5
+            CALL func
+            top:
+            ADDIM R1, 1
+            JNE top
+            HALT
+            func:
+            ADDIM R2, 2
+            RET
+            .DATA
+            .WORD 8, 19, 4294967295",
+        )
+        .unwrap();
+
+        let source = disassemble(&program.bytes).unwrap();
+        assert!(source.contains("CALL sub_0000A0"));
+        assert!(source.contains("JNE loc_000040"));
+        // data that happens to decode as HALT and CMPF stays data
+        assert!(source.contains(".DATA"));
+        assert!(source.contains(".WORD 8 "));
+        assert!(source.contains(".WORD 19 "));
+        assert_eq!(assemble(&source).unwrap().bytes, program.bytes);
+    }
+
+    #[test]
+    fn round_trips_containers() {
+        let program = assemble(
+            "start:
+            LDI32 R1, 5
+            CALL func
+            top:
+            ADDIM R1, -1
+            JNE top
+            HALT
+            unused: ORI R0, R0, R0
+            func:
+            ADDIM R2, 2
+            RET
+            .DATA
+            values: .WORD 8, 19
+            end:",
+        )
+        .unwrap();
+        let container = program.image().to_bytes();
+
+        let source = disassemble(&container).unwrap();
+        assert!(source.contains("CALL func"));
+        assert!(source.contains("JNE top"));
+        assert!(!source.contains("sub_"));
+        assert!(source.contains("values:"));
+        assert!(source.trim_end().ends_with("end:"));
+        // unreachable instructions stay in the executable segment
+        assert!(source.contains("unused:\n    ORI R0, R0, R0"));
+        let reassembled = assemble(&source).unwrap();
+        assert_eq!(reassembled.symbols, program.symbols);
+        assert_eq!(reassembled.image().to_bytes(), container);
+
+        // labels local to a macro expansion can't be written in source
+        let spin = assemble(
+            ".macro SPIN r
+            again: ADDIM \\r, 1
+            JNE again
+            .endm
+            SPIN R1
+            SPIN R2
+            HALT",
+        )
+        .unwrap();
+        assert!(spin.symbols.is_empty());
+        let container = spin.image().to_bytes();
+        let source = disassemble(&container).unwrap();
+        assert!(source.contains("JNE loc_000000"));
+        assert!(source.contains("JNE loc_000040"));
+        let reassembled = assemble(&source).unwrap().image();
+        assert_eq!(reassembled.segments, spin.image().segments);
+
+        let mut image = program.image();
+        image.entry = Some(32);
+        assert!(disassemble(&image.to_bytes()).is_err());
+        let mut image = program.image();
+        image.symbols.insert("unaligned".to_string(), 7);
+        assert!(disassemble(&image.to_bytes()).is_err());
+    }
+
+    #[test]
+    fn writes_signed_offsets() {
+        let program = assemble(
+            "top:
+            ADDIM R1, -1
+            CMP32 R1, R0
+            IJNE -64
+            IJE 32
+            HALT",
+        )
+        .unwrap();
+
+        let source = disassemble(&program.bytes).unwrap();
+        assert!(source.contains("ADDIM R1, -1 "));
+        assert!(source.contains("IJNE -64 "));
+        assert!(source.contains("IJE 32 "));
+        // nothing refers to the relative jumps' targets by name
+        assert!(!source.contains("loc_"));
+        let reassembled = assemble(&source).unwrap();
+        assert!(reassembled.warnings.is_empty());
+        assert_eq!(reassembled.bytes, program.bytes);
+    }
+
+    #[test]
+    fn undecodable_words_become_data() {
+        let image = [0, 0, 0, 8, 0xFF, 0xFF, 0xFF, 0xFF];
+        let source = disassemble(&image).unwrap();
+        assert!(source.contains("HALT"));
+        assert!(source.contains(".WORD 4294967295"));
+
+        assert!(disassemble(&image[..6]).is_err());
+    }
+}
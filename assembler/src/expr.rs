@@ -0,0 +1,120 @@
+#![warn(clippy::all, clippy::pedantic)]
+
+use std::ops::Range;
+
+use crate::ast::{BinaryOp, Expr, ExprKind, UnaryOp};
+use crate::diagnostics::Diagnostic;
+use crate::object::Target;
+use crate::source::SourceFile;
+
+/// Value of an expression. Addresses that aren't known until the program is
+/// linked are kept as an offset from what they depend on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Value {
+    pub offset: i64,
+    pub base: Option<Target>, // what `offset` is relative to, if not 0
+}
+
+impl From<i64> for Value {
+    fn from(offset: i64) -> Self {
+        Self { offset, base: None }
+    }
+}
+
+/// Evaluates `expr` with 64-bit signed arithmetic, looking up the value of
+/// each symbol it refers to with `lookup`. Only constants may be added to or
+/// subtracted from an address that isn't known yet, though two such
+/// addresses within the same section may be subtracted
+pub fn evaluate(
+    file: &SourceFile,
+    expr: &Expr,
+    lookup: &impl Fn(&str) -> Option<Value>,
+) -> Result<Value, Diagnostic> {
+    let unknown_address = || {
+        file.error(
+            &expr.span,
+            "Addresses aren't known until link time, so only constants can be added to or \
+             subtracted from them"
+                .to_string(),
+        )
+    };
+
+    let (val, base) = match &expr.kind {
+        ExprKind::Int(val) => (Some(*val), None),
+        ExprKind::Symbol(name) => {
+            return lookup(name)
+                .ok_or_else(|| file.error(&expr.span, format!("Undefined symbol {name}")));
+        }
+        ExprKind::Unary(op, operand) => {
+            let Value { offset, base: None } = evaluate(file, operand, lookup)? else {
+                return Err(unknown_address());
+            };
+            let val = match op {
+                UnaryOp::Neg => offset.checked_neg(),
+                UnaryOp::Not => Some(!offset),
+            };
+            (val, None)
+        }
+        ExprKind::Binary(op, lhs, rhs) => {
+            let lhs = evaluate(file, lhs, lookup)?;
+            let rhs_val = evaluate(file, rhs, lookup)?;
+            let base = match (op, lhs.base, rhs_val.base) {
+                (_, None, None) => None,
+                (BinaryOp::Add | BinaryOp::Sub, Some(base), None)
+                | (BinaryOp::Add, None, Some(base)) => Some(base),
+                // the distance between two addresses in the same section is fixed
+                (BinaryOp::Sub, Some(lhs_base), Some(rhs_base)) if lhs_base == rhs_base => None,
+                _ => return Err(unknown_address()),
+            };
+            let (lhs, rhs_val) = (lhs.offset, rhs_val.offset);
+            let val = match op {
+                BinaryOp::Div | BinaryOp::Rem if rhs_val == 0 => {
+                    return Err(file.error(&rhs.span, "Division by zero".to_string()));
+                }
+                BinaryOp::Shl | BinaryOp::Shr if !(0..64).contains(&rhs_val) => {
+                    return Err(file.error(
+                        &rhs.span,
+                        format!("Shift amount {rhs_val} is out of range. Valid range is [0, 63]"),
+                    ));
+                }
+                BinaryOp::Or => Some(lhs | rhs_val),
+                BinaryOp::Xor => Some(lhs ^ rhs_val),
+                BinaryOp::And => Some(lhs & rhs_val),
+                // the shift amount was checked above
+                #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                BinaryOp::Shl => Some(lhs << rhs_val as u32),
+                #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                BinaryOp::Shr => Some(lhs >> rhs_val as u32),
+                BinaryOp::Add => lhs.checked_add(rhs_val),
+                BinaryOp::Sub => lhs.checked_sub(rhs_val),
+                BinaryOp::Mul => lhs.checked_mul(rhs_val),
+                BinaryOp::Div => lhs.checked_div(rhs_val),
+                BinaryOp::Rem => lhs.checked_rem(rhs_val),
+            };
+            (val, base)
+        }
+    };
+
+    let offset = val.ok_or_else(|| {
+        file.error(
+            &expr.span,
+            "Expression overflows a 64-bit integer".to_string(),
+        )
+    })?;
+    Ok(Value { offset, base })
+}
+
+/// Returns the name and span of every symbol `expr` refers to, in the order
+/// they're written
+pub fn symbols(expr: &Expr) -> Vec<(&str, &Range<usize>)> {
+    match &expr.kind {
+        ExprKind::Int(_) => vec![],
+        ExprKind::Symbol(name) => vec![(name, &expr.span)],
+        ExprKind::Unary(_, operand) => symbols(operand),
+        ExprKind::Binary(_, lhs, rhs) => {
+            let mut found = symbols(lhs);
+            found.append(&mut symbols(rhs));
+            found
+        }
+    }
+}
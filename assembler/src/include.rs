@@ -0,0 +1,157 @@
+#![warn(clippy::all, clippy::pedantic)]
+
+use std::path::{Path, PathBuf};
+
+use crate::diagnostics::Diagnostic;
+use crate::lexer::{self, Token, TokenKind};
+use crate::source::{FileId, SourceFile};
+
+const INCLUDE_DIRECTIVE: &str = "INCLUDE";
+
+/// Tokenizes the file `id`, replacing each `.include "path"` line with the
+/// tokens of the file it names. Paths are looked up relative to the including
+/// file, then in each of `include_dirs`
+pub fn expand(
+    file: &mut SourceFile,
+    id: FileId,
+    include_dirs: &[PathBuf],
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Vec<Token> {
+    // files currently being included, to catch ones that include themselves
+    let mut stack: Vec<PathBuf> = Path::new(file.name(id))
+        .canonicalize()
+        .into_iter()
+        .collect();
+    expand_file(file, id, include_dirs, &mut stack, diagnostics)
+}
+
+fn expand_file(
+    file: &mut SourceFile,
+    id: FileId,
+    include_dirs: &[PathBuf],
+    stack: &mut Vec<PathBuf>,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Vec<Token> {
+    let mut tokens = lexer::tokenize(file, id, diagnostics)
+        .into_iter()
+        .peekable();
+    let mut expanded = vec![];
+    let mut line_start = true;
+    while let Some(token) = tokens.next() {
+        let is_include = line_start
+            && matches!(&token.kind, TokenKind::Directive(name)
+                if name.eq_ignore_ascii_case(INCLUDE_DIRECTIVE));
+        line_start = token.kind == TokenKind::Newline;
+        if !is_include {
+            expanded.push(token);
+            continue;
+        }
+
+        let mut operands = vec![];
+        while let Some(operand) =
+            tokens.next_if(|t| !matches!(t.kind, TokenKind::Newline | TokenKind::Eof))
+        {
+            operands.push(operand);
+        }
+        match include(
+            file,
+            id,
+            &token,
+            &operands,
+            include_dirs,
+            stack,
+            diagnostics,
+        ) {
+            Ok(mut included) => expanded.append(&mut included),
+            Err(diagnostic) => diagnostics.push(diagnostic),
+        }
+    }
+    expanded
+}
+
+/// Reads in and expands the file named by the `.include` directive in the
+/// file `id`, returning its tokens without the trailing `Eof`
+fn include(
+    file: &mut SourceFile,
+    id: FileId,
+    directive: &Token,
+    operands: &[Token],
+    include_dirs: &[PathBuf],
+    stack: &mut Vec<PathBuf>,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Result<Vec<Token>, Diagnostic> {
+    let [Token {
+        kind: TokenKind::Str(path),
+        span,
+    }] = operands
+    else {
+        let span = operands
+            .first()
+            .map_or(&directive.span, |operand| &operand.span);
+        return Err(file.error(
+            span,
+            format!(".{INCLUDE_DIRECTIVE} takes a single quoted path. Usage: .{INCLUDE_DIRECTIVE} \"<path>\""),
+        ));
+    };
+
+    let including_dir = Path::new(file.name(id)).parent().map(Path::to_path_buf);
+    let Some(found) = including_dir
+        .iter()
+        .chain(include_dirs)
+        .map(|dir| dir.join(path))
+        .find(|candidate| candidate.is_file())
+    else {
+        return Err(file.error(span, format!("Couldn't find included file {path}")));
+    };
+    let canonical = found.canonicalize().unwrap_or_else(|_| found.clone());
+    if stack.contains(&canonical) {
+        return Err(file.error(span, format!("{path} would end up including itself")));
+    }
+    let text = std::fs::read_to_string(&found)
+        .map_err(|e| file.error(span, format!("Couldn't read included file {path}: {e}")))?;
+
+    let included = file.add_include(&found.display().to_string(), text, span.start);
+    stack.push(canonical);
+    let mut tokens = expand_file(file, included, include_dirs, stack, diagnostics);
+    stack.pop();
+    tokens.pop();
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::include::expand;
+    use crate::lexer::TokenKind;
+    use crate::source::{SourceFile, MAIN_FILE};
+
+    #[test]
+    fn splices_in_included_files() {
+        let dir = std::env::temp_dir().join(format!("giggle-flop-include-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("lib")).unwrap();
+        std::fs::write(dir.join("lib/ret.gf"), "RET\n.include \"../loop.gf\"").unwrap();
+        std::fs::write(dir.join("loop.gf"), ".include \"lib/ret.gf\"\n").unwrap();
+
+        let main = dir.join("main.gf");
+        let mut file =
+            SourceFile::new(&main.display().to_string(), ".include \"lib/ret.gf\"\nHALT");
+        let mut diagnostics = vec![];
+        let tokens = expand(&mut file, MAIN_FILE, &[], &mut diagnostics);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // loop.gf including ret.gf again is caught rather than recursing forever
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].file.ends_with("loop.gf"));
+        let kinds: Vec<_> = tokens.into_iter().map(|token| token.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TokenKind::Ident("RET".to_string()),
+                TokenKind::Newline,
+                TokenKind::Newline,
+                TokenKind::Newline,
+                TokenKind::Ident("HALT".to_string()),
+                TokenKind::Eof,
+            ]
+        );
+    }
+}
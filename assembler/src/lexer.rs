@@ -0,0 +1,400 @@
+#![warn(clippy::all, clippy::pedantic)]
+
+use std::fmt::Display;
+use std::ops::Range;
+
+use crate::diagnostics::Diagnostic;
+use crate::source::{FileId, SourceFile};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind {
+    Ident(String),      // mnemonics, registers, and labels
+    Directive(String),  // name following a `.`
+    MacroParam(String), // name following a `\`, within a macro body
+    Int(u64),
+    Float(f64),
+    Str(String), // quoted, with escapes resolved
+    Comma,
+    Colon,
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Percent,
+    Shl,
+    Shr,
+    Amp,
+    Pipe,
+    Caret,
+    Tilde,
+    LParen,
+    RParen,
+    Newline,
+    Eof,
+}
+
+impl Display for TokenKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Ident(name) => write!(f, "`{name}`"),
+            Self::Directive(name) => write!(f, "directive `.{name}`"),
+            Self::MacroParam(name) => write!(f, "macro parameter `\\{name}`"),
+            Self::Int(val) => write!(f, "number `{val}`"),
+            Self::Float(val) => write!(f, "number `{val}`"),
+            Self::Str(val) => write!(f, "string {val:?}"),
+            Self::Comma => write!(f, "`,`"),
+            Self::Colon => write!(f, "`:`"),
+            Self::Plus => write!(f, "`+`"),
+            Self::Minus => write!(f, "`-`"),
+            Self::Star => write!(f, "`*`"),
+            Self::Slash => write!(f, "`/`"),
+            Self::Percent => write!(f, "`%`"),
+            Self::Shl => write!(f, "`<<`"),
+            Self::Shr => write!(f, "`>>`"),
+            Self::Amp => write!(f, "`&`"),
+            Self::Pipe => write!(f, "`|`"),
+            Self::Caret => write!(f, "`^`"),
+            Self::Tilde => write!(f, "`~`"),
+            Self::LParen => write!(f, "`(`"),
+            Self::RParen => write!(f, "`)`"),
+            Self::Newline => write!(f, "the end of the line"),
+            Self::Eof => write!(f, "the end of the file"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Range<usize>, // byte range within the source
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// Splits the file `id` into tokens, always ending with `Eof`. Comments and
+/// whitespace other than line breaks are dropped, and characters that can't
+/// start a token are reported and skipped
+pub fn tokenize(file: &SourceFile, id: FileId, diagnostics: &mut Vec<Diagnostic>) -> Vec<Token> {
+    let text = file.text(id);
+    // spans are offsets within the whole source, not just this file
+    let base = file.start(id);
+    let mut tokens = vec![];
+    let mut chars = text.char_indices().peekable();
+
+    // byte offset just past the run of characters matching `pred` from `start`
+    let run_end = |start: usize, pred: fn(char) -> bool| {
+        text[start..]
+            .find(|c: char| !pred(c))
+            .map_or(text.len(), |len| start + len)
+    };
+
+    while let Some((start, c)) = chars.next() {
+        let (kind, end) = match c {
+            '\n' => (TokenKind::Newline, start + 1),
+            ',' => (TokenKind::Comma, start + 1),
+            ':' => (TokenKind::Colon, start + 1),
+            '+' => (TokenKind::Plus, start + 1),
+            '-' => (TokenKind::Minus, start + 1),
+            '*' => (TokenKind::Star, start + 1),
+            '/' if text[start..].starts_with("//") => {
+                // comments run to the end of the line
+                let end = text[start..]
+                    .find('\n')
+                    .map_or(text.len(), |len| start + len);
+                while chars.next_if(|(idx, _)| *idx < end).is_some() {}
+                continue;
+            }
+            '/' => (TokenKind::Slash, start + 1),
+            '%' => (TokenKind::Percent, start + 1),
+            '<' if text[start..].starts_with("<<") => (TokenKind::Shl, start + 2),
+            '>' if text[start..].starts_with(">>") => (TokenKind::Shr, start + 2),
+            '&' => (TokenKind::Amp, start + 1),
+            '|' => (TokenKind::Pipe, start + 1),
+            '^' => (TokenKind::Caret, start + 1),
+            '~' => (TokenKind::Tilde, start + 1),
+            '(' => (TokenKind::LParen, start + 1),
+            ')' => (TokenKind::RParen, start + 1),
+            c if c.is_whitespace() => continue,
+            '.' if text[start + 1..].starts_with(|c: char| c.is_ascii_alphabetic()) => {
+                let end = run_end(start + 1, is_ident_char);
+                (TokenKind::Directive(text[start + 1..end].to_string()), end)
+            }
+            '\\' if text[start + 1..].starts_with(|c: char| is_ident_char(c)) => {
+                let end = run_end(start + 1, is_ident_char);
+                (TokenKind::MacroParam(text[start + 1..end].to_string()), end)
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let end = run_end(start, is_ident_char);
+                (TokenKind::Ident(text[start..end].to_string()), end)
+            }
+            '\'' => match lex_char(text, start) {
+                Ok(token) => token,
+                Err(end) => {
+                    diagnostics.push(file.error(
+                        &(base + start..base + end),
+                        format!("Invalid character literal {}", &text[start..end]),
+                    ));
+                    while chars.next_if(|(idx, _)| *idx < end).is_some() {}
+                    continue;
+                }
+            },
+            '"' => match lex_string(text, start) {
+                Ok(token) => token,
+                Err(end) => {
+                    diagnostics.push(file.error(
+                        &(base + start..base + end),
+                        format!("Invalid string {}", &text[start..end]),
+                    ));
+                    while chars.next_if(|(idx, _)| *idx < end).is_some() {}
+                    continue;
+                }
+            },
+            c if c.is_ascii_digit() => match lex_number(text, start) {
+                Ok(token) => token,
+                Err(end) => {
+                    diagnostics.push(file.error(
+                        &(base + start..base + end),
+                        format!("Invalid number {}", &text[start..end]),
+                    ));
+                    while chars.next_if(|(idx, _)| *idx < end).is_some() {}
+                    continue;
+                }
+            },
+            c => {
+                diagnostics.push(file.error(
+                    &(base + start..base + start + c.len_utf8()),
+                    format!("Unexpected character '{c}'"),
+                ));
+                continue;
+            }
+        };
+        while chars.next_if(|(idx, _)| *idx < end).is_some() {}
+        tokens.push(Token {
+            kind,
+            span: base + start..base + end,
+        });
+    }
+
+    let end = base + text.len();
+    tokens.push(Token {
+        kind: TokenKind::Eof,
+        span: end..end,
+    });
+    tokens
+}
+
+/// Lexes the number starting at byte `start`, returning the token and the
+/// offset just past it, or just the offset if it isn't a valid number
+fn lex_number(text: &str, start: usize) -> Result<(TokenKind, usize), usize> {
+    let bytes = text.as_bytes();
+    let mut end = start;
+    let mut is_float = false;
+    while end < bytes.len() {
+        match bytes[end] {
+            b'.' if !is_float && bytes.get(end + 1).is_some_and(u8::is_ascii_digit) => {
+                is_float = true;
+            }
+            // signed exponents
+            b'+' | b'-'
+                if matches!(bytes[end - 1], b'e' | b'E')
+                    && bytes[start..end - 1]
+                        .iter()
+                        .all(|b| b.is_ascii_digit() || *b == b'.') => {}
+            b if is_ident_char(char::from(b)) => {}
+            _ => break,
+        }
+        end += 1;
+    }
+
+    let literal = &text[start..end];
+    let radix_digits = [("0x", 16), ("0X", 16), ("0b", 2), ("0B", 2)]
+        .into_iter()
+        .find_map(|(prefix, radix)| Some((literal.strip_prefix(prefix)?, radix)));
+    if let Some((digits, radix)) = radix_digits {
+        u64::from_str_radix(digits, radix)
+            .map(|val| (TokenKind::Int(val), end))
+            .map_err(|_| end)
+    } else if literal.bytes().all(|b| b.is_ascii_digit()) {
+        literal
+            .parse()
+            .map(|val| (TokenKind::Int(val), end))
+            .map_err(|_| end)
+    } else if is_float || literal.contains(['e', 'E']) {
+        literal
+            .parse()
+            .map(|val| (TokenKind::Float(val), end))
+            .map_err(|_| end)
+    } else {
+        Err(end)
+    }
+}
+
+/// Returns the character an escape sequence stands for, given the
+/// character following its `\`
+fn escape(c: char) -> Option<char> {
+    match c {
+        'n' => Some('\n'),
+        't' => Some('\t'),
+        'r' => Some('\r'),
+        '0' => Some('\0'),
+        '\\' | '\'' | '"' => Some(c),
+        _ => None,
+    }
+}
+
+/// Lexes the character literal starting at byte `start`, e.g. `'A'` or
+/// `'\n'`, as its code point. Returns the token and the offset just past it,
+/// or just the offset if it isn't a valid literal
+fn lex_char(text: &str, start: usize) -> Result<(TokenKind, usize), usize> {
+    let rest = &text[start + 1..];
+    let line = &rest[..rest.find('\n').unwrap_or(rest.len())];
+    let mut chars = line.char_indices();
+    let val = match chars.next() {
+        Some((_, '\\')) => chars.next().and_then(|(_, c)| escape(c)),
+        Some((_, '\'')) | None => None,
+        Some((_, c)) => Some(c),
+    };
+
+    if let (Some(val), Some((len, '\''))) = (val, chars.next()) {
+        return Ok((TokenKind::Int(u32::from(val).into()), start + len + 2));
+    }
+    // report everything up to the closing quote, or the rest of the line if
+    // there isn't one
+    let skip = usize::from(!line.starts_with('\''));
+    Err(line
+        .char_indices()
+        .skip(skip)
+        .find(|(_, c)| *c == '\'')
+        .map_or(start + 1 + line.len(), |(len, _)| start + len + 2))
+}
+
+/// Lexes the string literal starting at byte `start`, e.g. `"lib/math.gf"`,
+/// which must end on the same line. Returns the token and the offset just
+/// past it, or just the offset if it isn't a valid literal
+fn lex_string(text: &str, start: usize) -> Result<(TokenKind, usize), usize> {
+    let rest = &text[start + 1..];
+    let line = &rest[..rest.find('\n').unwrap_or(rest.len())];
+    let mut val = String::new();
+    let mut valid = true;
+    let mut chars = line.char_indices();
+    while let Some((idx, c)) = chars.next() {
+        match c {
+            '"' if valid => return Ok((TokenKind::Str(val), start + idx + 2)),
+            '"' => return Err(start + idx + 2),
+            '\\' => match chars.next().and_then(|(_, c)| escape(c)) {
+                Some(c) => val.push(c),
+                None => valid = false,
+            },
+            c => val.push(c),
+        }
+    }
+    // unterminated, so report the rest of the line
+    Err(start + 1 + line.len())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::lexer::{tokenize, TokenKind};
+    use crate::source::{SourceFile, MAIN_FILE};
+
+    fn kinds(text: &str) -> (Vec<TokenKind>, usize) {
+        let mut diagnostics = vec![];
+        let tokens = tokenize(
+            &SourceFile::new("test.gf", text),
+            MAIN_FILE,
+            &mut diagnostics,
+        );
+        (
+            tokens.into_iter().map(|token| token.kind).collect(),
+            diagnostics.len(),
+        )
+    }
+
+    #[test]
+    fn tokenizes_lines() {
+        let (tokens, errors) = kinds("loop: ldi32 r1, 12 // count\n.WORD -3, 1.5e-2\n");
+        assert_eq!(errors, 0);
+        assert_eq!(
+            tokens,
+            vec![
+                TokenKind::Ident("loop".to_string()),
+                TokenKind::Colon,
+                TokenKind::Ident("ldi32".to_string()),
+                TokenKind::Ident("r1".to_string()),
+                TokenKind::Comma,
+                TokenKind::Int(12),
+                TokenKind::Newline,
+                TokenKind::Directive("WORD".to_string()),
+                TokenKind::Minus,
+                TokenKind::Int(3),
+                TokenKind::Comma,
+                TokenKind::Float(0.015),
+                TokenKind::Newline,
+                TokenKind::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenizes_number_bases() {
+        let (tokens, errors) = kinds(r"0x980 0b101 'A' '\n' 0x 'AB' 0b12");
+        assert_eq!(errors, 3);
+        assert_eq!(
+            tokens,
+            vec![
+                TokenKind::Int(0x980),
+                TokenKind::Int(0b101),
+                TokenKind::Int(65),
+                TokenKind::Int(10),
+                TokenKind::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenizes_strings() {
+        let (tokens, errors) = kinds(r#".include "lib\"s.gf" "bad\q" "open"#);
+        assert_eq!(errors, 2);
+        assert_eq!(
+            tokens,
+            vec![
+                TokenKind::Directive("include".to_string()),
+                TokenKind::Str("lib\"s.gf".to_string()),
+                TokenKind::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenizes_operators() {
+        let (tokens, errors) = kinds("(BASE+4*32)<<1 // not/a/comment");
+        assert_eq!(errors, 0);
+        assert_eq!(
+            tokens,
+            vec![
+                TokenKind::LParen,
+                TokenKind::Ident("BASE".to_string()),
+                TokenKind::Plus,
+                TokenKind::Int(4),
+                TokenKind::Star,
+                TokenKind::Int(32),
+                TokenKind::RParen,
+                TokenKind::Shl,
+                TokenKind::Int(1),
+                TokenKind::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_bad_characters() {
+        let (tokens, errors) = kinds("HALT $ 12abc");
+        assert_eq!(errors, 2);
+        assert_eq!(
+            tokens,
+            vec![TokenKind::Ident("HALT".to_string()), TokenKind::Eof]
+        );
+    }
+}
@@ -0,0 +1,1952 @@
+#![warn(clippy::all, clippy::pedantic)]
+
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::Range;
+use std::path::PathBuf;
+
+use giggle_flop::debug::debug_info::{DebugInfo, LineMapping, SourceText};
+use giggle_flop::instruction::instruction::{
+    sign_extends_immediate, Instruction, IMMEDIATE_FIELD_WIDTH, INSTR_LAYOUTS,
+};
+
+use giggle_flop::register::register_system::{ALL_INSTR_TYPES, FLOAT_REG_COUNT, GEN_REG_COUNT};
+use giggle_flop::system::image::{ProgramImage, Segment};
+
+mod ast;
+pub mod diagnostics;
+pub mod disassembler;
+mod expr;
+mod include;
+mod lexer;
+pub mod link;
+mod listing;
+mod macros;
+pub mod object;
+mod output;
+mod parser;
+mod pseudo;
+mod source;
+
+use crate::ast::{
+    Expr, ExprKind, Ident, Operand, OperandKind, RegisterGroup, Statement, StatementKind,
+};
+use crate::expr::Value;
+use crate::listing::Emitted;
+use crate::object::{ObjectSymbol, Relocation, RelocationKind, Section, Target};
+use crate::pseudo::Pseudo;
+use crate::source::{SourceFile, MAIN_FILE};
+
+pub use diagnostics::{Diagnostic, Diagnostics, Severity, Span};
+pub use disassembler::disassemble;
+pub use link::{link, LinkMap};
+pub use listing::{Listing, ListingLine};
+pub use object::Object;
+pub use output::OutputFormat;
+
+/// Bit address within the assembled image
+pub type Address = u32;
+
+// Immediates may be written either unsigned or signed, the latter stored as
+// two's complement within the field
+const MAX_IMMEDIATE_VAL: i64 = (1 << IMMEDIATE_FIELD_WIDTH) - 1;
+const MIN_SIGNED_IMMEDIATE_VAL: i64 = -(1 << (IMMEDIATE_FIELD_WIDTH - 1));
+const MAX_SIGNED_IMMEDIATE_VAL: i64 = (1 << (IMMEDIATE_FIELD_WIDTH - 1)) - 1;
+
+const INSTR_WIDTH_BITS: Address = 32;
+const INSTR_START_ADDR: Address = 0;
+// Name diagnostics refer to when assembling source that didn't come from a file
+const DEFAULT_FILE_NAME: &str = "<source>";
+
+// Everything following this directive is placed directly after the program's
+// instructions in the output image
+const DATA_SECTION_DIRECTIVE: &str = "DATA";
+// Defines a named constant usable anywhere an integer is expected
+const CONSTANT_DIRECTIVE: &str = "EQU";
+// Lets objects linked with this one use the named labels and constants
+const GLOBAL_DIRECTIVE: &str = "GLOBAL";
+// Declares symbols defined by another object, to be filled in by the linker
+const EXTERN_DIRECTIVE: &str = "EXTERN";
+// Memory is only word addressable, so every initializer occupies a full word
+// regardless of the directive's width
+const DATA_WORD_WIDTH_BITS: Address = 32;
+
+/// Initializer directives allowed within the `.DATA` section
+#[derive(Debug, Clone, Eq, PartialEq, Copy)]
+enum DataDirective {
+    Word,
+    Half,
+    Byte,
+    Float,
+}
+
+impl DataDirective {
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_uppercase().as_str() {
+            "WORD" => Some(Self::Word),
+            "HALF" => Some(Self::Half),
+            "BYTE" => Some(Self::Byte),
+            "FLOAT" => Some(Self::Float),
+            _ => None,
+        }
+    }
+
+    /// Returns the inclusive range of integer values accepted by the directive
+    fn int_range(self) -> Option<(i64, i64)> {
+        match self {
+            Self::Word => Some((i64::from(i32::MIN), i64::from(u32::MAX))),
+            Self::Half => Some((i64::from(i16::MIN), i64::from(u16::MAX))),
+            Self::Byte => Some((i64::from(i8::MIN), i64::from(u8::MAX))),
+            Self::Float => None,
+        }
+    }
+}
+
+/// What to fill into a word once the program is linked, when its value
+/// depends on an address that's only known then
+#[derive(Debug, Clone)]
+struct Fixup {
+    kind: RelocationKind,
+    target: Target,
+    addend: i64,
+}
+
+/// Splits `value` into the part known now and, if it depends on an address
+/// that isn't, what the linker should fill in. The part known now is left 0
+/// in that case
+fn split_value(value: Value, kind: RelocationKind) -> (i64, Option<Fixup>) {
+    match value.base {
+        None => (value.offset, None),
+        Some(target) => (
+            0,
+            Some(Fixup {
+                kind,
+                target,
+                addend: value.offset,
+            }),
+        ),
+    }
+}
+
+/// The instructions and initialized data words making up an assembled program
+#[derive(Debug, Default)]
+struct ProgramContents {
+    instructions: Vec<Instruction>,
+    data: Vec<u32>,
+    relocations: Vec<Relocation>, // words the linker still has to fill in
+    exports: Vec<String>,         // symbols named by `.GLOBAL`
+    emitted: Vec<Emitted>,        // what each statement added, for the listing
+}
+
+impl ProgramContents {
+    fn relocate(&mut self, section: Section, index: usize, fixup: Option<Fixup>) {
+        if let Some(Fixup {
+            kind,
+            target,
+            addend,
+        }) = fixup
+        {
+            self.relocations.push(Relocation {
+                section,
+                index,
+                kind,
+                target,
+                addend,
+            });
+        }
+    }
+
+    fn push_instruction(&mut self, (instr, fixup): (Instruction, Option<Fixup>)) {
+        self.relocate(Section::Text, self.instructions.len(), fixup);
+        self.instructions.push(instr);
+    }
+
+    fn push_data(&mut self, (word, fixup): (u32, Option<Fixup>)) {
+        self.relocate(Section::Data, self.data.len(), fixup);
+        self.data.push(word);
+    }
+
+    /// Notes the words `statement` added to `section` from index `start`
+    /// onward, along with the label it defines, for the listing
+    fn record(&mut self, statement: &Statement, section: Section, start: usize) {
+        let words = match section {
+            Section::Text => self.instructions[start..]
+                .iter()
+                .map(Instruction::encode)
+                .collect(),
+            Section::Data => self.data[start..].to_vec(),
+        };
+        let label = match &statement.kind {
+            StatementKind::Label(label) => Some(label.name.clone()),
+            _ => None,
+        };
+        if !words.is_empty() || label.is_some() {
+            self.emitted.push(Emitted {
+                offset: statement.span.start,
+                section,
+                index: start,
+                words,
+                label,
+            });
+        }
+    }
+}
+
+/// An assembled program, ready to be loaded into the VM at address 0
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Program {
+    pub instructions: Vec<Instruction>,
+    pub data: Vec<u32>, // initialized words placed right after the instructions
+    pub bytes: Vec<u8>, // big-endian image of the instructions followed by the data
+    pub symbols: BTreeMap<String, Address>, // label -> bit address
+    pub warnings: Diagnostics,
+    pub listing: Listing, // source lines alongside what they assembled to
+}
+
+impl Program {
+    /// Returns the bit address at which the `.DATA` section starts
+    #[must_use]
+    pub fn data_start(&self) -> Address {
+        Address::try_from(self.instructions.len()).unwrap_or(Address::MAX) * INSTR_WIDTH_BITS
+    }
+
+    /// Returns the program as the loader sees it, with the instructions and
+    /// data in segments of their own and execution starting at the first
+    /// instruction
+    #[must_use]
+    pub fn image(&self) -> ProgramImage {
+        let mut segments = vec![Segment {
+            address: INSTR_START_ADDR,
+            executable: true,
+            words: self.instructions.iter().map(Instruction::encode).collect(),
+        }];
+        if !self.data.is_empty() {
+            segments.push(Segment {
+                address: self.data_start(),
+                executable: false,
+                words: self.data.clone(),
+            });
+        }
+        ProgramImage {
+            entry: Some(INSTR_START_ADDR),
+            segments,
+            symbols: self.symbols.clone(),
+        }
+    }
+
+    /// Returns the debug info a debugger needs to show the program's source,
+    /// which maps each instruction back to the line it was assembled from
+    #[must_use]
+    pub fn debug_info(&self) -> DebugInfo {
+        let mut debug_info = DebugInfo {
+            symbols: self.symbols.clone(),
+            ..DebugInfo::default()
+        };
+        for line in &self.listing.lines {
+            let file = debug_info
+                .files
+                .iter()
+                .position(|file| file.name == line.file)
+                .unwrap_or_else(|| {
+                    debug_info.files.push(SourceText {
+                        name: line.file.clone(),
+                        lines: vec![],
+                    });
+                    debug_info.files.len() - 1
+                });
+            // a file included more than once is listed once for each time
+            let lines = &mut debug_info.files[file].lines;
+            if lines.len() < line.line {
+                lines.resize(line.line, String::new());
+                lines[line.line - 1].clone_from(&line.text);
+            }
+
+            let Some(address) = line.address.filter(|address| *address < self.data_start()) else {
+                continue;
+            };
+            if line.words.is_empty() {
+                continue;
+            }
+            let width = Address::try_from(line.words.len()).unwrap_or(Address::MAX);
+            debug_info.lines.push(LineMapping {
+                address,
+                width: width.saturating_mul(INSTR_WIDTH_BITS),
+                file,
+                line: line.line,
+            });
+        }
+        debug_info.lines.sort_by_key(|mapping| mapping.address);
+        debug_info
+    }
+}
+
+/// Settings that change how source is assembled
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub verbose: bool,              // trace each step of assembly to stdout
+    pub file_name: String, // file diagnostics are reported against, and includes are relative to
+    pub include_dirs: Vec<PathBuf>, // searched for `.INCLUDE`d files not next to the includer
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            verbose: false,
+            file_name: DEFAULT_FILE_NAME.to_string(),
+            include_dirs: vec![],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SymbolKind {
+    Label,    // bit address of the following instruction or data
+    Constant, // defined by `.equ`
+    Extern,   // declared by `.extern`, and defined by another object
+}
+
+impl SymbolKind {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Label => "label",
+            Self::Constant => "constant",
+            Self::Extern => "external symbol",
+        }
+    }
+}
+
+/// A label, constant, or external symbol and the value it stands for
+struct Symbol {
+    kind: SymbolKind,
+    value: Value,
+    span: Range<usize>,
+    used: Cell<bool>,
+    in_data: bool, // a label within the `.DATA` section
+    failed: bool,  // a constant whose definition couldn't be evaluated
+}
+
+type SymbolTable = HashMap<String, Symbol>;
+
+/// Looks up the value of `name`, noting that it's been used
+fn resolve(symbols: &SymbolTable, name: &str) -> Option<Value> {
+    symbols.get(name).map(|symbol| {
+        symbol.used.set(true);
+        symbol.value.clone()
+    })
+}
+
+fn evaluate(file: &SourceFile, expr: &Expr, symbols: &SymbolTable) -> Result<Value, Diagnostic> {
+    expr::evaluate(file, expr, &|name| resolve(symbols, name))
+}
+
+/// Like [`evaluate`], for values that have to be known before linking
+fn evaluate_absolute(
+    file: &SourceFile,
+    expr: &Expr,
+    symbols: &SymbolTable,
+) -> Result<i64, Diagnostic> {
+    match evaluate(file, expr, symbols)? {
+        Value { offset, base: None } => Ok(offset),
+        Value { base: Some(_), .. } => Err(file.error(
+            &expr.span,
+            "Value depends on an address that isn't known until link time".to_string(),
+        )),
+    }
+}
+
+fn is_data_section(name: &Ident) -> bool {
+    name.name.eq_ignore_ascii_case(DATA_SECTION_DIRECTIVE)
+}
+
+fn is_constant_definition(name: &Ident) -> bool {
+    name.name.eq_ignore_ascii_case(CONSTANT_DIRECTIVE)
+}
+
+fn is_export(name: &Ident) -> bool {
+    name.name.eq_ignore_ascii_case(GLOBAL_DIRECTIVE)
+}
+
+fn is_import(name: &Ident) -> bool {
+    name.name.eq_ignore_ascii_case(EXTERN_DIRECTIVE)
+}
+
+/// Statements using the long form of `LI`, by index
+type LongLoads = HashSet<usize>;
+
+/// Returns the number of bits a statement will occupy in the output image
+fn get_statement_width(statement: &Statement, long: bool) -> Address {
+    match &statement.kind {
+        StatementKind::Label(_) => 0,
+        StatementKind::Directive { name, .. }
+            if is_data_section(name)
+                || is_constant_definition(name)
+                || is_export(name)
+                || is_import(name) =>
+        {
+            0
+        }
+        StatementKind::Directive { operands, .. } => {
+            Address::try_from(operands.len()).unwrap_or(Address::MAX) * DATA_WORD_WIDTH_BITS
+        }
+        StatementKind::Instruction { mnemonic, operands } => {
+            let count = Pseudo::from_name(&mnemonic.name)
+                .map_or(1, |pseudo| pseudo.instruction_count(operands, long));
+            Address::try_from(count).unwrap_or(Address::MAX) * INSTR_WIDTH_BITS
+        }
+    }
+}
+
+/// Adds `name` to the symbol table unless it clashes with a register or an
+/// existing symbol, returning the new entry
+fn define_symbol<'a>(
+    file: &SourceFile,
+    symbols: &'a mut SymbolTable,
+    name: &Ident,
+    kind: SymbolKind,
+    value: Value,
+    opts: &Options,
+) -> Result<&'a mut Symbol, Diagnostic> {
+    if parser::is_register_name(&name.name) {
+        return Err(file.error(
+            &name.span,
+            format!(
+                "{} can't be a {} since it would be read as a register",
+                name.name,
+                kind.name()
+            ),
+        ));
+    }
+    if let Some(prev) = symbols.get(&name.name) {
+        return Err(file.error(
+            &name.span,
+            format!(
+                "Multiple definitions of {} {}. Previous definition: 0x{:08X} on line {}",
+                kind.name(),
+                name.name,
+                prev.value.offset,
+                file.span(&prev.span).line
+            ),
+        ));
+    }
+
+    if opts.verbose {
+        println!(
+            "Adding {}->0x{:08X} to symbol table as a {}",
+            name.name,
+            value.offset,
+            kind.name()
+        );
+    }
+    Ok(symbols.entry(name.name.clone()).or_insert(Symbol {
+        kind,
+        value,
+        span: name.span.clone(),
+        used: Cell::new(false),
+        in_data: false,
+        failed: false,
+    }))
+}
+
+/// Returns whether `statement` refers to a constant whose definition failed,
+/// in which case any error it has would only follow on from that one
+fn uses_failed_constant(statement: &Statement, symbols: &SymbolTable) -> bool {
+    let (StatementKind::Directive { operands, .. } | StatementKind::Instruction { operands, .. }) =
+        &statement.kind
+    else {
+        return false;
+    };
+    operands
+        .iter()
+        .filter_map(|operand| match &operand.kind {
+            OperandKind::Expr(expr) => Some(expr),
+            _ => None,
+        })
+        .flat_map(expr::symbols)
+        .any(|(name, _)| symbols.get(name).is_some_and(|symbol| symbol.failed))
+}
+
+/// Returns the name a `.equ` directive defines, if it has one
+fn get_constant_name(operands: &[Operand]) -> Option<&str> {
+    match &operands.first()?.kind {
+        OperandKind::Expr(Expr {
+            kind: ExprKind::Symbol(name),
+            ..
+        }) => Some(name),
+        _ => None,
+    }
+}
+
+/// Evaluates a `.equ NAME, expr` definition. `constants` holds the name of
+/// every constant in the program, to tell one used before its definition
+/// apart from one that's never defined
+fn define_constant(
+    file: &SourceFile,
+    symbols: &mut SymbolTable,
+    statement: &Statement,
+    operands: &[Operand],
+    constants: &HashSet<&str>,
+    opts: &Options,
+) -> Result<(), Diagnostic> {
+    let [name, value] = operands else {
+        return Err(file.error(
+            &statement.span,
+            format!(
+                ".{CONSTANT_DIRECTIVE} takes a name and a value but {} operand(s) were given. \
+                 Usage: .{CONSTANT_DIRECTIVE} NAME, <expression>",
+                operands.len()
+            ),
+        ));
+    };
+    let OperandKind::Expr(Expr {
+        kind: ExprKind::Symbol(ref constant),
+        ..
+    }) = name.kind
+    else {
+        return Err(file.error(
+            &name.span,
+            format!("Expected a constant name, found {}", name.kind),
+        ));
+    };
+    let OperandKind::Expr(ref expr) = value.kind else {
+        return Err(file.error(
+            &value.span,
+            format!("Expected an integer expression, found {}", value.kind),
+        ));
+    };
+
+    let later = expr::symbols(expr)
+        .into_iter()
+        .find(|(symbol, _)| !symbols.contains_key(*symbol) && constants.contains(symbol));
+    let value = if uses_failed_constant(statement, symbols) {
+        // already reported
+        Ok(None)
+    } else if let Some((later, span)) = later {
+        Err(file.error(
+            span,
+            format!(
+                "Constant {} is used before its .{}",
+                macros::source_name(later),
+                CONSTANT_DIRECTIVE.to_ascii_lowercase()
+            ),
+        ))
+    } else {
+        evaluate(file, expr, symbols).map(Some)
+    };
+
+    let name = Ident {
+        name: constant.clone(),
+        span: name.span.clone(),
+    };
+    match value {
+        Ok(Some(value)) => {
+            define_symbol(file, symbols, &name, SymbolKind::Constant, value, opts)?;
+            Ok(())
+        }
+        failed => {
+            // so uses of the constant aren't reported as well
+            symbols.entry(name.name).or_insert(Symbol {
+                kind: SymbolKind::Constant,
+                value: Value::from(0),
+                span: name.span,
+                used: Cell::new(false),
+                in_data: false,
+                failed: true,
+            });
+            failed.map(|_| ())
+        }
+    }
+}
+
+/// Declares each symbol named by an `.extern` directive
+fn define_imports(
+    file: &SourceFile,
+    symbols: &mut SymbolTable,
+    operands: &[Operand],
+    opts: &Options,
+) -> Result<(), Diagnostic> {
+    for operand in operands {
+        let OperandKind::Expr(Expr {
+            kind: ExprKind::Symbol(ref name),
+            ..
+        }) = operand.kind
+        else {
+            return Err(file.error(
+                &operand.span,
+                format!("Expected a symbol name, found {}", operand.kind),
+            ));
+        };
+        let name = Ident {
+            name: name.clone(),
+            span: operand.span.clone(),
+        };
+        let value = Value {
+            offset: 0,
+            base: Some(Target::Symbol(name.name.clone())),
+        };
+        define_symbol(file, symbols, &name, SymbolKind::Extern, value, opts)?;
+    }
+    Ok(())
+}
+
+/// Returns the value of a label at `addr`. When assembling an object, where
+/// its sections end up isn't known, so labels are offsets from the start of
+/// theirs
+fn get_label_value(addr: Address, data_start: Option<Address>, relocatable: bool) -> Value {
+    match data_start {
+        _ if !relocatable => Value::from(i64::from(addr)),
+        None => Value {
+            offset: i64::from(addr),
+            base: Some(Target::Section(Section::Text)),
+        },
+        Some(data_start) => Value {
+            offset: i64::from(addr - data_start),
+            base: Some(Target::Section(Section::Data)),
+        },
+    }
+}
+
+/// Builds the symbol table. Every label and external symbol is known before
+/// any constant is evaluated, so constants may refer to any of them but only
+/// to constants defined above them
+fn get_symbols(
+    file: &SourceFile,
+    statements: &[Statement],
+    long_loads: &LongLoads,
+    relocatable: bool,
+    diagnostics: &mut Vec<Diagnostic>,
+    opts: &Options,
+) -> SymbolTable {
+    let mut curr_addr = INSTR_START_ADDR;
+    let mut data_start = None;
+    let mut symbols = SymbolTable::new();
+    for (idx, statement) in statements.iter().enumerate() {
+        if let StatementKind::Directive { name, .. } = &statement.kind {
+            if is_data_section(name) {
+                data_start.get_or_insert(curr_addr);
+            }
+        }
+        let StatementKind::Label(label) = &statement.kind else {
+            curr_addr += get_statement_width(statement, long_loads.contains(&idx));
+            continue;
+        };
+        let value = get_label_value(curr_addr, data_start, relocatable);
+        match define_symbol(file, &mut symbols, label, SymbolKind::Label, value, opts) {
+            Ok(symbol) => symbol.in_data = data_start.is_some(),
+            Err(diagnostic) => diagnostics.push(diagnostic),
+        }
+    }
+
+    for statement in statements {
+        if let StatementKind::Directive { name, operands } = &statement.kind {
+            if is_import(name) {
+                if let Err(diagnostic) = define_imports(file, &mut symbols, operands, opts) {
+                    diagnostics.push(diagnostic);
+                }
+            }
+        }
+    }
+
+    let constants: HashSet<&str> = statements
+        .iter()
+        .filter_map(|statement| match &statement.kind {
+            StatementKind::Directive { name, operands } if is_constant_definition(name) => {
+                get_constant_name(operands)
+            }
+            _ => None,
+        })
+        .collect();
+    for statement in statements {
+        if let StatementKind::Directive { name, operands } = &statement.kind {
+            if is_constant_definition(name) {
+                if let Err(diagnostic) =
+                    define_constant(file, &mut symbols, statement, operands, &constants, opts)
+                {
+                    diagnostics.push(diagnostic);
+                }
+            }
+        }
+    }
+
+    symbols
+}
+
+/// Returns the operands of `statement` if it's an `LI` pseudo-instruction
+fn get_load_operands(statement: &Statement) -> Option<&[Operand]> {
+    match &statement.kind {
+        StatementKind::Instruction { mnemonic, operands }
+            if Pseudo::from_name(&mnemonic.name) == Some(Pseudo::Li) =>
+        {
+            Some(operands)
+        }
+        _ => None,
+    }
+}
+
+/// Lays out the program and builds its symbol table. `LI` takes more
+/// instructions for values wider than an immediate, which can move the labels
+/// its value depends on, so loads switch to the long form until the layout
+/// stops changing. Loads of values only known once linked always take the
+/// long form
+fn get_layout(
+    file: &SourceFile,
+    statements: &[Statement],
+    relocatable: bool,
+    diagnostics: &mut Vec<Diagnostic>,
+    opts: &Options,
+) -> (SymbolTable, LongLoads) {
+    let mut long_loads = LongLoads::new();
+    loop {
+        let mut symbol_diagnostics = vec![];
+        let symbols = get_symbols(
+            file,
+            statements,
+            &long_loads,
+            relocatable,
+            &mut symbol_diagnostics,
+            opts,
+        );
+
+        let mut grown = false;
+        for (idx, statement) in statements.iter().enumerate() {
+            let Some([_, value, ..]) = get_load_operands(statement) else {
+                continue;
+            };
+            // bad values are reported when the load is assembled
+            let needs_long_form = parse_load_value(file, value, &symbols)
+                .is_ok_and(|(value, fixup)| fixup.is_some() || pseudo::li_needs_long_form(value));
+            if needs_long_form && long_loads.insert(idx) {
+                grown = true;
+            }
+        }
+
+        if !grown {
+            diagnostics.append(&mut symbol_diagnostics);
+            return (symbols, long_loads);
+        }
+    }
+}
+
+/// Returns the type and opcode of the instruction named `mnemonic`
+fn find_instruction(mnemonic: &str) -> Option<(usize, u32)> {
+    ALL_INSTR_TYPES
+        .iter()
+        .enumerate()
+        .find_map(|(instr_type, names)| {
+            let opcode = names
+                .iter()
+                .position(|name| name.eq_ignore_ascii_case(mnemonic))?;
+            Some((instr_type, u32::try_from(opcode).ok()?))
+        })
+}
+
+fn get_reg_group(instr_type: usize) -> RegisterGroup {
+    if matches!(instr_type, 3 | 6) {
+        RegisterGroup::FloatingPoint
+    } else {
+        RegisterGroup::General
+    }
+}
+
+/// Describes the operands an instruction of `instr_type` takes, e.g.
+/// `ADDIM R<n>, <immediate>`
+fn get_usage(name: &str, instr_type: usize) -> String {
+    let layout = INSTR_LAYOUTS[instr_type];
+    let reg = match get_reg_group(instr_type) {
+        RegisterGroup::General => "R<n>",
+        RegisterGroup::FloatingPoint => "F<n>",
+    };
+    let operands: Vec<&str> = std::iter::repeat_n(reg, layout.reg_count)
+        .chain(layout.has_immediate.then_some("<immediate>"))
+        .collect();
+    format!("{name} {}", operands.join(", "))
+        .trim_end()
+        .to_string()
+}
+
+fn parse_reg(
+    file: &SourceFile,
+    operand: &Operand,
+    reg_group: RegisterGroup,
+) -> Result<usize, Diagnostic> {
+    let (expected, reg_count) = match reg_group {
+        RegisterGroup::General => ("a general purpose register", GEN_REG_COUNT),
+        RegisterGroup::FloatingPoint => ("a floating point register", FLOAT_REG_COUNT),
+    };
+    match operand.kind {
+        OperandKind::Register { group, index } if group == reg_group => {
+            if index < reg_count {
+                Ok(index)
+            } else {
+                Err(file.error(
+                    &operand.span,
+                    format!("Invalid register number {index}. Valid range is [0-{reg_count})"),
+                ))
+            }
+        }
+        ref kind => Err(file.error(&operand.span, format!("Expected {expected}, found {kind}"))),
+    }
+}
+
+/// Checks `val` fits the immediate of the instruction `opcode` of
+/// `instr_type`, returning its encoding or why it doesn't. `ADDIM` and the PC
+/// relative jumps sign-extend their immediate, so only take signed values,
+/// while the rest also take unsigned addresses
+fn encode_immediate(instr_type: usize, opcode: u32, val: i64) -> Result<u32, String> {
+    if sign_extends_immediate(instr_type, opcode) {
+        if !(MIN_SIGNED_IMMEDIATE_VAL..=MAX_SIGNED_IMMEDIATE_VAL).contains(&val) {
+            let name = ALL_INSTR_TYPES[instr_type][opcode as usize];
+            let instead = if instr_type == 1 {
+                "Use an absolute jump to reach farther targets"
+            } else {
+                "Use LI to load values outside it"
+            };
+            return Err(format!(
+                "Immediate {val} doesn't fit in the sign-extended immediate of {name}. Valid \
+                 range is [{MIN_SIGNED_IMMEDIATE_VAL}, {MAX_SIGNED_IMMEDIATE_VAL}]. {instead}"
+            ));
+        }
+    } else if !(MIN_SIGNED_IMMEDIATE_VAL..=MAX_IMMEDIATE_VAL).contains(&val) {
+        return Err(format!(
+            "Immediate {val} doesn't fit in {IMMEDIATE_FIELD_WIDTH} bits. Valid range is \
+             [{MIN_SIGNED_IMMEDIATE_VAL}, {MAX_SIGNED_IMMEDIATE_VAL}] signed or \
+             [0, {MAX_IMMEDIATE_VAL}] unsigned"
+        ));
+    }
+
+    // masking off the sign extension leaves the two's complement encoding
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    Ok((val & MAX_IMMEDIATE_VAL) as u32)
+}
+
+fn parse_immediate(
+    file: &SourceFile,
+    operand: &Operand,
+    symbols: &SymbolTable,
+    instr_type: usize,
+    opcode: u32,
+) -> Result<(u32, Option<Fixup>), Diagnostic> {
+    let OperandKind::Expr(ref expr) = operand.kind else {
+        return Err(file.error(
+            &operand.span,
+            format!(
+                "Expected an immediate value or label, found {}",
+                operand.kind
+            ),
+        ));
+    };
+
+    let (val, fixup) = split_value(evaluate(file, expr, symbols)?, RelocationKind::Immediate);
+    let immediate =
+        encode_immediate(instr_type, opcode, val).map_err(|msg| file.error(&operand.span, msg))?;
+    Ok((immediate, fixup))
+}
+
+fn parse_instruction(
+    file: &SourceFile,
+    statement: &Statement,
+    mnemonic: &Ident,
+    operands: &[Operand],
+    symbols: &SymbolTable,
+    opts: &Options,
+) -> Result<(Instruction, Option<Fixup>), Diagnostic> {
+    let Some((instr_type, opcode)) = find_instruction(&mnemonic.name) else {
+        return Err(file.error(
+            &mnemonic.span,
+            format!("Unknown instruction {}", mnemonic.name),
+        ));
+    };
+    let name = ALL_INSTR_TYPES[instr_type][opcode as usize];
+    if opts.verbose {
+        println!("Parsed {} as instruction type {instr_type}", mnemonic.name);
+    }
+
+    let layout = INSTR_LAYOUTS[instr_type];
+    let n_operands = layout.reg_count + usize::from(layout.has_immediate);
+    if operands.len() != n_operands {
+        return Err(file.error(
+            &statement.span,
+            format!(
+                "{name} takes {n_operands} operand(s) but {} were given. Usage: {}",
+                operands.len(),
+                get_usage(name, instr_type)
+            ),
+        ));
+    }
+
+    let regs = operands[..layout.reg_count]
+        .iter()
+        .map(|operand| parse_reg(file, operand, get_reg_group(instr_type)))
+        .collect::<Result<Vec<usize>, Diagnostic>>()?;
+    let (immediate, fixup) = if layout.has_immediate {
+        parse_immediate(
+            file,
+            &operands[layout.reg_count],
+            symbols,
+            instr_type,
+            opcode,
+        )?
+    } else {
+        (0, None)
+    };
+
+    let Some(parsed) = Instruction::from_fields(instr_type, opcode, &regs, immediate) else {
+        return Err(file.error(
+            &statement.span,
+            format!("Invalid instruction type: {instr_type}"),
+        ));
+    };
+    if opts.verbose {
+        println!("Parsed {} as {parsed}", file.snippet(&statement.span));
+    }
+    Ok((parsed, fixup))
+}
+
+/// Evaluates the value an `LI` loads, which may be any 32-bit value
+fn parse_load_value(
+    file: &SourceFile,
+    operand: &Operand,
+    symbols: &SymbolTable,
+) -> Result<(u32, Option<Fixup>), Diagnostic> {
+    let OperandKind::Expr(ref expr) = operand.kind else {
+        return Err(file.error(
+            &operand.span,
+            format!(
+                "Expected an immediate value or label, found {}",
+                operand.kind
+            ),
+        ));
+    };
+
+    let (val, fixup) = split_value(evaluate(file, expr, symbols)?, RelocationKind::High);
+    if !(i64::from(i32::MIN)..=i64::from(u32::MAX)).contains(&val) {
+        return Err(file.error(
+            &operand.span,
+            format!(
+                "Value {val} doesn't fit in 32 bits. Valid range is [{}, {}] signed or \
+                 [0, {}] unsigned",
+                i32::MIN,
+                i32::MAX,
+                u32::MAX
+            ),
+        ));
+    }
+
+    // negative values are loaded as their two's complement
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    Ok((val as u32, fixup))
+}
+
+/// Expands a pseudo-instruction and assembles the instructions it stands for
+fn parse_pseudo(
+    file: &SourceFile,
+    statement: &Statement,
+    pseudo: Pseudo,
+    long: bool,
+    symbols: &SymbolTable,
+    opts: &Options,
+) -> Result<Vec<(Instruction, Option<Fixup>)>, Diagnostic> {
+    let StatementKind::Instruction { mnemonic, operands } = &statement.kind else {
+        unreachable!();
+    };
+    pseudo::check_operands(file, statement, pseudo, operands)?;
+    let (li_value, li_fixup) = match pseudo {
+        Pseudo::Li => {
+            let (value, fixup) = parse_load_value(file, &operands[1], symbols)?;
+            (Some(value), fixup)
+        }
+        _ => (None, None),
+    };
+
+    let mut instrs = pseudo::expand(file, statement, pseudo, mnemonic, operands, li_value, long)?
+        .iter()
+        .map(|expanded| {
+            let StatementKind::Instruction { mnemonic, operands } = &expanded.kind else {
+                unreachable!();
+            };
+            parse_instruction(file, expanded, mnemonic, operands, symbols, opts)
+        })
+        .collect::<Result<Vec<_>, Diagnostic>>()?;
+
+    // the long form loads the high part with its second instruction and adds
+    // the low part with its last
+    if let Some(fixup) = li_fixup {
+        let last = instrs.len() - 1;
+        instrs[last].1 = Some(Fixup {
+            kind: RelocationKind::Low,
+            ..fixup.clone()
+        });
+        instrs[1].1 = Some(fixup);
+    }
+    Ok(instrs)
+}
+
+fn parse_data_value(
+    file: &SourceFile,
+    operand: &Operand,
+    directive: DataDirective,
+    symbols: &SymbolTable,
+) -> Result<(u32, Option<Fixup>), Diagnostic> {
+    let Some((min, max)) = directive.int_range() else {
+        // truncation to single precision is intended
+        #[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+        let val = match operand.kind {
+            OperandKind::Float(val) => val as f32,
+            OperandKind::Expr(ref expr) => evaluate_absolute(file, expr, symbols)? as f32,
+            ref kind @ OperandKind::Register { .. } => {
+                return Err(file.error(
+                    &operand.span,
+                    format!("Expected a floating point value, found {kind}"),
+                ))
+            }
+        };
+        // values past the largest single precision one round to infinity
+        if !val.is_finite() {
+            return Err(file.error(
+                &operand.span,
+                format!(
+                    "{directive:?} value {} is out of range. Valid range is [{:e}, {:e}]",
+                    file.snippet(&operand.span),
+                    f32::MIN,
+                    f32::MAX
+                ),
+            ));
+        }
+        return Ok((val.to_bits(), None));
+    };
+
+    let OperandKind::Expr(ref expr) = operand.kind else {
+        return Err(file.error(
+            &operand.span,
+            format!("Expected a {directive:?} value, found {}", operand.kind),
+        ));
+    };
+    // only whole words have room for an address
+    let (raw_val, fixup) = if directive == DataDirective::Word {
+        split_value(evaluate(file, expr, symbols)?, RelocationKind::Word)
+    } else {
+        (evaluate_absolute(file, expr, symbols)?, None)
+    };
+
+    if !(min..=max).contains(&raw_val) {
+        return Err(file.error(
+            &operand.span,
+            format!("{directive:?} value {raw_val} is out of range. Valid range is [{min}, {max}]"),
+        ));
+    }
+
+    // Negative values are stored sign-extended to the full word
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    Ok((raw_val as u32, fixup))
+}
+
+fn parse_data_directive(
+    file: &SourceFile,
+    statement: &Statement,
+    name: &Ident,
+    operands: &[Operand],
+    symbols: &SymbolTable,
+    opts: &Options,
+) -> Result<Vec<(u32, Option<Fixup>)>, Diagnostic> {
+    let Some(directive) = DataDirective::from_name(&name.name) else {
+        return Err(file.error(
+            &name.span,
+            format!("Unknown data directive: .{}", name.name),
+        ));
+    };
+    if operands.is_empty() {
+        return Err(file.error(
+            &statement.span,
+            format!("Missing {directive:?} initializer value"),
+        ));
+    }
+
+    let data = operands
+        .iter()
+        .map(|operand| parse_data_value(file, operand, directive, symbols))
+        .collect::<Result<Vec<_>, Diagnostic>>()?;
+    if opts.verbose {
+        let words: Vec<u32> = data.iter().map(|(word, _)| *word).collect();
+        println!(
+            "Parsed {} as {directive:?} data {words:?}",
+            file.snippet(&statement.span)
+        );
+    }
+
+    Ok(data)
+}
+
+/// Checks the symbols named by a `.global` directive can be exported,
+/// returning their names
+fn get_exports(
+    file: &SourceFile,
+    operands: &[Operand],
+    symbols: &SymbolTable,
+) -> Result<Vec<String>, Diagnostic> {
+    let mut exports = vec![];
+    for operand in operands {
+        let OperandKind::Expr(Expr {
+            kind: ExprKind::Symbol(ref name),
+            ..
+        }) = operand.kind
+        else {
+            return Err(file.error(
+                &operand.span,
+                format!("Expected a symbol name, found {}", operand.kind),
+            ));
+        };
+        let Some(symbol) = symbols.get(name) else {
+            return Err(file.error(&operand.span, format!("Undefined symbol {name}")));
+        };
+        if let Some(Target::Symbol(import)) = &symbol.value.base {
+            return Err(file.error(
+                &operand.span,
+                format!("Can't export {name} since it depends on {import} from another file"),
+            ));
+        }
+        symbol.used.set(true);
+        exports.push(name.clone());
+    }
+    Ok(exports)
+}
+
+fn get_program_contents(
+    file: &SourceFile,
+    statements: &[Statement],
+    symbols: &SymbolTable,
+    long_loads: &LongLoads,
+    relocatable: bool,
+    diagnostics: &mut Vec<Diagnostic>,
+    opts: &Options,
+) -> ProgramContents {
+    let mut contents = ProgramContents::default();
+    let mut in_data_section = false;
+
+    for (idx, statement) in statements.iter().enumerate() {
+        let relocation_count = contents.relocations.len();
+        let (section, start) = if in_data_section {
+            (Section::Data, contents.data.len())
+        } else {
+            (Section::Text, contents.instructions.len())
+        };
+        let parsed = match &statement.kind {
+            StatementKind::Label(_) => Ok(()),
+            _ if uses_failed_constant(statement, symbols) => Ok(()),
+            // already evaluated while building the symbol table
+            StatementKind::Directive { name, .. }
+                if is_constant_definition(name) || is_import(name) =>
+            {
+                Ok(())
+            }
+            StatementKind::Directive { name, operands } if is_export(name) => {
+                get_exports(file, operands, symbols)
+                    .map(|mut exports| contents.exports.append(&mut exports))
+            }
+            StatementKind::Directive { name, operands } if is_data_section(name) => {
+                if in_data_section {
+                    Err(file.error(
+                        &statement.span,
+                        format!("Multiple .{DATA_SECTION_DIRECTIVE} sections"),
+                    ))
+                } else if let Some(operand) = operands.first() {
+                    Err(file.error(
+                        &operand.span,
+                        format!(".{DATA_SECTION_DIRECTIVE} doesn't take any operands"),
+                    ))
+                } else {
+                    if opts.verbose {
+                        println!("Starting data section");
+                    }
+                    in_data_section = true;
+                    Ok(())
+                }
+            }
+            StatementKind::Directive { name, .. } if !in_data_section => Err(file.error(
+                &statement.span,
+                format!(
+                    "Data directive outside of the .{DATA_SECTION_DIRECTIVE} section: .{}",
+                    name.name
+                ),
+            )),
+            StatementKind::Directive { name, operands } => {
+                parse_data_directive(file, statement, name, operands, symbols, opts)
+                    .map(|data| data.into_iter().for_each(|word| contents.push_data(word)))
+            }
+            StatementKind::Instruction { mnemonic, .. } if in_data_section => Err(file.error(
+                &statement.span,
+                format!(
+                    "Instruction {} inside the .{DATA_SECTION_DIRECTIVE} section",
+                    mnemonic.name
+                ),
+            )),
+            StatementKind::Instruction { mnemonic, operands } => {
+                match Pseudo::from_name(&mnemonic.name) {
+                    Some(pseudo) => parse_pseudo(
+                        file,
+                        statement,
+                        pseudo,
+                        long_loads.contains(&idx),
+                        symbols,
+                        opts,
+                    )
+                    .map(|instrs| {
+                        for instr in instrs {
+                            contents.push_instruction(instr);
+                        }
+                    }),
+                    None => parse_instruction(file, statement, mnemonic, operands, symbols, opts)
+                        .map(|instr| contents.push_instruction(instr)),
+                }
+            }
+        };
+
+        // keep going so every problem gets reported in one run
+        if let Err(diagnostic) = parsed {
+            diagnostics.push(diagnostic);
+        }
+
+        // a program image has to be complete, so it can't use other files' symbols
+        if !relocatable {
+            if let Some(Relocation {
+                target: Target::Symbol(name),
+                ..
+            }) = contents.relocations.get(relocation_count)
+            {
+                diagnostics.push(file.error(
+                    &statement.span,
+                    format!(
+                        "{name} is defined in another file, so this has to be assembled as an \
+                         object and linked"
+                    ),
+                ));
+            }
+            contents.relocations.truncate(relocation_count);
+        }
+        contents.record(statement, section, start);
+    }
+
+    contents
+}
+
+/// Warns about every label that nothing refers to. Constants tend to be
+/// defined in sets that are only partly used, and labels in the data section
+/// to document its layout, so they're left out. A label in a macro is
+/// reported once, by the name it was written as, rather than once per
+/// expansion
+fn get_unused_label_warnings(file: &SourceFile, symbols: &SymbolTable) -> Vec<Diagnostic> {
+    let mut unused: Vec<(&str, &Symbol)> = symbols
+        .iter()
+        .filter(|(_, symbol)| {
+            symbol.kind == SymbolKind::Label && !symbol.used.get() && !symbol.in_data
+        })
+        .map(|(label, symbol)| (macros::source_name(label), symbol))
+        .collect();
+    // expansions come later in the source than the first one
+    unused.sort_by_key(|(_, symbol)| symbol.span.start);
+
+    let mut reported = HashSet::new();
+    unused
+        .into_iter()
+        .map(|(label, symbol)| {
+            file.diagnostic(
+                Severity::Warning,
+                &symbol.span,
+                format!("Label {label} is never used"),
+            )
+        })
+        .filter(|diagnostic| reported.insert((diagnostic.file.clone(), diagnostic.span)))
+        .collect()
+}
+
+fn get_bytes(contents: &ProgramContents) -> Vec<u8> {
+    contents
+        .instructions
+        .iter()
+        .map(Instruction::encode)
+        .chain(contents.data.iter().copied())
+        .flat_map(u32::to_be_bytes)
+        .collect()
+}
+
+/// Assembles the instructions and data in `source` into a program image
+///
+/// # Errors
+///
+/// Returns every problem found if `source` isn't a valid program
+pub fn assemble(source: &str) -> Result<Program, Diagnostics> {
+    assemble_with(source, &Options::default())
+}
+
+/// A source file assembled as far as it can be before deciding whether it
+/// becomes a program image or an object
+struct Assembled {
+    contents: ProgramContents,
+    symbols: SymbolTable,
+    warnings: Diagnostics,
+    listing: Listing,
+}
+
+/// Assembles `source`. When `relocatable`, labels are left as offsets from
+/// the start of their section, for the linker to fill in
+fn assemble_source(
+    source: &str,
+    opts: &Options,
+    relocatable: bool,
+) -> Result<Assembled, Diagnostics> {
+    let mut file = SourceFile::new(&opts.file_name, source);
+    let mut diagnostics = vec![];
+
+    let tokens = include::expand(&mut file, MAIN_FILE, &opts.include_dirs, &mut diagnostics);
+    let tokens = macros::expand(&mut file, tokens, &mut diagnostics);
+    let statements = parser::parse(&file, tokens, &mut diagnostics);
+    // get symbol to address map
+    let (symbols, long_loads) = get_layout(&file, &statements, relocatable, &mut diagnostics, opts);
+    let mut contents = get_program_contents(
+        &file,
+        &statements,
+        &symbols,
+        &long_loads,
+        relocatable,
+        &mut diagnostics,
+        opts,
+    );
+    let emitted = std::mem::take(&mut contents.emitted);
+    let data_start =
+        Address::try_from(contents.instructions.len()).unwrap_or(Address::MAX) * INSTR_WIDTH_BITS;
+    let listing = listing::build(&file, emitted, data_start);
+    diagnostics.append(&mut get_unused_label_warnings(&file, &symbols));
+    // expansions of a macro share the spans of its body, so are told apart
+    // by where they were expanded
+    diagnostics.sort_by_key(|diagnostic| {
+        (
+            file.order(&diagnostic.file),
+            diagnostic.span,
+            diagnostic.note.as_ref().and_then(|note| note.span),
+        )
+    });
+
+    let diagnostics = Diagnostics::from(diagnostics);
+    if diagnostics.has_errors() {
+        return Err(diagnostics);
+    }
+    Ok(Assembled {
+        contents,
+        symbols,
+        warnings: diagnostics,
+        listing,
+    })
+}
+
+/// Like [`assemble`], with non-default `opts`
+///
+/// # Errors
+///
+/// Returns every problem found if `source` isn't a valid program
+pub fn assemble_with(source: &str, opts: &Options) -> Result<Program, Diagnostics> {
+    let Assembled {
+        contents,
+        symbols,
+        warnings,
+        listing,
+    } = assemble_source(source, opts, false)?;
+
+    let bytes = get_bytes(&contents);
+    Ok(Program {
+        instructions: contents.instructions,
+        data: contents.data,
+        bytes,
+        // labels local to a macro expansion can't be named in source, so
+        // they're left out for the disassembler and debugger
+        symbols: symbols
+            .into_iter()
+            .filter(|(name, symbol)| {
+                symbol.kind == SymbolKind::Label && !macros::is_local_name(name)
+            })
+            .filter_map(|(label, symbol)| {
+                Some((label, Address::try_from(symbol.value.offset).ok()?))
+            })
+            .collect(),
+        warnings,
+        listing,
+    })
+}
+
+/// Assembles `source` into an object to be linked with others by [`link`].
+/// It may use symbols declared with `.EXTERN` that other objects export with
+/// `.GLOBAL`
+///
+/// # Errors
+///
+/// Returns every problem found if `source` isn't a valid program
+pub fn assemble_object(source: &str, opts: &Options) -> Result<Object, Diagnostics> {
+    let Assembled {
+        contents,
+        symbols,
+        warnings,
+        listing,
+    } = assemble_source(source, opts, true)?;
+
+    let mut object = Object::new(&opts.file_name);
+    object.text = contents
+        .instructions
+        .iter()
+        .map(Instruction::encode)
+        .collect();
+    object.data = contents.data;
+    object.relocations = contents.relocations;
+    object.warnings = warnings;
+    object.listing = listing;
+    for (name, symbol) in symbols {
+        let global = contents.exports.contains(&name);
+        match symbol.kind {
+            SymbolKind::Extern => {
+                object.imports.push(name);
+                continue;
+            }
+            // only exported constants are of any use to the linker
+            SymbolKind::Constant if !global => continue,
+            SymbolKind::Label if macros::is_local_name(&name) => continue,
+            SymbolKind::Label | SymbolKind::Constant => {}
+        }
+        let section = match symbol.value.base {
+            None => None,
+            Some(Target::Section(section)) => Some(section),
+            // exports were checked not to depend on other files' symbols
+            Some(Target::Symbol(_)) => continue,
+        };
+        object.symbols.push(ObjectSymbol {
+            name,
+            section,
+            value: symbol.value.offset,
+            global,
+        });
+    }
+    // keep the output the same from one run to the next
+    object.imports.sort();
+    object
+        .symbols
+        .sort_by(|a, b| (a.section, a.value, &a.name).cmp(&(b.section, b.value, &b.name)));
+    Ok(object)
+}
+
+#[cfg(test)]
+mod test {
+    use giggle_flop::instruction::instruction::Instruction;
+
+    use crate::{assemble, Severity, Span};
+
+    #[test]
+    fn assembles_from_memory() {
+        let program = assemble(
+            "// labels resolve to bit addresses
+            LDI32 R1, 3
+            loop:
+            ADDIM R1, 1
+            JNE loop
+            HALT
+            .DATA
+            values:
+            .WORD 1, -1",
+        )
+        .unwrap();
+
+        assert_eq!(program.instructions.len(), 4);
+        assert_eq!(
+            program.instructions[2],
+            Instruction::Type1 {
+                opcode: 2,
+                immediate: 0x20
+            }
+        );
+        assert_eq!(program.data, vec![1, u32::MAX]);
+        assert_eq!(program.bytes.len(), 6 * 4);
+        assert_eq!(program.bytes[16..], [0, 0, 0, 1, 0xFF, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(program.symbols["loop"], 0x20);
+        assert_eq!(program.symbols["values"], 0x80);
+        assert_eq!(program.data_start(), 0x80);
+        // labels naming data are left alone even if nothing refers to them
+        assert!(program.warnings.is_empty());
+    }
+
+    #[test]
+    fn assembles_data_directives() {
+        let program = assemble(
+            "LDI32 R1, table
+            HALT
+            .DATA
+            table: .WORD end, 7
+            .HALF -2, 0xFFFF
+            end: .BYTE 'A', -1
+            .FLOAT 1.5",
+        )
+        .unwrap();
+
+        // labels resolve to the bit address of their data, wherever they're used
+        assert_eq!(program.symbols["table"], 0x40);
+        assert_eq!(program.symbols["end"], 0x40 + 4 * 32);
+        assert_eq!(program.instructions[0].immediate(), Some(0x40));
+        assert_eq!(
+            program.data,
+            vec![
+                0x40 + 4 * 32,
+                7,
+                0xFFFF_FFFE,
+                0xFFFF,
+                65,
+                u32::MAX,
+                1.5f32.to_bits()
+            ]
+        );
+
+        let diagnostics =
+            assemble(".WORD 1\nHALT\n.DATA\n.BYTE 256\n.HALF -32769\n.FLOAT 1e39\n.FLOAT -1e400")
+                .unwrap_err();
+        let messages: Vec<_> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "Data directive outside of the .DATA section: .WORD",
+                "Byte value 256 is out of range. Valid range is [-128, 255]",
+                "Half value -32769 is out of range. Valid range is [-32768, 65535]",
+                "Float value 1e39 is out of range. Valid range is [-3.4028235e38, 3.4028235e38]",
+                "Float value -1e400 is out of range. Valid range is [-3.4028235e38, 3.4028235e38]",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_every_error() {
+        let diagnostics = assemble(
+            "start:
+    JE nowhere // first
+    LDI32 R16, 3
+  .DATA
+    .WORD 1, x, 3
+    FOO R1",
+        )
+        .unwrap_err();
+
+        let found: Vec<_> = diagnostics
+            .iter()
+            .map(|d| (d.severity, d.span.unwrap(), d.message.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (
+                    Severity::Warning,
+                    Span {
+                        line: 1,
+                        column_start: 1,
+                        column_end: 6
+                    },
+                    "Label start is never used"
+                ),
+                (
+                    Severity::Error,
+                    Span {
+                        line: 2,
+                        column_start: 8,
+                        column_end: 15
+                    },
+                    "Undefined symbol nowhere"
+                ),
+                (
+                    Severity::Error,
+                    Span {
+                        line: 3,
+                        column_start: 11,
+                        column_end: 14
+                    },
+                    "Invalid register number 16. Valid range is [0-16)"
+                ),
+                (
+                    Severity::Error,
+                    Span {
+                        line: 5,
+                        column_start: 14,
+                        column_end: 15
+                    },
+                    "Undefined symbol x"
+                ),
+                (
+                    Severity::Error,
+                    Span {
+                        line: 6,
+                        column_start: 5,
+                        column_end: 11
+                    },
+                    "Instruction FOO inside the .DATA section"
+                ),
+            ]
+        );
+        assert_eq!(diagnostics.error_count(), 4);
+    }
+
+    #[test]
+    fn accepts_labels_before_instructions() {
+        let program =
+            assemble("start: ldi32 r1, 3\nloop: addim R1, 1\n  jne loop // again\nhalt").unwrap();
+        assert_eq!(program.instructions.len(), 4);
+        assert_eq!(program.symbols["loop"], 0x20);
+        assert_eq!(
+            program.instructions[2],
+            Instruction::Type1 {
+                opcode: 2,
+                immediate: 0x20
+            }
+        );
+    }
+
+    #[test]
+    fn encodes_immediate_literals() {
+        let program =
+            assemble("LDI32 R1, 0x980\nADDIM R1, -1\nLDI32 R2, 'A'\nLDI32 R3, 0b1010\nHALT")
+                .unwrap();
+        let immediates: Vec<_> = program
+            .instructions
+            .iter()
+            .filter_map(Instruction::immediate)
+            .collect();
+        assert_eq!(immediates, vec![2432, 0x1F_FFFF, 65, 10]);
+
+        let diagnostics = assemble("LDI32 R1, -1048577\nLDI32 R1, 0x200000\nHALT").unwrap_err();
+        assert_eq!(diagnostics.error_count(), 2);
+        assert_eq!(
+            diagnostics.iter().next().unwrap().message,
+            "Immediate -1048577 doesn't fit in 21 bits. Valid range is [-1048576, 1048575] \
+             signed or [0, 2097151] unsigned"
+        );
+    }
+
+    #[test]
+    fn checks_sign_extended_immediates() {
+        let program = assemble("ADDIM R1, -1048576\nIJNE -32\nLD32 R1, 2097024\nHALT").unwrap();
+        let immediates: Vec<_> = program
+            .instructions
+            .iter()
+            .filter_map(Instruction::immediate)
+            .collect();
+        assert_eq!(immediates, vec![0x10_0000, 0x1F_FFE0, 2_097_024]);
+
+        // the console's CHAR_OUT would come out negative
+        let diagnostics = assemble("ADDIM R1, 2097024\nIJE 1048576\nHALT").unwrap_err();
+        let messages: Vec<_> = diagnostics.iter().map(|diag| &diag.message).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "Immediate 2097024 doesn't fit in the sign-extended immediate of ADDIM. Valid \
+                 range is [-1048576, 1048575]. Use LI to load values outside it",
+                "Immediate 1048576 doesn't fit in the sign-extended immediate of IJE. Valid \
+                 range is [-1048576, 1048575]. Use an absolute jump to reach farther targets",
+            ]
+        );
+    }
+
+    #[test]
+    fn evaluates_constant_expressions() {
+        let program = assemble(
+            ".equ WORD, 32
+            .equ COUNT, 3
+            .equ END, values + COUNT * WORD
+            LDI32 R1, values + (COUNT - 1) * WORD
+            LDI32 R2, END >> 5 | 1 << 8
+            LDI32 R3, -WORD & 0xFFFF
+            HALT
+            .DATA
+            values:
+            .WORD COUNT, END, WORD * WORD",
+        )
+        .unwrap();
+
+        let immediates: Vec<_> = program
+            .instructions
+            .iter()
+            .filter_map(Instruction::immediate)
+            .collect();
+        assert_eq!(
+            immediates,
+            vec![0x80 + 2 * 32, (0xE0 >> 5) | 1 << 8, 0xFFE0]
+        );
+        assert_eq!(program.data, vec![3, 0xE0, 1024]);
+        // constants aren't labels
+        assert_eq!(program.symbols.len(), 1);
+
+        let diagnostics = assemble(
+            ".equ A, 3
+            .equ C, B + 1
+            .equ B, 1
+            .equ A, 2
+            LDI32 R1, 1 / (B - 1)
+            .equ X, 1 / 0
+            .equ Y, X + C
+            LDI32 R2, C
+            LDI32 R3, Y << 2
+            HALT
+            .DATA
+            .WORD X, D",
+        )
+        .unwrap_err();
+        let messages: Vec<_> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+        // uses of C, X, and Y only follow on from their definitions failing
+        assert_eq!(
+            messages,
+            vec![
+                "Constant B is used before its .equ",
+                "Multiple definitions of constant A. Previous definition: 0x00000003 on line 1",
+                "Division by zero",
+                "Division by zero"
+            ]
+        );
+        let diagnostics = assemble("LDI32 R1, C\nHALT\n.DATA\n.WORD D").unwrap_err();
+        let messages: Vec<_> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(messages, vec!["Undefined symbol C", "Undefined symbol D"]);
+    }
+
+    #[test]
+    fn expands_pseudo_instructions() {
+        let program = assemble(
+            "LI R1, far
+            MOV R2, R1
+            JMP end
+            PUSH R2
+            POP R3
+            end: HALT
+            .DATA
+            .equ far, end + 0x200000",
+        )
+        .unwrap();
+
+        // the long LI moves `end`, which needs the long form to reach
+        assert_eq!(program.symbols["end"], (14 + 1 + 2 + 2 + 4) * 32);
+        let far = program.symbols["end"] + 0x20_0000;
+        let instrs = &program.instructions;
+        assert_eq!(
+            instrs[1],
+            Instruction::Type4 {
+                opcode: 9,
+                reg_1: 1,
+                immediate: far >> 11
+            }
+        );
+        assert_eq!(
+            instrs[12],
+            Instruction::Type5 {
+                opcode: 9,
+                reg_1: 1,
+                reg_2: 1,
+                reg_3: 1
+            }
+        );
+        assert_eq!(
+            instrs[13],
+            Instruction::Type4 {
+                opcode: 9,
+                reg_1: 1,
+                immediate: far & 0x7FF
+            }
+        );
+        assert_eq!(
+            instrs[15..17],
+            [
+                Instruction::Type1 {
+                    opcode: 1,
+                    immediate: program.symbols["end"]
+                },
+                Instruction::Type1 {
+                    opcode: 2,
+                    immediate: program.symbols["end"]
+                }
+            ]
+        );
+
+        let diagnostics = assemble("POP R13\nMOV R1\nCLR F1\nHALT").unwrap_err();
+        let messages: Vec<_> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "Can't POP into the stack pointer R13",
+                "MOV takes 2 operand(s) but 1 were given. Usage: MOV R<dest>, R<src>",
+                "Expected a general purpose register, found register F1"
+            ]
+        );
+    }
+
+    #[test]
+    fn li_short_form_survives_sign_extension() {
+        let program = assemble("LI R1, 0xFFFFF\nLI R2, -1\nLI R3, 0x100000\nHALT").unwrap();
+        let instrs = &program.instructions;
+        assert_eq!(instrs.len(), 2 + 2 + 14 + 1);
+        assert_eq!(
+            instrs[1],
+            Instruction::Type4 {
+                opcode: 9,
+                reg_1: 1,
+                immediate: 0xF_FFFF
+            }
+        );
+        assert_eq!(
+            instrs[3],
+            Instruction::Type4 {
+                opcode: 9,
+                reg_1: 2,
+                immediate: 0x1F_FFFF
+            }
+        );
+        // `ADDIM` would sign-extend it to 0xFFF00000
+        assert_eq!(
+            instrs[5],
+            Instruction::Type4 {
+                opcode: 9,
+                reg_1: 3,
+                immediate: 0x10_0000 >> 11
+            }
+        );
+    }
+
+    #[test]
+    fn li_scratch_register_shortens_the_long_form() {
+        let program = assemble("LI R1, 0x12345678, R2\nLI R3, 1, R4\nHALT").unwrap();
+        let instrs = &program.instructions;
+        assert_eq!(instrs.len(), 6 + 2 + 1);
+        assert_eq!(
+            instrs[3],
+            Instruction::Type4 {
+                opcode: 9,
+                reg_1: 2,
+                immediate: 1 << 11
+            }
+        );
+        assert_eq!(
+            instrs[4],
+            Instruction::Type5 {
+                opcode: 11,
+                reg_1: 1,
+                reg_2: 1,
+                reg_3: 2
+            }
+        );
+        assert_eq!(
+            instrs[5],
+            Instruction::Type4 {
+                opcode: 9,
+                reg_1: 1,
+                immediate: 0x678
+            }
+        );
+
+        let diagnostics = assemble("LI R1, 0x12345678, R1\nLI R1, 2, F1\nHALT").unwrap_err();
+        let messages: Vec<_> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "LI's scratch register has to differ from the register it loads",
+                "Expected a general purpose register to use as scratch, found register F1"
+            ]
+        );
+    }
+
+    #[test]
+    fn expands_macros() {
+        let program = assemble(
+            ".macro INC reg, amount
+                ADDIM \\reg, \\amount
+            .endm
+            .macro WAIT reg
+            loop:
+                INC \\reg, 1
+                JNE loop
+            .endm
+            WAIT R1
+            WAIT R2
+            HALT",
+        )
+        .unwrap();
+
+        // each expansion gets its own copy of `loop`, which isn't exported
+        assert!(program.symbols.is_empty());
+        assert_eq!(
+            program.instructions[1..4],
+            [
+                Instruction::Type1 {
+                    opcode: 2,
+                    immediate: 0
+                },
+                Instruction::Type4 {
+                    opcode: 9,
+                    reg_1: 2,
+                    immediate: 1
+                },
+                Instruction::Type1 {
+                    opcode: 2,
+                    immediate: 2 * 32
+                }
+            ]
+        );
+
+        let diagnostics = assemble(
+            ".macro INC reg
+                ADDIM \\reg, 1
+            .endm
+            INC F1
+            HALT",
+        )
+        .unwrap_err();
+        let error = diagnostics.iter().next().unwrap();
+        assert_eq!(
+            error.message,
+            "Expected a general purpose register, found register F1"
+        );
+        // the operand came from the invocation, so that's where it points
+        assert_eq!(error.span.unwrap().line, 4);
+        let diagnostics = assemble(
+            ".macro BAD
+                ADDIM R1
+            .endm
+            BAD
+            HALT",
+        )
+        .unwrap_err();
+        let error = diagnostics.iter().next().unwrap();
+        assert_eq!(error.span.unwrap().line, 2);
+        let note = error.note.as_ref().unwrap();
+        assert_eq!(note.message, "In this expansion of macro BAD");
+        assert_eq!(note.span.unwrap().line, 4);
+    }
+
+    #[test]
+    fn reports_macro_diagnostics_once_in_order() {
+        let source = ".macro SPIN r
+            again: ADDIM \\r, 1
+            LDI32 \\r, 0x200000
+            .endm
+            SPIN R1
+            SPIN R2
+            SPIN R3
+            HALT";
+        let diagnostics = assemble(source).unwrap_err();
+        let found: Vec<_> = diagnostics
+            .iter()
+            .map(|d| {
+                (
+                    d.message.as_str(),
+                    d.note.as_ref().unwrap().span.unwrap().line,
+                )
+            })
+            .collect();
+        let too_wide = "Immediate 2097152 doesn't fit in 21 bits. Valid range is \
+                        [-1048576, 1048575] signed or [0, 2097151] unsigned";
+        assert_eq!(
+            found,
+            vec![
+                ("Label again is never used", 5),
+                (too_wide, 5),
+                (too_wide, 6),
+                (too_wide, 7),
+            ]
+        );
+        // the same however the symbol table happens to be ordered
+        for _ in 0..5 {
+            assert_eq!(assemble(source).unwrap_err(), diagnostics);
+        }
+    }
+}
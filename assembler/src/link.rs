@@ -0,0 +1,384 @@
+#![warn(clippy::all, clippy::pedantic)]
+
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Display;
+
+use giggle_flop::instruction::instruction::{decode_raw_instr, Instruction};
+
+use crate::diagnostics::{Diagnostic, Diagnostics};
+use crate::listing::Listing;
+use crate::object::{Object, ObjectSymbol, RelocationKind, Section, Target};
+use crate::{
+    encode_immediate, get_bytes, pseudo, Address, Program, ProgramContents, DATA_WORD_WIDTH_BITS,
+    INSTR_START_ADDR, INSTR_WIDTH_BITS,
+};
+
+/// Where the linker put one section of an object
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Placement {
+    pub object: String, // file the object was assembled from
+    pub section: Section,
+    pub start: Address,
+    pub width: Address, // in bits
+}
+
+/// Label or constant in a linked program, and the object that defined it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MapSymbol {
+    pub name: String,
+    pub object: String,
+    pub value: i64, // bit address for labels
+    pub is_label: bool,
+    pub global: bool,
+}
+
+/// Where everything in a linked program ended up
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LinkMap {
+    pub placements: Vec<Placement>, // in address order
+    pub symbols: Vec<MapSymbol>,    // labels in address order, then constants
+}
+
+impl Display for LinkMap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Sections:")?;
+        writeln!(f, "  Start       End         Section  Object")?;
+        for placement in &self.placements {
+            writeln!(
+                f,
+                "  0x{:08X}  0x{:08X}  {:<7}  {}",
+                placement.start,
+                placement.start + placement.width,
+                placement.section.to_string(),
+                placement.object
+            )?;
+        }
+
+        writeln!(f, "\nSymbols:")?;
+        writeln!(f, "  Value       Scope   Symbol")?;
+        for symbol in &self.symbols {
+            writeln!(
+                f,
+                "  0x{:08X}  {:<6}  {} ({}{})",
+                symbol.value,
+                if symbol.global { "global" } else { "local" },
+                symbol.name,
+                symbol.object,
+                if symbol.is_label { "" } else { ", constant" }
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn section_words(object: &Object, section: Section) -> &[u32] {
+    match section {
+        Section::Text => &object.text,
+        Section::Data => &object.data,
+    }
+}
+
+fn section_width(object: &Object, section: Section) -> Address {
+    let word_width = match section {
+        Section::Text => INSTR_WIDTH_BITS,
+        Section::Data => DATA_WORD_WIDTH_BITS,
+    };
+    Address::try_from(section_words(object, section).len())
+        .unwrap_or(Address::MAX)
+        .saturating_mul(word_width)
+}
+
+/// Returns the value of `symbol` once its object's sections start at `bases`
+fn symbol_value(symbol: &ObjectSymbol, bases: &HashMap<Section, Address>) -> i64 {
+    symbol.section.map_or(symbol.value, |section| {
+        i64::from(bases[&section]) + symbol.value
+    })
+}
+
+/// Replaces the immediate field of the instruction `word`
+fn set_immediate(word: &mut u32, immediate: u32) -> Result<(), String> {
+    let instr = decode_raw_instr(*word)
+        .filter(|instr| instr.immediate().is_some())
+        .ok_or("the word isn't an instruction with an immediate")?;
+    let relocated = Instruction::from_fields(
+        instr.instr_type(),
+        instr.opcode(),
+        &instr.reg_args(),
+        immediate,
+    )
+    .ok_or("the word isn't a valid instruction")?;
+    *word = relocated.encode();
+    Ok(())
+}
+
+/// Fills the part of `word` that `kind` names in with `value`
+fn relocate(word: &mut u32, kind: RelocationKind, value: i64) -> Result<(), String> {
+    if kind == RelocationKind::Immediate {
+        let instr = decode_raw_instr(*word).ok_or("the word isn't an instruction")?;
+        let immediate = encode_immediate(instr.instr_type(), instr.opcode(), value)?;
+        return set_immediate(word, immediate);
+    }
+
+    if !(i64::from(i32::MIN)..=i64::from(u32::MAX)).contains(&value) {
+        return Err(format!("{value} doesn't fit in 32 bits"));
+    }
+    // negative values are stored as their two's complement
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let value = value as u32;
+    let (high, low) = pseudo::li_parts(value);
+    match kind {
+        RelocationKind::High => set_immediate(word, high),
+        RelocationKind::Low => set_immediate(word, low),
+        RelocationKind::Word => {
+            *word = value;
+            Ok(())
+        }
+        RelocationKind::Immediate => unreachable!(),
+    }
+}
+
+/// Returns copies of every object's text and data with their relocations
+/// filled in, now that `bases` says where each section starts and `globals`
+/// holds the value of every exported symbol
+fn apply_relocations(
+    objects: &[Object],
+    bases: &[HashMap<Section, Address>],
+    globals: &HashMap<&str, (i64, &str)>,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> (Vec<Vec<u32>>, Vec<Vec<u32>>) {
+    let mut texts: Vec<Vec<u32>> = objects.iter().map(|object| object.text.clone()).collect();
+    let mut datas: Vec<Vec<u32>> = objects.iter().map(|object| object.data.clone()).collect();
+    for (idx, object) in objects.iter().enumerate() {
+        for relocation in &object.relocations {
+            let target = match &relocation.target {
+                Target::Section(section) => i64::from(bases[idx][section]),
+                Target::Symbol(name) => match globals.get(name.as_str()) {
+                    Some((value, _)) => *value,
+                    // reported along with the rest of the object's imports
+                    None => continue,
+                },
+            };
+            let words = match relocation.section {
+                Section::Text => &mut texts[idx],
+                Section::Data => &mut datas[idx],
+            };
+            let relocated = match words.get_mut(relocation.index) {
+                Some(word) => relocate(word, relocation.kind, target + relocation.addend),
+                None => Err("the section isn't that long".to_string()),
+            };
+            if let Err(reason) = relocated {
+                diagnostics.push(Diagnostic::file_error(
+                    &object.source,
+                    format!(
+                        "Can't relocate word {} of the {} section: {reason}",
+                        relocation.index, relocation.section
+                    ),
+                ));
+            }
+        }
+    }
+
+    (texts, datas)
+}
+
+/// Combines `objects` into a single program. Every object's text is laid out
+/// in the order given starting from address 0, so the program starts with
+/// the first object's first instruction, followed by every object's data.
+/// Symbols an object imports are looked up among those the others export
+///
+/// # Errors
+///
+/// Returns every problem found if the objects can't be linked, such as
+/// symbols that are imported but never exported, or exported twice
+pub fn link(objects: &[Object]) -> Result<(Program, LinkMap), Diagnostics> {
+    let mut diagnostics = vec![];
+    let mut map = LinkMap::default();
+
+    let mut bases: Vec<HashMap<Section, Address>> = vec![HashMap::new(); objects.len()];
+    let mut curr_addr = INSTR_START_ADDR;
+    for section in [Section::Text, Section::Data] {
+        for (object, bases) in objects.iter().zip(&mut bases) {
+            let width = section_width(object, section);
+            bases.insert(section, curr_addr);
+            map.placements.push(Placement {
+                object: object.source.clone(),
+                section,
+                start: curr_addr,
+                width,
+            });
+            curr_addr = curr_addr.saturating_add(width);
+        }
+    }
+
+    let mut globals: HashMap<&str, (i64, &str)> = HashMap::new();
+    for (object, bases) in objects.iter().zip(&bases) {
+        for symbol in &object.symbols {
+            let value = symbol_value(symbol, bases);
+            map.symbols.push(MapSymbol {
+                name: symbol.name.clone(),
+                object: object.source.clone(),
+                value,
+                is_label: symbol.section.is_some(),
+                global: symbol.global,
+            });
+            if !symbol.global {
+                continue;
+            }
+            match globals.entry(&symbol.name) {
+                Entry::Vacant(entry) => {
+                    entry.insert((value, &object.source));
+                }
+                Entry::Occupied(entry) => diagnostics.push(Diagnostic::file_error(
+                    &object.source,
+                    format!(
+                        "Symbol {} is also exported by {}",
+                        symbol.name,
+                        entry.get().1
+                    ),
+                )),
+            }
+        }
+    }
+    map.symbols
+        .sort_by(|a, b| (!a.is_label, a.value, &a.name).cmp(&(!b.is_label, b.value, &b.name)));
+
+    for object in objects {
+        for import in &object.imports {
+            if !globals.contains_key(import.as_str()) {
+                diagnostics.push(Diagnostic::file_error(
+                    &object.source,
+                    format!("Undefined symbol {import}. No object exports it with .GLOBAL"),
+                ));
+            }
+        }
+    }
+
+    let (texts, datas) = apply_relocations(objects, &bases, &globals, &mut diagnostics);
+
+    let mut contents = ProgramContents::default();
+    for (object, text) in objects.iter().zip(&texts) {
+        for word in text {
+            match decode_raw_instr(*word) {
+                Some(instr) => contents.instructions.push(instr),
+                None => diagnostics.push(Diagnostic::file_error(
+                    &object.source,
+                    format!("0x{word:08X} in the text section isn't an instruction"),
+                )),
+            }
+        }
+    }
+    contents.data = datas.into_iter().flatten().collect();
+
+    let diagnostics = Diagnostics::from(diagnostics);
+    if diagnostics.has_errors() {
+        return Err(diagnostics);
+    }
+
+    let bytes = get_bytes(&contents);
+    let symbols: BTreeMap<String, Address> = map
+        .symbols
+        .iter()
+        .filter(|symbol| symbol.global && symbol.is_label)
+        .filter_map(|symbol| Some((symbol.name.clone(), Address::try_from(symbol.value).ok()?)))
+        .collect();
+    let program = Program {
+        instructions: contents.instructions,
+        data: contents.data,
+        bytes,
+        symbols,
+        warnings: objects
+            .iter()
+            .flat_map(|object| object.warnings.iter().cloned())
+            .collect::<Vec<_>>()
+            .into(),
+        listing: Listing::default(),
+    };
+    Ok((program, map))
+}
+
+#[cfg(test)]
+mod test {
+    use giggle_flop::instruction::instruction::Instruction;
+
+    use crate::{assemble, assemble_object, link, Object, Options};
+
+    fn object(name: &str, source: &str) -> Object {
+        let opts = Options {
+            file_name: name.to_string(),
+            ..Options::default()
+        };
+        assemble_object(source, &opts).unwrap()
+    }
+
+    #[test]
+    fn links_objects() {
+        let main = ".extern double
+            CALL double
+            HALT
+            .DATA
+            ptr: .WORD double + 32";
+        let lib = ".global double
+            double: ADDU R1, R1, R1
+            JE double
+            RET";
+        let (program, map) = link(&[object("main.gf", main), object("lib.gf", lib)]).unwrap();
+
+        // main's text, then lib's, then main's data
+        assert_eq!(program.symbols["double"], 2 * 32);
+        assert_eq!(map.placements[1].start, 2 * 32);
+        assert_eq!(map.placements[2].start, 5 * 32);
+        assert_eq!(
+            program.instructions[0],
+            Instruction::Type1 {
+                opcode: 0,
+                immediate: 2 * 32
+            }
+        );
+        assert_eq!(
+            program.instructions[3],
+            Instruction::Type1 {
+                opcode: 1,
+                immediate: 2 * 32
+            }
+        );
+        assert_eq!(program.data, vec![3 * 32]);
+
+        let diagnostics = link(&[object("main.gf", main)]).unwrap_err();
+        assert_eq!(
+            diagnostics.iter().next().unwrap().message,
+            "Undefined symbol double. No object exports it with .GLOBAL"
+        );
+        let diagnostics = link(&[object("lib.gf", lib), object("copy.gf", lib)]).unwrap_err();
+        assert_eq!(
+            diagnostics.iter().next().unwrap().message,
+            "Symbol double is also exported by lib.gf"
+        );
+        let diagnostics = assemble(main).unwrap_err();
+        assert_eq!(
+            diagnostics.iter().next().unwrap().message,
+            "double is defined in another file, so this has to be assembled as an object and linked"
+        );
+    }
+
+    #[test]
+    fn checks_sign_extended_relocations() {
+        let main = ".extern CHAR_OUT
+            LD32 R1, CHAR_OUT
+            ADDIM R1, CHAR_OUT
+            HALT";
+        let console = ".global CHAR_OUT
+            .equ CHAR_OUT, 2097024";
+        let diagnostics =
+            link(&[object("main.gf", main), object("console.gf", console)]).unwrap_err();
+        let messages: Vec<_> = diagnostics.iter().map(|diag| &diag.message).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "Can't relocate word 1 of the text section: Immediate 2097024 doesn't fit in the \
+                 sign-extended immediate of ADDIM. Valid range is [-1048576, 1048575]. Use LI \
+                 to load values outside it"
+            ]
+        );
+    }
+}
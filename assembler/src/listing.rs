@@ -0,0 +1,194 @@
+#![warn(clippy::all, clippy::pedantic)]
+
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use crate::object::Section;
+use crate::source::{FileId, SourceFile, MAIN_FILE};
+use crate::{Address, DATA_WORD_WIDTH_BITS, INSTR_WIDTH_BITS};
+
+/// Words or label a single statement added to the program
+#[derive(Debug)]
+pub struct Emitted {
+    pub offset: usize, // start of the statement's span
+    pub section: Section,
+    pub index: usize, // first word within the section
+    pub words: Vec<u32>,
+    pub label: Option<String>,
+}
+
+/// One line of source, along with everything assembled from it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ListingLine {
+    pub file: String,
+    pub line: usize,              // 1-based
+    pub address: Option<Address>, // of the first word, or the labels if there are none
+    pub words: Vec<u32>,          // including those of any macros invoked on the line
+    pub labels: Vec<String>,      // defined on the line, including by its macros
+    pub text: String,             // as written, comments and all
+}
+
+/// Every line of source assembled, in the order they were read, with included
+/// files listed after the line including them
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Listing {
+    pub lines: Vec<ListingLine>,
+}
+
+/// Lists the file `id` and everything it includes
+fn list_file(
+    file: &SourceFile,
+    id: FileId,
+    lines: &mut HashMap<(FileId, usize), ListingLine>,
+    listing: &mut Vec<ListingLine>,
+) {
+    for line_idx in 0..file.line_count(id) {
+        let mut line = lines.remove(&(id, line_idx)).unwrap_or_default();
+        line.file = file.name(id).to_string();
+        line.line = line_idx + 1;
+        line.text = file.line_text(id, line_idx).to_string();
+        listing.push(line);
+        for included in file.includes(id, line_idx) {
+            list_file(file, included, lines, listing);
+        }
+    }
+}
+
+/// Builds the listing of `file` from what each statement emitted, given that
+/// data starts at `data_start`
+pub fn build(file: &SourceFile, emitted: Vec<Emitted>, data_start: Address) -> Listing {
+    let mut lines: HashMap<(FileId, usize), ListingLine> = HashMap::new();
+    for emitted in emitted {
+        let index = Address::try_from(emitted.index).unwrap_or(Address::MAX);
+        let address = match emitted.section {
+            Section::Text => index.saturating_mul(INSTR_WIDTH_BITS),
+            Section::Data => data_start.saturating_add(index.saturating_mul(DATA_WORD_WIDTH_BITS)),
+        };
+        let line = lines.entry(file.origin(emitted.offset)).or_default();
+        line.address.get_or_insert(address);
+        line.words.extend(emitted.words);
+        line.labels.extend(emitted.label);
+    }
+
+    let mut listing = vec![];
+    list_file(file, MAIN_FILE, &mut lines, &mut listing);
+    Listing { lines: listing }
+}
+
+impl Display for Listing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let labels: Vec<String> = self
+            .lines
+            .iter()
+            .map(|line| {
+                line.labels
+                    .iter()
+                    .map(|label| format!("{label}:"))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect();
+        let labels_width = labels
+            .iter()
+            .map(String::len)
+            .fold("Labels".len(), usize::max);
+        let line_width = self.lines.iter().map(|line| line.line).max().unwrap_or(0);
+        let line_width = line_width.to_string().len().max("Line".len());
+        writeln!(
+            f,
+            "{:>line_width$}  {:<10}  {:<8}  {:>5}  {:<labels_width$}  Source",
+            "Line", "Address", "Word", "Words", "Labels"
+        )?;
+
+        let mut curr_file = None;
+        for (line, labels) in self.lines.iter().zip(labels) {
+            if curr_file != Some(&line.file) {
+                if curr_file.is_some() {
+                    writeln!(f)?;
+                }
+                writeln!(f, "{}:", line.file)?;
+                curr_file = Some(&line.file);
+            }
+
+            let address = line
+                .address
+                .map_or(String::new(), |address| format!("0x{address:08X}"));
+            let word = line
+                .words
+                .first()
+                .map_or(String::new(), |word| format!("{word:08X}"));
+            // how far a pseudo-instruction or macro expands
+            let count = match line.words.len() {
+                0 => String::new(),
+                count => count.to_string(),
+            };
+            let row = format!(
+                "{:>line_width$}  {address:<10}  {word:<8}  {count:>5}  {labels:<labels_width$}  {}",
+                line.line, line.text
+            );
+            writeln!(f, "{}", row.trim_end())?;
+
+            // the rest of a line's words each get a row of their own
+            let address = line.address.unwrap_or(0);
+            for (idx, word) in (1..).zip(line.words.iter().skip(1)) {
+                writeln!(
+                    f,
+                    "{:line_width$}  0x{:08X}  {word:08X}",
+                    "",
+                    address.saturating_add(idx * INSTR_WIDTH_BITS)
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{assemble, ListingLine};
+
+    #[test]
+    fn lists_every_line() {
+        let program = assemble(
+            ".macro INC reg
+                ADDIM \\reg, 1
+            .endm
+            start: NOP // spin
+            INC R1
+            .DATA
+            .WORD 5, 6",
+        )
+        .unwrap();
+        let lines = &program.listing.lines;
+        assert_eq!(lines.len(), 7);
+        assert_eq!(lines[1].address, None);
+        assert_eq!(
+            lines[3],
+            ListingLine {
+                file: "<source>".to_string(),
+                line: 4,
+                address: Some(0),
+                words: vec![program.instructions[0].encode()],
+                labels: vec!["start".to_string()],
+                text: "            start: NOP // spin".to_string(),
+            }
+        );
+        // the macro's instructions are listed against its invocation
+        assert_eq!(lines[4].address, Some(32));
+        assert_eq!(lines[4].words, vec![program.instructions[1].encode()]);
+        assert_eq!(lines[6].address, Some(64));
+        assert_eq!(lines[6].words, vec![5, 6]);
+
+        let listing = program.listing.to_string();
+        assert!(listing
+            .contains("\n   7  0x00000040  00000005      2                      .WORD 5, 6\n"));
+        assert!(listing.contains("\n      0x00000060  00000006\n"));
+
+        // only instructions are mapped back to their lines for the debugger
+        let debug_info = program.debug_info();
+        assert_eq!(debug_info.files[0].lines.len(), 7);
+        assert_eq!(debug_info.lines.len(), 2);
+        assert_eq!(debug_info.line_at(32).map(|mapping| mapping.line), Some(5));
+        assert_eq!(debug_info.resolve_breakpoint("start").unwrap(), 0);
+    }
+}
@@ -0,0 +1,467 @@
+#![warn(clippy::all, clippy::pedantic)]
+
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+use giggle_flop::register::register_system::ALL_INSTR_TYPES;
+
+use crate::diagnostics::Diagnostic;
+use crate::lexer::{Token, TokenKind};
+use crate::pseudo::Pseudo;
+use crate::source::SourceFile;
+
+const MACRO_DIRECTIVE: &str = "MACRO";
+const END_MACRO_DIRECTIVE: &str = "ENDM";
+const CONSTANT_DIRECTIVE: &str = "EQU";
+// Joins a label or constant local to a macro to the number of the expansion
+// it belongs to. Identifiers can't contain it, so the result never clashes
+// with a name from the source
+const LOCAL_NAME_SEPARATOR: char = '.';
+// Deep enough for any sensible nesting while still catching macros that
+// invoke themselves
+const MAX_EXPANSION_DEPTH: usize = 64;
+
+/// A `.macro NAME params ... .endm` definition
+struct Macro {
+    name: String,
+    params: Vec<String>,
+    body: Vec<Token>,
+    source: Range<usize>,    // byte range of the body
+    locals: HashSet<String>, // labels and constants renamed in each expansion
+    line: usize,             // line the definition starts on
+}
+
+#[derive(Default)]
+struct Expander {
+    macros: HashMap<String, Macro>, // upper case name -> definition
+    expansion_count: usize,
+}
+
+type Line = Vec<Token>;
+
+/// Returns whether `name` is a label or constant local to one expansion of a
+/// macro, which can't be written in source
+pub fn is_local_name(name: &str) -> bool {
+    name.contains(LOCAL_NAME_SEPARATOR)
+}
+
+/// Returns the name a label or constant was written as, without the
+/// expansion it belongs to if it's local to a macro
+pub fn source_name(name: &str) -> &str {
+    name.split(LOCAL_NAME_SEPARATOR).next().unwrap_or(name)
+}
+
+/// Splits tokens into lines, each ending with its `Newline` if it has one
+fn split_lines(tokens: Vec<Token>) -> Vec<Line> {
+    let mut lines = vec![];
+    let mut line = vec![];
+    for token in tokens {
+        let is_newline = token.kind == TokenKind::Newline;
+        line.push(token);
+        if is_newline {
+            lines.push(std::mem::take(&mut line));
+        }
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+fn is_directive(token: Option<&Token>, directive: &str) -> bool {
+    match token {
+        Some(Token {
+            kind: TokenKind::Directive(name),
+            ..
+        }) => name.eq_ignore_ascii_case(directive),
+        _ => false,
+    }
+}
+
+/// Returns the tokens of `line` before its `Newline`
+fn content(line: &[Token]) -> &[Token] {
+    match line.split_last() {
+        Some((last, rest)) if last.kind == TokenKind::Newline => rest,
+        _ => line,
+    }
+}
+
+/// Returns the number of leading tokens of `line` that define labels
+fn label_prefix_len(line: &[Token]) -> usize {
+    line.chunks_exact(2)
+        .take_while(|pair| {
+            matches!(
+                (&pair[0].kind, &pair[1].kind),
+                (TokenKind::Ident(_), TokenKind::Colon)
+            )
+        })
+        .count()
+        * 2
+}
+
+fn expected(file: &SourceFile, expected: &str, token: &Token) -> Diagnostic {
+    file.error(
+        &token.span,
+        format!("Expected {expected}, found {}", token.kind),
+    )
+}
+
+/// Splits a macro invocation's arguments on the commas between them.
+/// Commas inside parentheses belong to the argument
+fn split_args(tokens: &[Token]) -> Vec<&[Token]> {
+    if tokens.is_empty() {
+        return vec![];
+    }
+    let mut args = vec![];
+    let mut depth = 0_usize;
+    let mut start = 0;
+    for (idx, token) in tokens.iter().enumerate() {
+        match token.kind {
+            TokenKind::LParen => depth += 1,
+            TokenKind::RParen => depth = depth.saturating_sub(1),
+            TokenKind::Comma if depth == 0 => {
+                args.push(&tokens[start..idx]);
+                start = idx + 1;
+            }
+            _ => {}
+        }
+    }
+    args.push(&tokens[start..]);
+    args
+}
+
+impl Expander {
+    fn expand_lines(
+        &mut self,
+        file: &mut SourceFile,
+        lines: Vec<Line>,
+        depth: usize,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Vec<Token> {
+        let mut expanded = vec![];
+        let mut lines = lines.into_iter();
+        while let Some(line) = lines.next() {
+            if is_directive(line.first(), MACRO_DIRECTIVE) {
+                let mut body = vec![];
+                let mut terminated = false;
+                for body_line in lines.by_ref() {
+                    if is_directive(body_line.first(), END_MACRO_DIRECTIVE) {
+                        terminated = true;
+                        break;
+                    }
+                    body.push(body_line);
+                }
+                if terminated {
+                    if let Err(diagnostic) = self.define(file, &line, body) {
+                        diagnostics.push(diagnostic);
+                    }
+                } else {
+                    diagnostics.push(file.error(
+                        &line[0].span,
+                        format!(".{MACRO_DIRECTIVE} without a matching .{END_MACRO_DIRECTIVE}"),
+                    ));
+                }
+                continue;
+            }
+            if is_directive(line.first(), END_MACRO_DIRECTIVE) {
+                diagnostics.push(file.error(
+                    &line[0].span,
+                    format!(".{END_MACRO_DIRECTIVE} without a matching .{MACRO_DIRECTIVE}"),
+                ));
+                continue;
+            }
+
+            let labels = label_prefix_len(&line);
+            let is_invocation = matches!(
+                line.get(labels),
+                Some(Token { kind: TokenKind::Ident(name), .. })
+                    if self.macros.contains_key(&name.to_ascii_uppercase())
+            );
+            if !is_invocation {
+                expanded.extend(line);
+                continue;
+            }
+
+            // labels stay with the first line of the expansion
+            expanded.extend_from_slice(&line[..labels]);
+            match self.invoke(file, &line[labels..], depth, diagnostics) {
+                Ok(mut tokens) => expanded.append(&mut tokens),
+                Err(diagnostic) => diagnostics.push(diagnostic),
+            }
+            expanded.extend(
+                line.last()
+                    .filter(|t| t.kind == TokenKind::Newline)
+                    .cloned(),
+            );
+        }
+        expanded
+    }
+
+    /// Records the macro defined by the `.macro` line `header` and the lines
+    /// up to its `.endm`
+    fn define(
+        &mut self,
+        file: &SourceFile,
+        header: &[Token],
+        body: Vec<Line>,
+    ) -> Result<(), Diagnostic> {
+        let header = content(header);
+        let Some(Token {
+            kind: TokenKind::Ident(name),
+            span: name_span,
+        }) = header.get(1)
+        else {
+            let found = header.get(1).unwrap_or(&header[0]);
+            return Err(expected(file, "a macro name", found));
+        };
+
+        let mut params: Vec<String> = vec![];
+        for (idx, token) in header[2..].iter().enumerate() {
+            match (&token.kind, idx % 2) {
+                (TokenKind::Ident(param), 0) => {
+                    if params.contains(param) {
+                        return Err(
+                            file.error(&token.span, format!("Duplicate macro parameter {param}"))
+                        );
+                    }
+                    params.push(param.clone());
+                }
+                (TokenKind::Comma, 1) => {}
+                (_, 0) => return Err(expected(file, "a parameter name", token)),
+                _ => return Err(expected(file, "`,` or the end of the line", token)),
+            }
+        }
+        if let Some(comma) = header[2..].last().filter(|t| t.kind == TokenKind::Comma) {
+            return Err(file.error(
+                &comma.span,
+                "Expected a parameter name after `,`".to_string(),
+            ));
+        }
+
+        let is_instruction = ALL_INSTR_TYPES
+            .iter()
+            .flat_map(|names| names.iter())
+            .any(|instr| instr.eq_ignore_ascii_case(name))
+            || Pseudo::from_name(name).is_some();
+        if is_instruction {
+            return Err(file.error(
+                name_span,
+                format!("Macro {name} would hide the instruction of the same name"),
+            ));
+        }
+        if let Some(prev) = self.macros.get(&name.to_ascii_uppercase()) {
+            return Err(file.error(
+                name_span,
+                format!(
+                    "Multiple definitions of macro {name}. Previous definition on line {}",
+                    prev.line
+                ),
+            ));
+        }
+
+        let mut locals = HashSet::new();
+        for line in &body {
+            if is_directive(line.first(), MACRO_DIRECTIVE) {
+                return Err(file.error(
+                    &line[0].span,
+                    "Macros can't be defined inside other macros".to_string(),
+                ));
+            }
+            if is_directive(line.first(), CONSTANT_DIRECTIVE) {
+                if let Some(Token {
+                    kind: TokenKind::Ident(constant),
+                    ..
+                }) = line.get(1)
+                {
+                    locals.insert(constant.clone());
+                }
+            }
+            for pair in line[..label_prefix_len(line)].chunks_exact(2) {
+                if let TokenKind::Ident(label) = &pair[0].kind {
+                    locals.insert(label.clone());
+                }
+            }
+        }
+
+        let body: Vec<Token> = body.into_iter().flatten().collect();
+        let source = match (body.first(), body.last()) {
+            (Some(first), Some(last)) => first.span.start..last.span.end,
+            _ => name_span.end..name_span.end,
+        };
+        self.macros.insert(
+            name.to_ascii_uppercase(),
+            Macro {
+                name: name.clone(),
+                params,
+                body,
+                source,
+                locals,
+                line: file.span(&header[0].span).line,
+            },
+        );
+        Ok(())
+    }
+
+    /// Expands the invocation `line`, which starts with the macro's name,
+    /// along with any invocations within the expansion
+    fn invoke(
+        &mut self,
+        file: &mut SourceFile,
+        line: &[Token],
+        depth: usize,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Result<Vec<Token>, Diagnostic> {
+        let line = content(line);
+        let TokenKind::Ident(ref name) = line[0].kind else {
+            unreachable!();
+        };
+        let site = line[0].span.start..line[line.len() - 1].span.end;
+        self.expansion_count += 1;
+        let suffix = self.expansion_count;
+        let Some(mac) = self.macros.get(&name.to_ascii_uppercase()) else {
+            unreachable!();
+        };
+
+        if depth >= MAX_EXPANSION_DEPTH {
+            return Err(file.error(
+                &site,
+                format!(
+                    "Macro {} nests more than {MAX_EXPANSION_DEPTH} expansions deep. \
+                     Does it invoke itself?",
+                    mac.name
+                ),
+            ));
+        }
+        let args = split_args(&line[1..]);
+        if args.len() != mac.params.len() {
+            return Err(file.error(
+                &site,
+                format!(
+                    "Macro {} takes {} argument(s) but {} were given",
+                    mac.name,
+                    mac.params.len(),
+                    args.len()
+                ),
+            ));
+        }
+        if let Some(empty) = args.iter().position(|arg| arg.is_empty()) {
+            return Err(file.error(
+                &site,
+                format!("Missing argument for parameter {}", mac.params[empty]),
+            ));
+        }
+
+        let start = file.add_expansion(mac.source.clone(), &mac.name, site);
+        let shift = |span: &Range<usize>| {
+            start + (span.start - mac.source.start)..start + (span.end - mac.source.start)
+        };
+
+        let mut tokens = vec![];
+        for token in &mac.body {
+            let kind = match &token.kind {
+                TokenKind::MacroParam(param) => {
+                    let Some(idx) = mac.params.iter().position(|p| p == param) else {
+                        diagnostics.push(file.error(
+                            &shift(&token.span),
+                            format!("Unknown macro parameter \\{param}"),
+                        ));
+                        continue;
+                    };
+                    tokens.extend_from_slice(args[idx]);
+                    continue;
+                }
+                // give each expansion its own copy of the macro's labels
+                TokenKind::Ident(name) if mac.locals.contains(name) => {
+                    TokenKind::Ident(format!("{name}{LOCAL_NAME_SEPARATOR}{suffix}"))
+                }
+                kind => kind.clone(),
+            };
+            tokens.push(Token {
+                kind,
+                span: shift(&token.span),
+            });
+        }
+
+        Ok(self.expand_lines(file, split_lines(tokens), depth + 1, diagnostics))
+    }
+}
+
+/// Expands every macro invocation in `tokens`, dropping the definitions.
+/// Macros must be defined before they're used. Expanded tokens get offsets
+/// registered with `file`, so diagnostics about them point to both the line
+/// in the macro body and the invocation
+pub fn expand(
+    file: &mut SourceFile,
+    mut tokens: Vec<Token>,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Vec<Token> {
+    let eof = tokens.pop();
+    let mut expanded = Expander::default().expand_lines(file, split_lines(tokens), 0, diagnostics);
+    expanded.extend(eof);
+    expanded
+}
+
+#[cfg(test)]
+mod test {
+    use crate::lexer::{tokenize, TokenKind};
+    use crate::macros::expand;
+    use crate::source::{SourceFile, MAIN_FILE};
+
+    fn expand_text(text: &str) -> (Vec<TokenKind>, usize) {
+        let mut file = SourceFile::new("test.gf", text);
+        let mut diagnostics = vec![];
+        let tokens = tokenize(&file, MAIN_FILE, &mut diagnostics);
+        let tokens = expand(&mut file, tokens, &mut diagnostics);
+        (
+            tokens.into_iter().map(|token| token.kind).collect(),
+            diagnostics.len(),
+        )
+    }
+
+    #[test]
+    fn substitutes_arguments_and_renames_locals() {
+        let (tokens, errors) = expand_text(
+            ".macro WAIT reg
+            top: CMP32 \\reg, \\reg
+            JNE top
+            .endm
+            WAIT R1
+            WAIT R2",
+        );
+        assert_eq!(errors, 0);
+        let idents: Vec<_> = tokens
+            .iter()
+            .filter_map(|kind| match kind {
+                TokenKind::Ident(name) => Some(name.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            idents,
+            vec![
+                "top.1", "CMP32", "R1", "R1", "JNE", "top.1", "top.2", "CMP32", "R2", "R2", "JNE",
+                "top.2"
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_bad_definitions_and_invocations() {
+        let (_, errors) = expand_text(
+            ".macro ADDI a
+            .endm
+            .macro TWICE a, a
+            .endm
+            .macro TRAILING a,
+            .endm
+            .macro LOOP
+            LOOP
+            .endm
+            LOOP
+            .endm",
+        );
+        // the instruction name, the duplicate parameter, the trailing comma,
+        // the runaway recursion, and the stray .endm
+        assert_eq!(errors, 5);
+    }
+}
@@ -0,0 +1,135 @@
+#![warn(clippy::all, clippy::pedantic)]
+
+use std::fmt::Display;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::diagnostics::Diagnostics;
+use crate::listing::Listing;
+
+// Identifies object files, so other JSON isn't mistaken for one
+const OBJECT_FORMAT: &str = "giggle-flop-object";
+// Bumped whenever the format changes in a way older linkers can't read
+const OBJECT_VERSION: u32 = 1;
+
+/// Part of an object holding instructions or initialized data. When linked,
+/// every object's text comes first, followed by every object's data
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Section {
+    Text,
+    Data,
+}
+
+impl Display for Section {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Text => write!(f, "text"),
+            Self::Data => write!(f, "data"),
+        }
+    }
+}
+
+/// Address a relocated value is an offset from
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Target {
+    Section(Section), // start of a section of the same object
+    Symbol(String),   // symbol exported by another object
+}
+
+/// Which part of a word a relocation fills in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RelocationKind {
+    Immediate, // an instruction's immediate field
+    High,      // the immediate loading the upper bits of a long `LI`
+    Low,       // the immediate adding the low bits of a long `LI`
+    Word,      // a whole data word
+}
+
+/// Word whose value depends on an address only known once the program is
+/// linked. The value filled in is the address of `target` plus `addend`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Relocation {
+    pub section: Section, // section holding the word
+    pub index: usize,     // word within the section
+    pub kind: RelocationKind,
+    pub target: Target,
+    pub addend: i64,
+}
+
+/// Label or constant defined by an object
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ObjectSymbol {
+    pub name: String,
+    pub section: Option<Section>, // `None` for constants
+    pub value: i64,               // bit offset within the section, or the constant's value
+    pub global: bool,             // exported with `.GLOBAL` for other objects to use
+}
+
+/// A source file assembled on its own, with the addresses of its labels and
+/// any symbols it uses from other files left for the linker to fill in
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Object {
+    pub format: String,
+    pub version: u32,
+    pub source: String,             // file the object was assembled from
+    pub text: Vec<u32>,             // encoded instructions, with relocated immediates left 0
+    pub data: Vec<u32>,             // initialized words, with relocated words left 0
+    pub symbols: Vec<ObjectSymbol>, // every label, and exported constants
+    pub imports: Vec<String>,       // symbols declared `.EXTERN`
+    pub relocations: Vec<Relocation>,
+    #[serde(skip)]
+    pub warnings: Diagnostics,
+    #[serde(skip)]
+    pub listing: Listing, // addresses as if the object were linked on its own
+}
+
+impl Object {
+    #[must_use]
+    pub fn new(source: &str) -> Self {
+        Self {
+            format: OBJECT_FORMAT.to_string(),
+            version: OBJECT_VERSION,
+            source: source.to_string(),
+            text: vec![],
+            data: vec![],
+            symbols: vec![],
+            imports: vec![],
+            relocations: vec![],
+            warnings: Diagnostics::default(),
+            listing: Listing::default(),
+        }
+    }
+
+    /// Returns the object file contents, which are JSON
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the object can't be serialized
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Reads back an object written by [`Object::to_json`]
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `json` isn't an object file this version can read
+    pub fn from_json(json: &str) -> Result<Self> {
+        let object: Self = serde_json::from_str(json)?;
+        if object.format != OBJECT_FORMAT {
+            return Err(anyhow!("Not a {OBJECT_FORMAT} file"));
+        }
+        if object.version != OBJECT_VERSION {
+            return Err(anyhow!(
+                "Object format version {} isn't supported. Reassemble it with this version, \
+                 which reads version {OBJECT_VERSION}",
+                object.version
+            ));
+        }
+        Ok(object)
+    }
+}
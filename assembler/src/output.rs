@@ -0,0 +1,180 @@
+#![warn(clippy::all, clippy::pedantic)]
+
+use std::fmt::Write;
+
+use clap::ValueEnum;
+
+use crate::Program;
+
+/// File format an assembled or linked program is written in
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Container with a header, entry point, segments, symbols, and checksum
+    #[default]
+    Container,
+    /// Bare big-endian words loaded from address 0, as older loaders expect
+    Raw,
+    /// Intel HEX records of the raw image, addressed by byte
+    IntelHex,
+    /// Text for Verilog's `$readmemh`, one 32-bit word of the raw image per line
+    Readmemh,
+    /// C source defining the raw image as an array of bytes
+    CArray,
+    /// Rust source defining the raw image as an array of bytes
+    RustArray,
+}
+
+// Data bytes in each Intel HEX record
+const HEX_RECORD_LEN: usize = 16;
+// Name of the array in the C and Rust formats
+const ARRAY_NAME: &str = "program";
+
+/// Appends each of `bytes` to `text` as two hex digits between `prefix` and
+/// `separator`
+fn push_hex(text: &mut String, bytes: &[u8], prefix: &str, separator: &str) {
+    for byte in bytes {
+        write!(text, "{prefix}{byte:02X}{separator}").unwrap();
+    }
+}
+
+/// Returns a single Intel HEX record, checksum and all
+fn hex_record(kind: u8, offset: u16, data: &[u8]) -> String {
+    let len = u8::try_from(data.len()).expect("Intel HEX record too long");
+    let [offset_high, offset_low] = offset.to_be_bytes();
+    let mut record = vec![len, offset_high, offset_low, kind];
+    record.extend(data);
+    let checksum = record
+        .iter()
+        .fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+        .wrapping_neg();
+    record.push(checksum);
+    let mut hex = ":".to_string();
+    push_hex(&mut hex, &record, "", "");
+    hex + "\n"
+}
+
+/// Returns `bytes` as Intel HEX, starting at address 0
+fn intel_hex(bytes: &[u8]) -> String {
+    let mut hex = String::new();
+    let mut upper = 0;
+    for (idx, chunk) in bytes.chunks(HEX_RECORD_LEN).enumerate() {
+        let address = u32::try_from(idx * HEX_RECORD_LEN).expect("Program too large for Intel HEX");
+        let [b0, b1, b2, b3] = address.to_be_bytes();
+        let (high, low) = (u16::from_be_bytes([b0, b1]), u16::from_be_bytes([b2, b3]));
+        // addresses past 64K need an extended linear address record first
+        if high != upper {
+            hex += &hex_record(4, 0, &high.to_be_bytes());
+            upper = high;
+        }
+        hex += &hex_record(0, low, chunk);
+    }
+    hex + &hex_record(1, 0, &[])
+}
+
+/// Returns `bytes` as one hex word per line, for `$readmemh`
+fn readmemh(bytes: &[u8]) -> String {
+    let mut text = format!("// {} words, starting at address 0\n", bytes.len() / 4);
+    for word in bytes.chunks(4) {
+        push_hex(&mut text, word, "", "");
+        text.push('\n');
+    }
+    text
+}
+
+/// Returns the elements of an array holding `bytes`, 12 to a line
+fn array_elements(bytes: &[u8]) -> String {
+    let mut text = String::new();
+    for line in bytes.chunks(12) {
+        text += "   ";
+        push_hex(&mut text, line, " 0x", ",");
+        text.push('\n');
+    }
+    text
+}
+
+/// Returns C source defining `bytes` and their length
+fn c_array(bytes: &[u8]) -> String {
+    format!(
+        "#include <stddef.h>\n\n\
+         const unsigned char {ARRAY_NAME}[] = {{\n{}}};\n\
+         const size_t {ARRAY_NAME}_len = {};\n",
+        array_elements(bytes),
+        bytes.len()
+    )
+}
+
+/// Returns Rust source defining `bytes` as a constant
+fn rust_array(bytes: &[u8]) -> String {
+    format!(
+        "pub const {}: [u8; {}] = [\n{}];\n",
+        ARRAY_NAME.to_uppercase(),
+        bytes.len(),
+        array_elements(bytes)
+    )
+}
+
+impl OutputFormat {
+    /// Returns the contents of the file holding `program` in this format
+    #[must_use]
+    pub fn render(self, program: &Program) -> Vec<u8> {
+        match self {
+            Self::Container => program.image().to_bytes(),
+            Self::Raw => program.bytes.clone(),
+            Self::IntelHex => intel_hex(&program.bytes).into_bytes(),
+            Self::Readmemh => readmemh(&program.bytes).into_bytes(),
+            Self::CArray => c_array(&program.bytes).into_bytes(),
+            Self::RustArray => rust_array(&program.bytes).into_bytes(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use giggle_flop::system::image::ProgramImage;
+
+    use crate::output::{c_array, intel_hex, readmemh, rust_array};
+    use crate::{assemble, disassemble, OutputFormat};
+
+    #[test]
+    fn renders_containers() {
+        let program = assemble("start: CALL start\nHALT\n.DATA\nvalue: .WORD 7").unwrap();
+        let image = ProgramImage::parse(&OutputFormat::Container.render(&program)).unwrap();
+        assert_eq!(image.entry, Some(0));
+        assert_eq!(image.segments.len(), 2);
+        assert_eq!(image.segments[1].address, 64);
+        assert_eq!(image.segments[1].words, vec![7]);
+        assert_eq!(image.symbols["value"], 64);
+
+        // only the container keeps the label names
+        let raw = OutputFormat::Raw.render(&program);
+        assert_eq!(raw, program.bytes);
+        assert!(disassemble(&OutputFormat::Container.render(&program))
+            .unwrap()
+            .contains("CALL start"));
+        assert!(disassemble(&raw).unwrap().contains("CALL sub_000000"));
+    }
+
+    #[test]
+    fn renders_text_formats() {
+        let bytes = [0, 0, 0, 0x1F, 0, 0, 0, 7];
+        assert_eq!(
+            intel_hex(&bytes),
+            ":080000000000001F00000007D2\n:00000001FF\n"
+        );
+        assert!(intel_hex(&vec![0; 0x10010]).contains("\n:020000040001F9\n:10000000"));
+        assert_eq!(
+            readmemh(&bytes),
+            "// 2 words, starting at address 0\n0000001F\n00000007\n"
+        );
+        assert!(c_array(&bytes).contains(
+            "const unsigned char program[] = {\n    \
+             0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00, 0x07,\n};\n\
+             const size_t program_len = 8;\n"
+        ));
+        assert_eq!(
+            rust_array(&bytes),
+            "pub const PROGRAM: [u8; 8] = [\n    \
+             0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00, 0x07,\n];\n"
+        );
+    }
+}
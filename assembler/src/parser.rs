@@ -0,0 +1,404 @@
+#![warn(clippy::all, clippy::pedantic)]
+
+use crate::ast::{
+    BinaryOp, Expr, ExprKind, Ident, Operand, OperandKind, RegisterGroup, Statement, StatementKind,
+    UnaryOp,
+};
+use crate::diagnostics::Diagnostic;
+use crate::lexer::{Token, TokenKind};
+use crate::source::SourceFile;
+
+/// Recursive descent parser over the tokens of a single file. Each line holds
+/// any number of labels followed by at most one instruction or directive
+struct Parser<'a> {
+    file: &'a SourceFile,
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos]
+    }
+
+    fn peek_kind_at(&self, ahead: usize) -> &TokenKind {
+        let idx = (self.pos + ahead).min(self.tokens.len() - 1);
+        &self.tokens[idx].kind
+    }
+
+    fn bump(&mut self) -> Token {
+        let token = self.tokens[self.pos].clone();
+        if self.pos < self.tokens.len() - 1 {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn at_line_end(&self) -> bool {
+        matches!(self.peek().kind, TokenKind::Newline | TokenKind::Eof)
+    }
+
+    fn unexpected(&self, expected: &str) -> Diagnostic {
+        let token = self.peek();
+        self.file.error(
+            &token.span,
+            format!("Expected {expected}, found {}", token.kind),
+        )
+    }
+
+    /// Skips past the rest of the current line after an error
+    fn recover(&mut self) {
+        while !self.at_line_end() {
+            self.bump();
+        }
+    }
+
+    fn parse_file(&mut self, diagnostics: &mut Vec<Diagnostic>) -> Vec<Statement> {
+        let mut statements = vec![];
+        loop {
+            match self.peek().kind {
+                TokenKind::Eof => break,
+                TokenKind::Newline => {
+                    self.bump();
+                }
+                _ => {
+                    if let Err(diagnostic) = self.parse_line(&mut statements) {
+                        diagnostics.push(diagnostic);
+                        self.recover();
+                    }
+                }
+            }
+        }
+        statements
+    }
+
+    fn parse_line(&mut self, statements: &mut Vec<Statement>) -> Result<(), Diagnostic> {
+        while let (TokenKind::Ident(_), TokenKind::Colon) =
+            (&self.peek().kind, self.peek_kind_at(1))
+        {
+            let label = self.parse_ident();
+            let colon = self.bump();
+            statements.push(Statement {
+                span: label.span.start..colon.span.end,
+                kind: StatementKind::Label(label),
+            });
+        }
+
+        let statement = match self.peek().kind {
+            TokenKind::Newline | TokenKind::Eof => return Ok(()),
+            TokenKind::Ident(_) => {
+                let mnemonic = self.parse_ident();
+                let operands = self.parse_operands()?;
+                Statement {
+                    span: mnemonic.span.start
+                        ..operands.last().map_or(mnemonic.span.end, |op| op.span.end),
+                    kind: StatementKind::Instruction { mnemonic, operands },
+                }
+            }
+            TokenKind::Directive(_) => {
+                let token = self.bump();
+                let TokenKind::Directive(name) = token.kind else {
+                    unreachable!();
+                };
+                let operands = self.parse_operands()?;
+                Statement {
+                    span: token.span.start
+                        ..operands.last().map_or(token.span.end, |op| op.span.end),
+                    kind: StatementKind::Directive {
+                        name: Ident {
+                            name,
+                            span: token.span,
+                        },
+                        operands,
+                    },
+                }
+            }
+            _ => return Err(self.unexpected("an instruction, directive, or label")),
+        };
+
+        if !self.at_line_end() {
+            return Err(self.unexpected("`,` or the end of the line"));
+        }
+        statements.push(statement);
+        Ok(())
+    }
+
+    /// Parses the identifier under the cursor, which the caller has checked for
+    fn parse_ident(&mut self) -> Ident {
+        let token = self.bump();
+        let TokenKind::Ident(name) = token.kind else {
+            unreachable!();
+        };
+        Ident {
+            name,
+            span: token.span,
+        }
+    }
+
+    fn parse_operands(&mut self) -> Result<Vec<Operand>, Diagnostic> {
+        let mut operands = vec![];
+        if self.at_line_end() {
+            return Ok(operands);
+        }
+        operands.push(self.parse_operand()?);
+        while self.peek().kind == TokenKind::Comma {
+            self.bump();
+            operands.push(self.parse_operand()?);
+        }
+        Ok(operands)
+    }
+
+    fn parse_operand(&mut self) -> Result<Operand, Diagnostic> {
+        let start = self.peek().span.start;
+        let kind = match (&self.peek().kind, self.peek_kind_at(1)) {
+            (TokenKind::Ident(name), _) => match register(name) {
+                Some((group, index)) => OperandKind::Register { group, index },
+                None => return self.parse_expr_operand(),
+            },
+            (TokenKind::Float(val), _) => OperandKind::Float(*val),
+            (TokenKind::Minus, TokenKind::Float(val)) => {
+                let val = -val;
+                self.bump();
+                OperandKind::Float(val)
+            }
+            (TokenKind::Int(_) | TokenKind::Minus | TokenKind::Tilde | TokenKind::LParen, _) => {
+                return self.parse_expr_operand();
+            }
+            _ => return Err(self.unexpected("a register, number, or label")),
+        };
+        let end = self.bump().span.end;
+        Ok(Operand {
+            kind,
+            span: start..end,
+        })
+    }
+
+    fn parse_expr_operand(&mut self) -> Result<Operand, Diagnostic> {
+        let expr = self.parse_expr()?;
+        Ok(Operand {
+            span: expr.span.clone(),
+            kind: OperandKind::Expr(expr),
+        })
+    }
+
+    fn parse_expr(&mut self) -> Result<Expr, Diagnostic> {
+        self.parse_binary(0)
+    }
+
+    /// Parses operators binding at least as tightly as `min_precedence`, all
+    /// of which are left associative
+    fn parse_binary(&mut self, min_precedence: u8) -> Result<Expr, Diagnostic> {
+        let mut lhs = self.parse_unary()?;
+        while let Some((op, precedence)) = binary_op(&self.peek().kind) {
+            if precedence < min_precedence {
+                break;
+            }
+            self.bump();
+            let rhs = self.parse_binary(precedence + 1)?;
+            lhs = Expr {
+                span: lhs.span.start..rhs.span.end,
+                kind: ExprKind::Binary(op, Box::new(lhs), Box::new(rhs)),
+            };
+        }
+        Ok(lhs)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, Diagnostic> {
+        let op = match self.peek().kind {
+            TokenKind::Minus => UnaryOp::Neg,
+            TokenKind::Tilde => UnaryOp::Not,
+            _ => return self.parse_primary(),
+        };
+        let start = self.bump().span.start;
+        let operand = self.parse_unary()?;
+        let span = start..operand.span.end;
+        let kind = match (op, &operand.kind) {
+            // negative literals are kept as literals
+            (UnaryOp::Neg, ExprKind::Int(val)) => ExprKind::Int(-val),
+            _ => ExprKind::Unary(op, Box::new(operand)),
+        };
+        Ok(Expr { kind, span })
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, Diagnostic> {
+        let token = self.peek().clone();
+        let kind = match token.kind {
+            TokenKind::Int(val) => {
+                let Ok(val) = i64::try_from(val) else {
+                    return Err(self
+                        .file
+                        .error(&token.span, format!("Number {val} is too large")));
+                };
+                ExprKind::Int(val)
+            }
+            TokenKind::Ident(name) if register(&name).is_none() => ExprKind::Symbol(name),
+            TokenKind::LParen => {
+                self.bump();
+                let expr = self.parse_expr()?;
+                if self.peek().kind != TokenKind::RParen {
+                    return Err(self.unexpected("`)`"));
+                }
+                let end = self.bump().span.end;
+                return Ok(Expr {
+                    kind: expr.kind,
+                    span: token.span.start..end,
+                });
+            }
+            _ => return Err(self.unexpected("an integer or symbol")),
+        };
+        self.bump();
+        Ok(Expr {
+            kind,
+            span: token.span,
+        })
+    }
+}
+
+/// Returns the operator `kind` stands for between two operands, and its
+/// precedence
+fn binary_op(kind: &TokenKind) -> Option<(BinaryOp, u8)> {
+    let op = match kind {
+        TokenKind::Pipe => BinaryOp::Or,
+        TokenKind::Caret => BinaryOp::Xor,
+        TokenKind::Amp => BinaryOp::And,
+        TokenKind::Shl => BinaryOp::Shl,
+        TokenKind::Shr => BinaryOp::Shr,
+        TokenKind::Plus => BinaryOp::Add,
+        TokenKind::Minus => BinaryOp::Sub,
+        TokenKind::Star => BinaryOp::Mul,
+        TokenKind::Slash => BinaryOp::Div,
+        TokenKind::Percent => BinaryOp::Rem,
+        _ => return None,
+    };
+    let precedence = match op {
+        BinaryOp::Or => 0,
+        BinaryOp::Xor => 1,
+        BinaryOp::And => 2,
+        BinaryOp::Shl | BinaryOp::Shr => 3,
+        BinaryOp::Add | BinaryOp::Sub => 4,
+        BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => 5,
+    };
+    Some((op, precedence))
+}
+
+/// Returns the register named by `name`, e.g. `R3` or `f0`
+fn register(name: &str) -> Option<(RegisterGroup, usize)> {
+    let mut chars = name.chars();
+    let group = match chars.next()? {
+        'r' | 'R' => RegisterGroup::General,
+        'f' | 'F' => RegisterGroup::FloatingPoint,
+        _ => return None,
+    };
+    let index = chars.as_str();
+    if index.is_empty() || !index.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some((group, index.parse().unwrap_or(usize::MAX)))
+}
+
+/// Parses the tokens of `file` into statements, reporting any syntax errors
+/// in `diagnostics`. Parsing resumes on the next line after an error
+pub fn parse(
+    file: &SourceFile,
+    tokens: Vec<Token>,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Vec<Statement> {
+    Parser {
+        file,
+        tokens,
+        pos: 0,
+    }
+    .parse_file(diagnostics)
+}
+
+/// Returns whether `name` would be read as a register rather than a label
+pub fn is_register_name(name: &str) -> bool {
+    register(name).is_some()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ast::{
+        BinaryOp, Expr, ExprKind, OperandKind, RegisterGroup, Statement, StatementKind,
+    };
+    use crate::diagnostics::Diagnostic;
+    use crate::lexer::tokenize;
+    use crate::parser::parse;
+    use crate::source::{SourceFile, MAIN_FILE};
+
+    fn parse_text(text: &str) -> (Vec<Statement>, Vec<Diagnostic>) {
+        let file = SourceFile::new("test.gf", text);
+        let mut diagnostics = vec![];
+        let tokens = tokenize(&file, MAIN_FILE, &mut diagnostics);
+        let statements = parse(&file, tokens, &mut diagnostics);
+        (statements, diagnostics)
+    }
+
+    #[test]
+    fn labels_share_lines_with_instructions() {
+        let (statements, diagnostics) = parse_text("loop: addim r2, -4\n\nend: HALT");
+        assert!(diagnostics.is_empty());
+        assert_eq!(statements.len(), 4);
+        assert!(matches!(&statements[0].kind, StatementKind::Label(label) if label.name == "loop"));
+        let StatementKind::Instruction { mnemonic, operands } = &statements[1].kind else {
+            panic!("Expected an instruction, found {:?}", statements[1]);
+        };
+        assert_eq!(mnemonic.name, "addim");
+        assert_eq!(
+            operands[0].kind,
+            OperandKind::Register {
+                group: RegisterGroup::General,
+                index: 2
+            }
+        );
+        assert_eq!(
+            operands[1].kind,
+            OperandKind::Expr(Expr {
+                kind: ExprKind::Int(-4),
+                span: 16..18
+            })
+        );
+        assert_eq!(operands[1].span, 16..18);
+        assert_eq!(statements[1].span, 6..18);
+    }
+
+    #[test]
+    fn rejects_trailing_junk() {
+        let (statements, diagnostics) = parse_text("CMP32 R1, R2 R3\nJE , end\nHALT");
+        // both bad lines are reported, and parsing carries on after them
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(
+            diagnostics[0].message,
+            "Expected `,` or the end of the line, found `R3`"
+        );
+        assert_eq!(
+            diagnostics[1].message,
+            "Expected a register, number, or label, found `,`"
+        );
+        assert_eq!(statements.len(), 1);
+    }
+
+    #[test]
+    fn parses_expressions_by_precedence() {
+        let (statements, diagnostics) = parse_text("ADDIM R1, BASE + 4 * (N - 1) | 1");
+        assert!(diagnostics.is_empty());
+        let StatementKind::Instruction { operands, .. } = &statements[0].kind else {
+            panic!("Expected an instruction, found {:?}", statements[0]);
+        };
+        let OperandKind::Expr(expr) = &operands[1].kind else {
+            panic!("Expected an expression, found {:?}", operands[1]);
+        };
+        assert_eq!(expr.span, 10..32);
+        let ExprKind::Binary(BinaryOp::Or, sum, _) = &expr.kind else {
+            panic!("Expected `|` at the root, found {expr:?}");
+        };
+        let ExprKind::Binary(BinaryOp::Add, _, product) = &sum.kind else {
+            panic!("Expected `+` below `|`, found {sum:?}");
+        };
+        assert!(matches!(
+            product.kind,
+            ExprKind::Binary(BinaryOp::Mul, _, _)
+        ));
+        assert_eq!(product.span, 17..28);
+    }
+}
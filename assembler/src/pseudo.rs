@@ -0,0 +1,310 @@
+#![warn(clippy::all, clippy::pedantic)]
+
+use std::ops::{Range, RangeInclusive};
+
+use giggle_flop::instruction::instruction::{sign_extend_immediate, IMMEDIATE_FIELD_WIDTH};
+
+use crate::ast::{
+    Expr, ExprKind, Ident, Operand, OperandKind, RegisterGroup, Statement, StatementKind,
+};
+use crate::diagnostics::Diagnostic;
+use crate::source::SourceFile;
+
+/// Register `PUSH` and `POP` use as the stack pointer. R14 and R15 already
+/// hold the trap cause and return address
+pub const STACK_POINTER_REG: usize = 13;
+
+// `LI` builds values too wide for a single immediate by loading the high bits,
+// shifting them into place, then adding the low bits. The shift doubles the
+// register once per remaining bit, unless a scratch register is given to
+// multiply by the whole shift at once
+const LI_SHIFT: u32 = u32::BITS - IMMEDIATE_FIELD_WIDTH;
+const LI_LOW_BITS_MASK: u32 = (1 << LI_SHIFT) - 1;
+const IMMEDIATE_MASK: u32 = (1 << IMMEDIATE_FIELD_WIDTH) - 1;
+// Stack slots are a full word each
+const STACK_SLOT_WIDTH_BITS: u32 = 32;
+
+/// Mnemonics the assembler expands into one or more real instructions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pseudo {
+    Nop,  // does nothing
+    Clr,  // zeroes a register
+    Mov,  // copies one register into another
+    Li,   // loads any 32-bit value, optionally with a scratch register
+    Jmp,  // jumps whatever the flags are
+    Push, // stores a register at the top of the stack, which grows upward
+    Pop,  // loads the top of the stack into a register
+}
+
+impl Pseudo {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_uppercase().as_str() {
+            "NOP" => Some(Self::Nop),
+            "CLR" => Some(Self::Clr),
+            "MOV" => Some(Self::Mov),
+            "LI" => Some(Self::Li),
+            "JMP" => Some(Self::Jmp),
+            "PUSH" => Some(Self::Push),
+            "POP" => Some(Self::Pop),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Nop => "NOP",
+            Self::Clr => "CLR",
+            Self::Mov => "MOV",
+            Self::Li => "LI",
+            Self::Jmp => "JMP",
+            Self::Push => "PUSH",
+            Self::Pop => "POP",
+        }
+    }
+
+    fn usage(self) -> &'static str {
+        match self {
+            Self::Nop => "NOP",
+            Self::Clr => "CLR R<n>",
+            Self::Mov => "MOV R<dest>, R<src>",
+            Self::Li => "LI R<n>, <immediate>[, R<scratch>]",
+            Self::Jmp => "JMP <address>",
+            Self::Push => "PUSH R<n>",
+            Self::Pop => "POP R<n>",
+        }
+    }
+
+    fn operand_counts(self) -> RangeInclusive<usize> {
+        match self {
+            Self::Nop => 0..=0,
+            Self::Clr | Self::Jmp | Self::Push | Self::Pop => 1..=1,
+            Self::Mov => 2..=2,
+            Self::Li => 2..=3,
+        }
+    }
+
+    /// Returns how many instructions the pseudo-instruction expands to, given
+    /// its `operands`. `long` picks the form of `LI` used for values wider
+    /// than an immediate
+    pub fn instruction_count(self, operands: &[Operand], long: bool) -> usize {
+        match self {
+            Self::Nop | Self::Clr | Self::Mov => 1,
+            Self::Li if long && li_scratch(operands).is_some() => 6,
+            Self::Li if long => 3 + LI_SHIFT as usize,
+            Self::Li | Self::Jmp | Self::Push => 2,
+            Self::Pop => 4,
+        }
+    }
+}
+
+/// Returns whether `LI` needs its long form to load `value`, which is the
+/// case unless `value` survives `ADDIM` sign-extending it
+pub fn li_needs_long_form(value: u32) -> bool {
+    sign_extend_immediate(value & IMMEDIATE_MASK) != value
+}
+
+/// Returns the value `ADDIM` adds for the low bits of `value`, as the signed
+/// immediate it has to be written as
+#[allow(clippy::cast_possible_wrap)]
+fn addim_value(value: u32) -> i32 {
+    sign_extend_immediate(value & IMMEDIATE_MASK) as i32
+}
+
+/// Returns the scratch register `LI` was given, if any
+fn li_scratch(operands: &[Operand]) -> Option<&Operand> {
+    operands.get(2)
+}
+
+/// Splits a value loaded by the long form of `LI` into the bits its first
+/// `ADDIM` loads and the bits its last one adds
+pub fn li_parts(value: u32) -> (u32, u32) {
+    (value >> LI_SHIFT, value & LI_LOW_BITS_MASK)
+}
+
+/// Checks that a pseudo-instruction was given the right number of operands,
+/// and that `LI`'s scratch register can be written over
+pub fn check_operands(
+    file: &SourceFile,
+    statement: &Statement,
+    pseudo: Pseudo,
+    operands: &[Operand],
+) -> Result<(), Diagnostic> {
+    let counts = pseudo.operand_counts();
+    if !counts.contains(&operands.len()) {
+        let expected = if counts.start() == counts.end() {
+            counts.start().to_string()
+        } else {
+            format!("{} or {}", counts.start(), counts.end())
+        };
+        return Err(file.error(
+            &statement.span,
+            format!(
+                "{} takes {expected} operand(s) but {} were given. Usage: {}",
+                pseudo.name(),
+                operands.len(),
+                pseudo.usage()
+            ),
+        ));
+    }
+
+    if let (Pseudo::Li, Some(scratch)) = (pseudo, li_scratch(operands)) {
+        let OperandKind::Register {
+            group: RegisterGroup::General,
+            ..
+        } = scratch.kind
+        else {
+            return Err(file.error(
+                &scratch.span,
+                format!(
+                    "Expected a general purpose register to use as scratch, found {}",
+                    scratch.kind
+                ),
+            ));
+        };
+        if scratch.kind == operands[0].kind {
+            return Err(file.error(
+                &scratch.span,
+                "LI's scratch register has to differ from the register it loads".to_string(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn register(index: usize, span: &Range<usize>) -> Operand {
+    Operand {
+        kind: OperandKind::Register {
+            group: RegisterGroup::General,
+            index,
+        },
+        span: span.clone(),
+    }
+}
+
+fn int(val: impl Into<i64>, span: &Range<usize>) -> Operand {
+    Operand {
+        kind: OperandKind::Expr(Expr {
+            kind: ExprKind::Int(val.into()),
+            span: span.clone(),
+        }),
+        span: span.clone(),
+    }
+}
+
+/// Expands a pseudo-instruction whose operands have been checked with
+/// [`check_operands`] into real instructions. Each keeps the span of the
+/// pseudo-instruction, so problems with its operands are reported against
+/// what was written. `li_value` is the evaluated value `LI` loads
+pub fn expand(
+    file: &SourceFile,
+    statement: &Statement,
+    pseudo: Pseudo,
+    mnemonic: &Ident,
+    operands: &[Operand],
+    li_value: Option<u32>,
+    long: bool,
+) -> Result<Vec<Statement>, Diagnostic> {
+    let instr = |name: &str, operands: Vec<Operand>| Statement {
+        kind: StatementKind::Instruction {
+            mnemonic: Ident {
+                name: name.to_string(),
+                span: mnemonic.span.clone(),
+            },
+            operands,
+        },
+        span: statement.span.clone(),
+    };
+    let sp = register(STACK_POINTER_REG, &mnemonic.span);
+
+    let expanded = match pseudo {
+        Pseudo::Nop => {
+            let r0 = register(0, &mnemonic.span);
+            vec![instr("ORI", vec![r0.clone(), r0.clone(), r0])]
+        }
+        Pseudo::Clr => {
+            let reg = &operands[0];
+            vec![instr("XORI", vec![reg.clone(), reg.clone(), reg.clone()])]
+        }
+        Pseudo::Mov => {
+            let (dest, src) = (&operands[0], &operands[1]);
+            vec![instr("ORI", vec![dest.clone(), src.clone(), src.clone()])]
+        }
+        Pseudo::Li => {
+            let (reg, span) = (&operands[0], &operands[1].span);
+            let value = li_value.unwrap_or_default();
+            let mut expanded = vec![instr("XORI", vec![reg.clone(), reg.clone(), reg.clone()])];
+            if long {
+                let (high, low) = li_parts(value);
+                // the bits sign-extending `high` sets are shifted back out
+                expanded.push(instr(
+                    "ADDIM",
+                    vec![reg.clone(), int(addim_value(high), span)],
+                ));
+                if let Some(scratch) = li_scratch(operands) {
+                    expanded.push(instr(
+                        "XORI",
+                        vec![scratch.clone(), scratch.clone(), scratch.clone()],
+                    ));
+                    expanded.push(instr(
+                        "ADDIM",
+                        vec![scratch.clone(), int(1 << LI_SHIFT, span)],
+                    ));
+                    expanded.push(instr(
+                        "MULU",
+                        vec![reg.clone(), reg.clone(), scratch.clone()],
+                    ));
+                } else {
+                    for _ in 0..LI_SHIFT {
+                        expanded.push(instr("ADDU", vec![reg.clone(), reg.clone(), reg.clone()]));
+                    }
+                }
+                expanded.push(instr("ADDIM", vec![reg.clone(), int(low, span)]));
+            } else {
+                expanded.push(instr(
+                    "ADDIM",
+                    vec![reg.clone(), int(addim_value(value), span)],
+                ));
+            }
+            expanded
+        }
+        // one of the two is always taken
+        Pseudo::Jmp => vec![
+            instr("JE", operands.to_vec()),
+            instr("JNE", operands.to_vec()),
+        ],
+        Pseudo::Push => vec![
+            instr("STIN32", vec![operands[0].clone(), sp.clone()]),
+            instr(
+                "ADDIM",
+                vec![sp, int(STACK_SLOT_WIDTH_BITS, &mnemonic.span)],
+            ),
+        ],
+        Pseudo::Pop => {
+            let reg = &operands[0];
+            if let OperandKind::Register {
+                group: RegisterGroup::General,
+                index: STACK_POINTER_REG,
+            } = reg.kind
+            {
+                return Err(file.error(
+                    &reg.span,
+                    format!("Can't POP into the stack pointer R{STACK_POINTER_REG}"),
+                ));
+            }
+            // the popped register holds the slot size while the stack
+            // pointer steps back, since there's no register to spare
+            vec![
+                instr("XORI", vec![reg.clone(), reg.clone(), reg.clone()]),
+                instr(
+                    "ADDIM",
+                    vec![reg.clone(), int(STACK_SLOT_WIDTH_BITS, &mnemonic.span)],
+                ),
+                instr("SUBU", vec![sp.clone(), sp.clone(), reg.clone()]),
+                instr("LDIN32", vec![reg.clone(), sp]),
+            ]
+        }
+    };
+
+    debug_assert_eq!(expanded.len(), pseudo.instruction_count(operands, long));
+    Ok(expanded)
+}
@@ -0,0 +1,332 @@
+#![warn(clippy::all, clippy::pedantic)]
+
+use std::ops::Range;
+
+use crate::diagnostics::{Diagnostic, Severity, Span};
+
+/// One file read in while assembling, either the one being assembled or one
+/// it includes
+struct File {
+    name: String,
+    text: String,
+    start: usize,               // offset of the file's first byte
+    line_starts: Vec<usize>,    // byte offset of the start of each line, within the file
+    included_at: Option<usize>, // offset of the `.INCLUDE` that read it in
+}
+
+impl File {
+    /// Returns the index of the line containing byte `offset` of the file
+    fn line_idx(&self, offset: usize) -> usize {
+        self.line_starts.partition_point(|start| *start <= offset) - 1
+    }
+
+    /// Returns the text of the line at `line_idx`, without its line ending
+    fn line_text(&self, line_idx: usize) -> &str {
+        let start = self.line_starts[line_idx];
+        let end = self
+            .line_starts
+            .get(line_idx + 1)
+            .map_or(self.text.len(), |next| next - 1);
+        self.text[start..end].trim_end_matches('\r')
+    }
+
+    /// Returns the byte range of the line containing byte `offset` of the file
+    fn line_range(&self, offset: usize) -> Range<usize> {
+        let line_idx = self.line_idx(offset);
+        let start = self.line_starts[line_idx];
+        start..start + self.line_text(line_idx).len()
+    }
+}
+
+/// Copy of part of a file made by expanding a macro. Tokens in the copy get
+/// offsets of their own, so each expansion can be told apart while still
+/// mapping back to the lines it came from
+struct Expansion {
+    start: usize,         // first offset given to the copy
+    source: Range<usize>, // what was copied
+    macro_name: String,   // macro that was expanded
+    site: Range<usize>,   // invocation that expanded it, possibly itself in an expansion
+}
+
+/// Source text being assembled, along with any files it includes, able to
+/// turn byte ranges within it into diagnostics. Every file and expansion gets
+/// its own range of offsets, so a token's span alone says where it came from
+pub struct SourceFile {
+    files: Vec<File>,
+    expansions: Vec<Expansion>,
+    end: usize, // offset just past the last file or expansion added
+}
+
+/// Identifies a file added to a [`SourceFile`]
+pub type FileId = usize;
+
+/// The file being assembled, added when the [`SourceFile`] is created
+pub const MAIN_FILE: FileId = 0;
+
+impl SourceFile {
+    pub fn new(name: &str, text: &str) -> Self {
+        let mut file = Self {
+            files: vec![],
+            expansions: vec![],
+            end: 0,
+        };
+        file.add_file(name, text.to_string());
+        file
+    }
+
+    /// Adds the text of the file `name`, giving it offsets of its own
+    pub fn add_file(&mut self, name: &str, text: String) -> FileId {
+        let start = self.next_start();
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(idx, _)| idx + 1))
+            .collect();
+        self.end = start + text.len();
+        self.files.push(File {
+            name: name.to_string(),
+            text,
+            start,
+            line_starts,
+            included_at: None,
+        });
+        self.files.len() - 1
+    }
+
+    /// Like [`SourceFile::add_file`], for a file read in by the `.INCLUDE`
+    /// at offset `site`
+    pub fn add_include(&mut self, name: &str, text: String, site: usize) -> FileId {
+        let id = self.add_file(name, text);
+        self.files[id].included_at = Some(site);
+        id
+    }
+
+    /// Returns the number of lines in the file `id`, not counting the empty
+    /// one after a trailing newline
+    pub fn line_count(&self, id: FileId) -> usize {
+        let file = &self.files[id];
+        file.line_starts.len() - usize::from(file.text.ends_with('\n'))
+    }
+
+    /// Returns the text of line `line_idx` of the file `id`
+    pub fn line_text(&self, id: FileId, line_idx: usize) -> &str {
+        self.files[id].line_text(line_idx)
+    }
+
+    /// Returns the files included from line `line_idx` of the file `id`
+    pub fn includes(&self, id: FileId, line_idx: usize) -> impl Iterator<Item = FileId> + '_ {
+        (0..self.files.len()).filter(move |included| {
+            self.files[*included]
+                .included_at
+                .is_some_and(|site| self.origin(site) == (id, line_idx))
+        })
+    }
+
+    /// Returns the file and index of the line that `offset` came from. Text
+    /// from a macro expansion comes from the line that invoked the macro
+    pub fn origin(&self, offset: usize) -> (FileId, usize) {
+        let mut offset = offset;
+        while let Some(expansion) = self.expansion(offset) {
+            offset = expansion.site.start;
+        }
+        let (id, range) = self.locate(&(offset..offset));
+        (id, self.files[id].line_idx(range.start))
+    }
+
+    pub fn text(&self, id: FileId) -> &str {
+        &self.files[id].text
+    }
+
+    /// Returns the offset of the first byte of the file `id`
+    pub fn start(&self, id: FileId) -> usize {
+        self.files[id].start
+    }
+
+    pub fn name(&self, id: FileId) -> &str {
+        &self.files[id].name
+    }
+
+    /// Returns the file the text at `offset` was written in, looking through
+    /// any macro expansions
+    pub fn file_at(&self, offset: usize) -> FileId {
+        self.locate(&(offset..offset)).0
+    }
+
+    /// Returns where the file `name` comes in the order files were added,
+    /// for sorting diagnostics that name it
+    pub fn order(&self, name: &str) -> usize {
+        self.files
+            .iter()
+            .position(|file| file.name == name)
+            .unwrap_or(self.files.len())
+    }
+
+    fn next_start(&self) -> usize {
+        // keep a gap so the end of one region isn't the start of the next
+        if self.files.is_empty() {
+            0
+        } else {
+            self.end + 1
+        }
+    }
+
+    /// Records that `source` is being copied by expanding `macro_name` at
+    /// `site`, returning the offset to shift the copy's tokens to
+    pub fn add_expansion(
+        &mut self,
+        source: Range<usize>,
+        macro_name: &str,
+        site: Range<usize>,
+    ) -> usize {
+        let start = self.next_start();
+        self.end = start + source.len();
+        self.expansions.push(Expansion {
+            start,
+            source,
+            macro_name: macro_name.to_string(),
+            site,
+        });
+        start
+    }
+
+    /// Returns the expansion `offset` falls within, if it isn't in a file
+    fn expansion(&self, offset: usize) -> Option<&Expansion> {
+        let expansion_idx = self
+            .expansions
+            .partition_point(|expansion| expansion.start <= offset);
+        let expansion = self.expansions[..expansion_idx].last()?;
+        let file_idx = self.files.partition_point(|file| file.start <= offset);
+        let in_file = self.files[..file_idx]
+            .last()
+            .is_some_and(|file| file.start > expansion.start);
+        (!in_file).then_some(expansion)
+    }
+
+    /// Maps `range` onto the file it was written in, returning the file and
+    /// the range within it. The range is cut off at the end of the line it
+    /// starts on if it runs into another file or expansion
+    fn locate(&self, range: &Range<usize>) -> (FileId, Range<usize>) {
+        let expansion = self.expansion(range.start);
+        let map = |offset: usize| {
+            expansion.map_or(offset, |expansion| {
+                expansion.source.start + (offset - expansion.start)
+            })
+        };
+        let start = map(range.start);
+        let id = self.files.partition_point(|file| file.start <= start) - 1;
+        let file = &self.files[id];
+
+        let same_expansion =
+            self.expansion(range.end).map(|e| e.start) == expansion.map(|e| e.start);
+        let end = if same_expansion && map(range.end) <= file.start + file.text.len() {
+            map(range.end) - file.start
+        } else {
+            usize::MAX
+        };
+        let start = start - file.start;
+        (id, start..end.max(start))
+    }
+
+    /// Returns the span of `range`, cut off at the end of the line it starts on
+    pub fn span(&self, range: &Range<usize>) -> Span {
+        let (id, range) = self.locate(range);
+        let file = &self.files[id];
+        let line = file.line_range(range.start);
+        let column = |offset: usize| {
+            file.text[line.start..offset.clamp(line.start, line.end)]
+                .chars()
+                .count()
+                + 1
+        };
+        Span {
+            line: file.line_idx(range.start) + 1,
+            column_start: column(range.start),
+            column_end: column(range.end),
+        }
+    }
+
+    /// Returns the text `range` covers, cut off at the end of the line it
+    /// starts on
+    pub fn snippet(&self, range: &Range<usize>) -> &str {
+        let (id, range) = self.locate(range);
+        let file = &self.files[id];
+        let line = file.line_range(range.start);
+        &file.text[range.start.clamp(line.start, line.end)..range.end.clamp(line.start, line.end)]
+    }
+
+    pub fn diagnostic(
+        &self,
+        severity: Severity,
+        range: &Range<usize>,
+        message: String,
+    ) -> Diagnostic {
+        let span = self.span(range);
+        let file = &self.files[self.file_at(range.start)];
+        // point out every macro invocation that led here, innermost first
+        let note = self.expansion(range.start).map(|expansion| {
+            Box::new(self.diagnostic(
+                Severity::Note,
+                &expansion.site,
+                format!("In this expansion of macro {}", expansion.macro_name),
+            ))
+        });
+        Diagnostic {
+            severity,
+            message,
+            file: file.name.clone(),
+            span: Some(span),
+            source_line: Some(file.line_text(span.line - 1).to_string()),
+            note,
+        }
+    }
+
+    pub fn error(&self, range: &Range<usize>, message: String) -> Diagnostic {
+        self.diagnostic(Severity::Error, range, message)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::diagnostics::Span;
+    use crate::source::SourceFile;
+
+    #[test]
+    fn locates_spans() {
+        let file = SourceFile::new("test.gf", "HALT\r\n  JE end\n");
+        let diagnostic = file.error(&(11..14), "Undefined label end".to_string());
+        assert_eq!(
+            diagnostic.span,
+            Some(Span {
+                line: 2,
+                column_start: 6,
+                column_end: 9
+            })
+        );
+        assert_eq!(diagnostic.source_line.as_deref(), Some("  JE end"));
+        // ranges running past the end of a line stop at it
+        assert_eq!(file.span(&(2..9)).column_end, 5);
+    }
+
+    #[test]
+    fn maps_expansions_to_their_source() {
+        let mut file = SourceFile::new("test.gf", "  ADDIM R1, 1\nINC\n");
+        let start = file.add_expansion(2..13, "INC", 14..17);
+        let diagnostic = file.error(&(start + 6..start + 8), "Bad register".to_string());
+        assert_eq!(diagnostic.span.unwrap().line, 1);
+        assert_eq!(diagnostic.span.unwrap().column_start, 9);
+        assert_eq!(file.snippet(&(start..start + 11)), "ADDIM R1, 1");
+        let note = diagnostic.note.unwrap();
+        assert_eq!(note.span.unwrap().line, 2);
+        assert_eq!(note.message, "In this expansion of macro INC");
+    }
+
+    #[test]
+    fn maps_included_files_to_their_names() {
+        let mut file = SourceFile::new("main.gf", ".include \"lib.gf\"\nHALT\n");
+        let id = file.add_file("lib.gf", "ret: RET\n".to_string());
+        let start = file.start(id);
+        let diagnostic = file.error(&(start + 5..start + 8), "Bad".to_string());
+        assert_eq!(diagnostic.file, "lib.gf");
+        assert_eq!(diagnostic.span.unwrap().column_start, 6);
+        assert_eq!(file.file_at(start + 1), id);
+        assert_eq!(file.name(file.file_at(18)), "main.gf");
+    }
+}
@@ -0,0 +1,270 @@
+#![warn(clippy::all, clippy::pedantic)]
+
+use std::io::{Read, Write};
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use clap::Parser;
+
+use giggle_flop::config::config::MachineConfig;
+use giggle_flop::memory::memory_system::{ADDRESS_SPACE_SIZE, MEM_BLOCK_WIDTH};
+use giggle_flop::system::branch_predictor::PredictorKind;
+use giggle_flop::system::system::{System, SystemMessage};
+use giggle_flop::system::trap::Trap;
+
+const DEFAULT_MAX_CYCLES: usize = 10_000_000;
+
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Parser, Debug)]
+#[command(version, about = "Runs a Giggle-Flop program without the GUI")]
+struct HeadlessArgs {
+    #[arg(help = "Path to the assembled program to run")]
+    program: PathBuf,
+    #[arg(
+        long,
+        short = 'c',
+        default_value_t = DEFAULT_MAX_CYCLES,
+        help = "Maximum number of clock cycles to run before giving up"
+    )]
+    max_cycles: usize,
+    #[arg(
+        long,
+        help = "TOML or JSON file describing the memory hierarchy and pipeline"
+    )]
+    config: Option<PathBuf>,
+    #[arg(long, help = "Run without the pipeline, overriding the config")]
+    no_pipeline: bool,
+    #[arg(
+        long,
+        conflicts_with = "no_forwarding",
+        help = "Forward results between pipeline stages, overriding the config"
+    )]
+    forwarding: bool,
+    #[arg(
+        long,
+        help = "Stall on data hazards until writeback, overriding the config"
+    )]
+    no_forwarding: bool,
+    #[arg(
+        long,
+        help = "Branch predictor to use (not-taken, backward-taken, one-bit, or two-bit), overriding the config"
+    )]
+    predictor: Option<PredictorKind>,
+    #[arg(
+        long,
+        short,
+        value_parser = parse_mem_range,
+        help = "Memory range to print after execution, as START:END (bit addresses, END exclusive). May be repeated"
+    )]
+    dump: Vec<(usize, usize)>,
+    #[arg(
+        long,
+        help = "File to fill the console's input buffer from, or - for stdin"
+    )]
+    input: Option<PathBuf>,
+    #[arg(long, short, help = "Only print the number of clock cycles taken")]
+    quiet: bool,
+}
+
+fn parse_address(addr: &str) -> Result<usize> {
+    let addr = addr.trim();
+    let parsed = if let Some(hex) = addr.strip_prefix("0x").or_else(|| addr.strip_prefix("0X")) {
+        usize::from_str_radix(hex, 16)
+    } else {
+        addr.parse::<usize>()
+    };
+
+    parsed.map_err(|e| anyhow!("Invalid address {addr}: {e}"))
+}
+
+fn parse_mem_range(range: &str) -> Result<(usize, usize)> {
+    let Some((start, end)) = range.split_once(':') else {
+        return Err(anyhow!(
+            "Expected a range of the form START:END, got {range}"
+        ));
+    };
+    let start = parse_address(start)?;
+    let end = parse_address(end)?;
+    if !start.is_multiple_of(MEM_BLOCK_WIDTH) {
+        return Err(anyhow!(
+            "Range start {start} is not aligned to a {MEM_BLOCK_WIDTH}-bit word"
+        ));
+    }
+    if end <= start {
+        return Err(anyhow!(
+            "Range end {end} doesn't come after range start {start}"
+        ));
+    }
+    if end > ADDRESS_SPACE_SIZE {
+        return Err(anyhow!(
+            "Range end {end} is past the end of the {ADDRESS_SPACE_SIZE}-bit address space"
+        ));
+    }
+
+    Ok((start, end))
+}
+
+fn print_mem_range(system: &System, start: usize, end: usize) {
+    println!("Memory [0x{start:08X}, 0x{end:08X}):");
+    for addr in (start..end).step_by(MEM_BLOCK_WIDTH) {
+        if let Some(block) = system.memory_system.force_load(addr) {
+            println!("0x{addr:08X}: {block}");
+        } else {
+            println!("0x{addr:08X}: <<Out of range>>");
+        }
+    }
+}
+
+/// How a run came to an end
+enum Outcome {
+    Halted,
+    Faulted(Trap),
+    CycleLimit,
+}
+
+fn run(args: &HeadlessArgs) -> Result<Outcome> {
+    if !args.program.is_file() {
+        return Err(anyhow!(
+            "Program file {} does not exist",
+            args.program.display()
+        ));
+    }
+
+    let mut config = match &args.config {
+        Some(path) => MachineConfig::from_file(path)?,
+        None => MachineConfig::default(),
+    };
+    if args.no_pipeline {
+        config.pipeline = false;
+    }
+    if args.forwarding {
+        config.forwarding = true;
+    } else if args.no_forwarding {
+        config.forwarding = false;
+    }
+    if let Some(kind) = args.predictor {
+        config.branch_predictor.kind = kind;
+    }
+
+    let mut system = System::new(config)?;
+    system.load_program(&args.program)?;
+    if let Some(path) = &args.input {
+        let input = if path.as_os_str() == "-" {
+            let mut input = Vec::new();
+            std::io::stdin().read_to_end(&mut input)?;
+            input
+        } else {
+            std::fs::read(path)
+                .map_err(|e| anyhow!("Failed to read input file {}: {e}", path.display()))?
+        };
+        system.memory_system.console_mut().push_input(&input);
+    }
+
+    let mut outcome = Outcome::CycleLimit;
+    let mut printed = 0;
+    while system.clock < args.max_cycles {
+        let msg = system.step();
+        // stream the program's console output as it's produced
+        let output = system.memory_system.console().output();
+        if output.len() > printed {
+            print!("{}", &output[printed..]);
+            std::io::stdout().flush()?;
+            printed = output.len();
+        }
+        match msg {
+            SystemMessage::Halt => {
+                outcome = Outcome::Halted;
+                break;
+            }
+            SystemMessage::Exception(trap) if trap.handler.is_none() => {
+                outcome = Outcome::Faulted(trap);
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    // keep the run summary off the last line of program output
+    if !system.memory_system.console().output().is_empty()
+        && !system.memory_system.console().output().ends_with('\n')
+    {
+        println!();
+    }
+
+    if args.quiet {
+        println!("{}", system.clock);
+        return Ok(outcome);
+    }
+
+    match outcome {
+        Outcome::Halted => println!("Halted after {} clock cycles", system.clock),
+        Outcome::Faulted(trap) => println!(
+            "Stopped on an unhandled exception after {} clock cycles: {trap}",
+            system.clock
+        ),
+        Outcome::CycleLimit => println!(
+            "Cycle limit reached, stopped after {} clock cycles",
+            system.clock
+        ),
+    }
+    println!("\nRegisters:\n{}", system.registers);
+    for &(start, end) in &args.dump {
+        print_mem_range(&system, start, end);
+    }
+    println!("\nPerformance Report:\n{}", system.report());
+
+    Ok(outcome)
+}
+
+fn main() {
+    let args = HeadlessArgs::parse();
+
+    match run(&args) {
+        Ok(Outcome::Halted) => {}
+        Ok(Outcome::CycleLimit) => std::process::exit(2),
+        Ok(Outcome::Faulted(_)) => std::process::exit(3),
+        Err(e) => {
+            eprintln!("Error: {e:#}");
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use giggle_flop::memory::memory_system::ADDRESS_SPACE_SIZE;
+
+    use crate::{parse_address, parse_mem_range};
+
+    #[test]
+    fn parses_addresses() {
+        assert_eq!(parse_address("0x40").unwrap(), 64);
+        assert_eq!(parse_address("0X1F").unwrap(), 31);
+        assert_eq!(parse_address(" 128 ").unwrap(), 128);
+        assert!(parse_address("0x").is_err());
+        assert!(parse_address("-32").is_err());
+        assert!(parse_address("0x1_0000_0000_0000_0000").is_err());
+    }
+
+    #[test]
+    fn parses_mem_ranges() {
+        assert_eq!(parse_mem_range("0:64").unwrap(), (0, 64));
+        assert_eq!(parse_mem_range("0x20:0x40").unwrap(), (32, 64));
+        let whole = format!("0:{ADDRESS_SPACE_SIZE}");
+        assert_eq!(parse_mem_range(&whole).unwrap(), (0, ADDRESS_SPACE_SIZE));
+
+        // empty, backwards, unaligned, and missing the separator
+        assert!(parse_mem_range("64:64").is_err());
+        assert!(parse_mem_range("64:32").is_err());
+        assert!(parse_mem_range("16:64").is_err());
+        assert!(parse_mem_range("0x40").is_err());
+        assert!(parse_mem_range("0-64").is_err());
+
+        let past_end = format!("0:{}", ADDRESS_SPACE_SIZE + 1);
+        assert!(parse_mem_range(&past_end)
+            .unwrap_err()
+            .to_string()
+            .contains("address space"));
+        assert!(parse_mem_range("0:0xFFFFFFFFFFFFFFFFF").is_err());
+    }
+}
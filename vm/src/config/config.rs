@@ -0,0 +1,457 @@
+#![warn(clippy::all, clippy::pedantic)]
+
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::memory::console::CONSOLE_BASE;
+use crate::memory::memory_system::{ReplacementPolicy, WritePolicy, MEM_BLOCK_WIDTH};
+use crate::system::branch_predictor::PredictorKind;
+use crate::system::system::Cycle;
+
+/// Description of a single level of the memory hierarchy
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LevelConfig {
+    pub capacity: usize, // number of cache lines
+    pub latency: Cycle,
+    #[serde(default = "LevelConfig::default_associativity")]
+    pub associativity: usize, // number of ways per set, 1 being direct mapped
+    #[serde(default)]
+    pub replacement: ReplacementPolicy,
+    #[serde(default)]
+    pub write_policy: WritePolicy,
+}
+
+impl LevelConfig {
+    /// Creates a direct mapped level with `capacity` lines and a `latency` cycle
+    /// access time
+    #[must_use]
+    pub fn new(capacity: usize, latency: Cycle) -> Self {
+        Self {
+            capacity,
+            latency,
+            associativity: Self::default_associativity(),
+            replacement: ReplacementPolicy::default(),
+            write_policy: WritePolicy::default(),
+        }
+    }
+
+    fn default_associativity() -> usize {
+        1
+    }
+
+    /// Checks the level's geometry, naming it `name` in any error
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the level has no lines, or its associativity doesn't
+    /// evenly divide them
+    pub fn validate(&self, name: &str) -> Result<()> {
+        if self.capacity == 0 {
+            return Err(anyhow!("{name} has a capacity of 0 lines"));
+        }
+        if self.associativity == 0 || !self.capacity.is_multiple_of(self.associativity) {
+            return Err(anyhow!(
+                "{name}: associativity {} must evenly divide its {} lines",
+                self.associativity,
+                self.capacity
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Description of the fetch stage's branch predictor
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PredictorConfig {
+    pub kind: PredictorKind,
+    pub btb_entries: usize, // only used by the 1-bit and 2-bit predictors
+}
+
+impl Default for PredictorConfig {
+    fn default() -> Self {
+        Self {
+            kind: PredictorKind::default(),
+            btb_entries: 16,
+        }
+    }
+}
+
+/// Description of the simulated machine, loadable from a TOML or JSON file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MachineConfig {
+    pub line_len: usize, // number of MEM_BLOCK_WIDTH-bit words in a cache line
+    pub levels: Vec<LevelConfig>, // ordered from L1 to main memory
+    // separate L1 instruction cache used by fetches in place of level 0, which
+    // then only serves data. Levels 1 and below are shared
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub icache: Option<LevelConfig>,
+    pub pipeline: bool,
+    // forward execute and memory stage results to dependent instructions
+    // rather than stalling them until writeback
+    pub forwarding: bool,
+    pub branch_predictor: PredictorConfig,
+    pub start_pc: u32,
+    // address exceptions are vectored to, the system stops on them if unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trap_handler: Option<u32>,
+}
+
+impl Default for MachineConfig {
+    fn default() -> Self {
+        Self {
+            line_len: 4,
+            levels: vec![
+                LevelConfig::new(64, 1),
+                LevelConfig::new(64, 10),
+                LevelConfig::new(256, 100),
+            ],
+            icache: None,
+            pipeline: true,
+            forwarding: false,
+            branch_predictor: PredictorConfig::default(),
+            start_pc: 0,
+            trap_handler: None,
+        }
+    }
+}
+
+impl MachineConfig {
+    /// Reads and validates a machine description from `path`. Files with a
+    /// `.json` extension are parsed as JSON, everything else as TOML
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can't be read, parsed, or validated
+    pub fn from_file(path: &Path) -> Result<Self> {
+        info!("Loading machine config {}", path.display());
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+
+        let is_json = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+        let config = if is_json {
+            Self::from_json(&contents)
+        } else {
+            Self::from_toml(&contents)
+        };
+
+        config.with_context(|| format!("Invalid config file {}", path.display()))
+    }
+
+    /// Parses and validates a TOML machine description
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `contents` isn't valid TOML describing a machine, or
+    /// fails [`MachineConfig::validate`]
+    pub fn from_toml(contents: &str) -> Result<Self> {
+        let config: Self = toml::from_str(contents)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Parses and validates a JSON machine description
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `contents` isn't valid JSON describing a machine, or
+    /// fails [`MachineConfig::validate`]
+    pub fn from_json(contents: &str) -> Result<Self> {
+        let config: Self = serde_json::from_str(contents)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Checks the description for values the simulator can't be built with
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming the first problem found, e.g. an empty memory
+    /// hierarchy, a level with an invalid geometry, or a start PC outside of
+    /// main memory
+    pub fn validate(&self) -> Result<()> {
+        if self.line_len == 0 {
+            return Err(anyhow!("line_len must be at least 1 word"));
+        }
+        let Some(main_level) = self.levels.last() else {
+            return Err(anyhow!(
+                "At least one memory level (main memory) must be specified"
+            ));
+        };
+        for (i, level) in self.levels.iter().enumerate() {
+            level.validate(&format!("Memory level {i}"))?;
+        }
+        if main_level.associativity != 1 {
+            return Err(anyhow!(
+                "Main memory (level {}) must have an associativity of 1",
+                self.levels.len() - 1
+            ));
+        }
+        if main_level.write_policy == WritePolicy::WriteBack {
+            return Err(anyhow!(
+                "Main memory (level {}) can't use a write-back policy",
+                self.levels.len() - 1
+            ));
+        }
+
+        if let Some(icache) = &self.icache {
+            icache.validate("I-cache")?;
+            if self.levels.len() < 2 {
+                return Err(anyhow!(
+                    "A split I-cache needs a data cache (level 0) in front of main memory"
+                ));
+            }
+            if icache.write_policy == WritePolicy::WriteBack {
+                return Err(anyhow!(
+                    "The I-cache is never written to, and can't use a write-back policy"
+                ));
+            }
+        }
+
+        if self.branch_predictor.kind.uses_btb() && self.branch_predictor.btb_entries == 0 {
+            return Err(anyhow!(
+                "The {} branch predictor needs at least one BTB entry",
+                self.branch_predictor.kind
+            ));
+        }
+
+        let Some(main_capacity) = main_level
+            .capacity
+            .checked_mul(self.line_len)
+            .and_then(|words| words.checked_mul(MEM_BLOCK_WIDTH))
+        else {
+            return Err(anyhow!(
+                "Main memory ({} lines of {} words) is larger than the address space",
+                main_level.capacity,
+                self.line_len
+            ));
+        };
+        if main_capacity > CONSOLE_BASE {
+            return Err(anyhow!(
+                "Main memory ({main_capacity} bits) overlaps the console registers at 0x{CONSOLE_BASE:08X}"
+            ));
+        }
+        check_code_address("start_pc", self.start_pc, main_capacity)?;
+        if let Some(handler) = self.trap_handler {
+            check_code_address("trap_handler", handler, main_capacity)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Checks that `addr`, named `name` in any error, can hold an instruction
+fn check_code_address(name: &str, addr: u32, main_capacity: usize) -> Result<()> {
+    let addr = addr as usize;
+    if !addr.is_multiple_of(MEM_BLOCK_WIDTH) {
+        return Err(anyhow!(
+            "{name} 0x{addr:08X} is not aligned to a {MEM_BLOCK_WIDTH}-bit word"
+        ));
+    }
+    if addr >= main_capacity {
+        return Err(anyhow!(
+            "{name} 0x{addr:08X} lies outside of main memory (capacity: {main_capacity} bits)"
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::config::config::{LevelConfig, MachineConfig};
+    use crate::memory::memory_system::{ReplacementPolicy, WritePolicy};
+    use crate::system::branch_predictor::PredictorKind;
+
+    #[test]
+    fn parses_toml() {
+        let config = MachineConfig::from_toml(
+            r"
+            line_len = 2
+            pipeline = false
+            forwarding = true
+            start_pc = 64
+
+            [[levels]]
+            capacity = 8
+            latency = 1
+
+            [[levels]]
+            capacity = 128
+            latency = 50
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(config.line_len, 2);
+        assert!(!config.pipeline);
+        assert!(config.forwarding);
+        assert_eq!(config.start_pc, 64);
+        assert_eq!(config.trap_handler, None);
+        assert_eq!(
+            config.levels,
+            vec![LevelConfig::new(8, 1), LevelConfig::new(128, 50)]
+        );
+    }
+
+    #[test]
+    fn parses_associativity() {
+        let config = MachineConfig::from_toml(
+            r#"
+            levels = [
+                { capacity = 64, latency = 1, associativity = 4, replacement = "fifo" },
+                { capacity = 256, latency = 100 },
+            ]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.levels[0].associativity, 4);
+        assert_eq!(config.levels[0].replacement, ReplacementPolicy::Fifo);
+        assert_eq!(config.levels[1].associativity, 1);
+        assert_eq!(config.levels[1].replacement, ReplacementPolicy::Lru);
+    }
+
+    #[test]
+    fn parses_write_policy() {
+        let config = MachineConfig::from_toml(
+            r#"
+            levels = [
+                { capacity = 64, latency = 1, write_policy = "write-back" },
+                { capacity = 256, latency = 100 },
+            ]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.levels[0].write_policy, WritePolicy::WriteBack);
+        assert_eq!(config.levels[1].write_policy, WritePolicy::WriteThrough);
+        // main memory has nowhere to write back to
+        assert!(MachineConfig::from_toml(
+            r#"levels = [{ capacity = 256, latency = 100, write_policy = "write-back" }]"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn parses_split_l1() {
+        let config = MachineConfig::from_toml(
+            r"
+            [icache]
+            capacity = 32
+            latency = 1
+            associativity = 2
+
+            [[levels]]
+            capacity = 64
+            latency = 1
+
+            [[levels]]
+            capacity = 256
+            latency = 100
+            ",
+        )
+        .unwrap();
+
+        let icache = config.icache.unwrap();
+        assert_eq!(icache.capacity, 32);
+        assert_eq!(icache.associativity, 2);
+        // a split L1 needs somewhere to put the data cache
+        assert!(MachineConfig::from_toml(
+            r"
+            icache = { capacity = 32, latency = 1 }
+            levels = [{ capacity = 256, latency = 100 }]
+            "
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn parses_branch_predictor() {
+        let config = MachineConfig::from_toml(
+            r#"
+            [branch_predictor]
+            kind = "two-bit"
+            btb_entries = 32
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.branch_predictor.kind, PredictorKind::TwoBit);
+        assert_eq!(config.branch_predictor.btb_entries, 32);
+        assert_eq!(
+            MachineConfig::default().branch_predictor.kind,
+            PredictorKind::NotTaken
+        );
+        // dynamic predictors need somewhere to keep their history
+        assert!(MachineConfig::from_toml(
+            r#"branch_predictor = { kind = "one-bit", btb_entries = 0 }"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn json_matches_toml() {
+        let json = MachineConfig::from_json(
+            r#"{ "line_len": 4, "levels": [{ "capacity": 256, "latency": 100 }] }"#,
+        )
+        .unwrap();
+        let toml = MachineConfig::from_toml(
+            r"
+            line_len = 4
+            levels = [{ capacity = 256, latency = 100 }]
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(json, toml);
+    }
+
+    #[test]
+    fn rejects_invalid_configs() {
+        // no levels
+        assert!(MachineConfig::from_toml("levels = []").is_err());
+        // empty level
+        assert!(MachineConfig::from_toml("levels = [{ capacity = 0, latency = 1 }]").is_err());
+        // empty lines
+        assert!(MachineConfig::from_toml("line_len = 0").is_err());
+        // misaligned start
+        assert!(MachineConfig::from_toml("start_pc = 3").is_err());
+        // start outside of main memory
+        assert!(MachineConfig::from_toml("start_pc = 32768").is_err());
+        // main memory running into the console registers
+        assert!(MachineConfig::from_toml(
+            "line_len = 256\nlevels = [{ capacity = 256, latency = 100 }]"
+        )
+        .is_err());
+        // main memory too large to even address
+        assert!(MachineConfig::from_toml(
+            "levels = [{ capacity = 1152921504606846976, latency = 100 }]"
+        )
+        .unwrap_err()
+        .to_string()
+        .contains("larger than the address space"));
+        // misaligned trap handler
+        assert!(MachineConfig::from_toml("trap_handler = 33").is_err());
+        // associativity doesn't divide the level
+        assert!(MachineConfig::from_toml(
+            "levels = [{ capacity = 6, latency = 1, associativity = 4 }, { capacity = 256, latency = 100 }]"
+        )
+        .is_err());
+        // associative main memory
+        assert!(MachineConfig::from_toml(
+            "levels = [{ capacity = 256, latency = 1, associativity = 2 }]"
+        )
+        .is_err());
+        // typo'd field
+        assert!(MachineConfig::from_toml("line_length = 4").is_err());
+    }
+}
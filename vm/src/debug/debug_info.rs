@@ -0,0 +1,236 @@
+#![warn(clippy::all, clippy::pedantic)]
+#![allow(
+    clippy::module_name_repetitions,
+    clippy::missing_errors_doc,
+    clippy::must_use_candidate
+)]
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use log::info;
+use serde::{Deserialize, Serialize};
+
+// Identifies debug info files, so other JSON isn't mistaken for one
+const DEBUG_INFO_FORMAT: &str = "giggle-flop-debug-info";
+// Bumped whenever the format changes in a way older debuggers can't read
+const DEBUG_INFO_VERSION: u32 = 1;
+// Appended to a program's path to find its debug info
+const DEBUG_INFO_EXTENSION: &str = "dbg";
+
+/// Text of a source file a program was assembled from
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceText {
+    pub name: String,
+    pub lines: Vec<String>,
+}
+
+/// Instructions assembled from a single source line
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LineMapping {
+    pub address: u32, // of the first instruction
+    pub width: u32,   // in bits, covering every instruction from the line
+    pub file: usize,  // index into `DebugInfo::files`
+    pub line: usize,  // 1-based
+}
+
+impl LineMapping {
+    pub fn contains(&self, address: u32) -> bool {
+        (self.address..self.address.saturating_add(self.width)).contains(&address)
+    }
+}
+
+/// What a debugger needs to show a program's source rather than its
+/// disassembly, written by the assembler alongside the program
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DebugInfo {
+    pub format: String,
+    pub version: u32,
+    pub files: Vec<SourceText>,         // the file assembled comes first
+    pub lines: Vec<LineMapping>,        // in address order
+    pub symbols: BTreeMap<String, u32>, // label -> bit address
+}
+
+impl Default for DebugInfo {
+    fn default() -> Self {
+        Self {
+            format: DEBUG_INFO_FORMAT.to_string(),
+            version: DEBUG_INFO_VERSION,
+            files: vec![],
+            lines: vec![],
+            symbols: BTreeMap::new(),
+        }
+    }
+}
+
+impl DebugInfo {
+    /// Returns where the debug info for the program at `program` is kept
+    pub fn sidecar_path(program: &Path) -> PathBuf {
+        let mut path = program.as_os_str().to_owned();
+        path.push(format!(".{DEBUG_INFO_EXTENSION}"));
+        path.into()
+    }
+
+    /// Reads the debug info file at `path`
+    pub fn from_file(path: &Path) -> Result<Self> {
+        info!("Loading debug info {}", path.display());
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read debug info {}", path.display()))?;
+        Self::from_json(&contents).with_context(|| format!("Invalid debug info {}", path.display()))
+    }
+
+    /// Parses debug info written by [`DebugInfo::to_json`]
+    pub fn from_json(contents: &str) -> Result<Self> {
+        let debug_info: Self = serde_json::from_str(contents)?;
+        if debug_info.format != DEBUG_INFO_FORMAT {
+            return Err(anyhow!("Not a {DEBUG_INFO_FORMAT} file"));
+        }
+        if debug_info.version != DEBUG_INFO_VERSION {
+            return Err(anyhow!(
+                "Debug info version {} isn't supported, only version {DEBUG_INFO_VERSION} is",
+                debug_info.version
+            ));
+        }
+        if let Some(mapping) = debug_info
+            .lines
+            .iter()
+            .find(|mapping| mapping.file >= debug_info.files.len())
+        {
+            return Err(anyhow!(
+                "Line mapping at 0x{:08X} refers to file {}, but there are only {}",
+                mapping.address,
+                mapping.file,
+                debug_info.files.len()
+            ));
+        }
+        Ok(debug_info)
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Returns the source line the instruction at `address` was assembled from
+    pub fn line_at(&self, address: u32) -> Option<&LineMapping> {
+        let idx = self
+            .lines
+            .partition_point(|mapping| mapping.address <= address);
+        self.lines[..idx]
+            .last()
+            .filter(|mapping| mapping.contains(address))
+    }
+
+    /// Returns the labels pointing at `address`
+    pub fn labels_at(&self, address: u32) -> impl Iterator<Item = &str> {
+        self.symbols
+            .iter()
+            .filter(move |(_, label_addr)| **label_addr == address)
+            .map(|(label, _)| label.as_str())
+    }
+
+    /// Returns the address of the first instruction at or after `line` of
+    /// `file`, skipping lines such as comments that have none
+    pub fn line_address(&self, file: usize, line: usize) -> Option<u32> {
+        self.lines
+            .iter()
+            .filter(|mapping| mapping.file == file && mapping.line >= line)
+            .min_by_key(|mapping| (mapping.line, mapping.address))
+            .map(|mapping| mapping.address)
+    }
+
+    /// Returns the address to break at for `location`, which is a label, a
+    /// line of the file assembled, or `file:line` for a line of an included
+    /// file
+    pub fn resolve_breakpoint(&self, location: &str) -> Result<u32> {
+        let location = location.trim();
+        if let Some(address) = self.symbols.get(location) {
+            return Ok(*address);
+        }
+
+        let (file, line) = match location.rsplit_once(':') {
+            Some((name, line)) => {
+                let file = self
+                    .files
+                    .iter()
+                    .position(|file| {
+                        file.name == name
+                            || Path::new(&file.name).file_name() == Some(name.as_ref())
+                    })
+                    .ok_or_else(|| anyhow!("No source file named {name}"))?;
+                (file, line)
+            }
+            None => (0, location),
+        };
+        let Ok(line) = line.parse::<usize>() else {
+            return Err(anyhow!("No label named {location}"));
+        };
+        let name = self.files.get(file).map_or("", |file| file.name.as_str());
+        self.line_address(file, line)
+            .ok_or_else(|| anyhow!("No instructions on or after line {line} of {name}"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::debug::debug_info::{DebugInfo, LineMapping, SourceText};
+
+    fn debug_info() -> DebugInfo {
+        let mapping = |address, width, file, line| LineMapping {
+            address,
+            width,
+            file,
+            line,
+        };
+        DebugInfo {
+            files: vec![
+                SourceText {
+                    name: "dir/main.gf".to_string(),
+                    lines: vec![
+                        "// start".to_string(),
+                        "main: LI R1, 7".to_string(),
+                        "CALL double".to_string(),
+                    ],
+                },
+                SourceText {
+                    name: "dir/lib.gf".to_string(),
+                    lines: vec!["double: ADDU R1, R1, R1".to_string(), "RET".to_string()],
+                },
+            ],
+            lines: vec![
+                mapping(0, 64, 0, 2),
+                mapping(64, 32, 0, 3),
+                mapping(96, 32, 1, 1),
+                mapping(128, 32, 1, 2),
+            ],
+            symbols: [("main".to_string(), 0), ("double".to_string(), 96)].into(),
+            ..DebugInfo::default()
+        }
+    }
+
+    #[test]
+    fn maps_addresses_to_lines() {
+        let debug_info = debug_info();
+        assert_eq!(debug_info.line_at(32).map(|mapping| mapping.line), Some(2));
+        assert_eq!(debug_info.line_at(96).map(|mapping| mapping.file), Some(1));
+        assert_eq!(debug_info.line_at(160), None);
+        assert_eq!(debug_info.labels_at(96).collect::<Vec<_>>(), vec!["double"]);
+
+        let json = debug_info.to_json().unwrap();
+        assert_eq!(DebugInfo::from_json(&json).unwrap(), debug_info);
+    }
+
+    #[test]
+    fn resolves_breakpoints() {
+        let debug_info = debug_info();
+        assert_eq!(debug_info.resolve_breakpoint("double").unwrap(), 96);
+        // the comment on line 1 moves the breakpoint to the next instruction
+        assert_eq!(debug_info.resolve_breakpoint("1").unwrap(), 0);
+        assert_eq!(debug_info.resolve_breakpoint("3").unwrap(), 64);
+        assert_eq!(debug_info.resolve_breakpoint("lib.gf:2").unwrap(), 128);
+        assert_eq!(debug_info.resolve_breakpoint("dir/lib.gf:1").unwrap(), 96);
+        assert!(debug_info.resolve_breakpoint("triple").is_err());
+        assert!(debug_info.resolve_breakpoint("4").is_err());
+        assert!(debug_info.resolve_breakpoint("other.gf:1").is_err());
+    }
+}
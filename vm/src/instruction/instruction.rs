@@ -11,14 +11,86 @@ use crate::{
     system::system::PipelineStage,
 };
 
-const MASK_1: u32 = 0b1;
-const MASK_2: u32 = 0b11;
-const MASK_3: u32 = 0b111;
-const MASK_4: u32 = 0b1111;
-const MASK_21: u32 = 0b1_1111_1111_1111_1111_1111;
+/// Width of the type field every instruction starts with
+pub const TYPE_FIELD_WIDTH: u32 = 3;
+/// Width of each register argument
+pub const REG_FIELD_WIDTH: u32 = 4;
+/// Width of an immediate argument
+pub const IMMEDIATE_FIELD_WIDTH: u32 = 21;
 
 pub type RawInstruction = u32;
 
+/// Sign-extends a two's complement immediate to 32 bits, for the instructions
+/// treating it as an offset (`ADDIM` and the PC relative jumps) rather than
+/// an address
+#[must_use]
+#[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+pub fn sign_extend_immediate(immediate: u32) -> u32 {
+    let shift = u32::BITS - IMMEDIATE_FIELD_WIDTH;
+    (((immediate << shift) as i32) >> shift) as u32
+}
+
+/// Returns whether the instruction `opcode` of `instr_type` sign-extends its
+/// immediate with `sign_extend_immediate` (`ADDIM` and the PC relative jumps)
+#[must_use]
+pub fn sign_extends_immediate(instr_type: usize, opcode: u32) -> bool {
+    matches!((instr_type, opcode), (1, 7..=12) | (4, 9))
+}
+
+/// Fields following the type field of an instruction, packed from the least
+/// significant bit up: the opcode, then the register arguments, then the
+/// immediate. Any remaining high bits are padding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InstrLayout {
+    pub opcode_width: u32,
+    pub reg_count: usize,
+    pub has_immediate: bool,
+}
+
+/// Layout of every instruction type, indexed by the type field. Both encoding
+/// and decoding are driven by this table
+pub const INSTR_LAYOUTS: [InstrLayout; 7] = [
+    InstrLayout {
+        opcode_width: 1,
+        reg_count: 0,
+        has_immediate: false,
+    },
+    InstrLayout {
+        opcode_width: 4,
+        reg_count: 0,
+        has_immediate: true,
+    },
+    InstrLayout {
+        opcode_width: 4,
+        reg_count: 2,
+        has_immediate: false,
+    },
+    InstrLayout {
+        opcode_width: 1,
+        reg_count: 2,
+        has_immediate: false,
+    },
+    InstrLayout {
+        opcode_width: 4,
+        reg_count: 1,
+        has_immediate: true,
+    },
+    InstrLayout {
+        opcode_width: 4,
+        reg_count: 3,
+        has_immediate: false,
+    },
+    InstrLayout {
+        opcode_width: 2,
+        reg_count: 3,
+        has_immediate: false,
+    },
+];
+
+const fn field_mask(width: u32) -> u32 {
+    (1 << width) - 1
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Copy)]
 pub enum Instruction {
     Type0 {
@@ -208,6 +280,190 @@ impl Instruction {
             }
         }
     }
+
+    /// Builds an instruction of `instr_type` from its fields, as laid out in
+    /// `INSTR_LAYOUTS`. Returns `None` if the type doesn't exist or `regs`
+    /// holds the wrong number of registers for it
+    pub fn from_fields(
+        instr_type: usize,
+        opcode: u32,
+        regs: &[usize],
+        immediate: u32,
+    ) -> Option<Self> {
+        if INSTR_LAYOUTS.get(instr_type)?.reg_count != regs.len() {
+            return None;
+        }
+        let instr = match instr_type {
+            0 => Instruction::Type0 { opcode },
+            1 => Instruction::Type1 { opcode, immediate },
+            2 => Instruction::Type2 {
+                opcode,
+                reg_1: regs[0],
+                reg_2: regs[1],
+            },
+            3 => Instruction::Type3 {
+                opcode,
+                freg_1: regs[0],
+                freg_2: regs[1],
+            },
+            4 => Instruction::Type4 {
+                opcode,
+                reg_1: regs[0],
+                immediate,
+            },
+            5 => Instruction::Type5 {
+                opcode,
+                reg_1: regs[0],
+                reg_2: regs[1],
+                reg_3: regs[2],
+            },
+            _ => Instruction::Type6 {
+                opcode,
+                freg_1: regs[0],
+                freg_2: regs[1],
+                freg_3: regs[2],
+            },
+        };
+        Some(instr)
+    }
+
+    /// Returns the value of the type field, which indexes `ALL_INSTR_TYPES`
+    /// and `INSTR_LAYOUTS`
+    pub fn instr_type(&self) -> usize {
+        match self {
+            Instruction::Type0 { .. } => 0,
+            Instruction::Type1 { .. } => 1,
+            Instruction::Type2 { .. } => 2,
+            Instruction::Type3 { .. } => 3,
+            Instruction::Type4 { .. } => 4,
+            Instruction::Type5 { .. } => 5,
+            Instruction::Type6 { .. } => 6,
+        }
+    }
+
+    pub fn opcode(&self) -> u32 {
+        match self {
+            Instruction::Type0 { opcode }
+            | Instruction::Type1 { opcode, .. }
+            | Instruction::Type2 { opcode, .. }
+            | Instruction::Type3 { opcode, .. }
+            | Instruction::Type4 { opcode, .. }
+            | Instruction::Type5 { opcode, .. }
+            | Instruction::Type6 { opcode, .. } => *opcode,
+        }
+    }
+
+    /// Returns the register arguments, general purpose or floating point, in
+    /// encoding order
+    pub fn reg_args(&self) -> Vec<usize> {
+        match *self {
+            Instruction::Type0 { .. } | Instruction::Type1 { .. } => vec![],
+            Instruction::Type4 { reg_1, .. } => vec![reg_1],
+            Instruction::Type2 { reg_1, reg_2, .. } => vec![reg_1, reg_2],
+            Instruction::Type3 { freg_1, freg_2, .. } => vec![freg_1, freg_2],
+            Instruction::Type5 {
+                reg_1,
+                reg_2,
+                reg_3,
+                ..
+            } => vec![reg_1, reg_2, reg_3],
+            Instruction::Type6 {
+                freg_1,
+                freg_2,
+                freg_3,
+                ..
+            } => vec![freg_1, freg_2, freg_3],
+        }
+    }
+
+    pub fn immediate(&self) -> Option<u32> {
+        match self {
+            Instruction::Type1 { immediate, .. } | Instruction::Type4 { immediate, .. } => {
+                Some(*immediate)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the machine code for the instruction. Fields too wide for
+    /// their slot in `INSTR_LAYOUTS` are truncated
+    pub fn encode(&self) -> RawInstruction {
+        let layout = INSTR_LAYOUTS[self.instr_type()];
+        let fields = std::iter::once((self.opcode(), layout.opcode_width))
+            .chain(
+                self.reg_args()
+                    .into_iter()
+                    .map(|reg| (reg as u32, REG_FIELD_WIDTH)),
+            )
+            .chain(
+                self.immediate()
+                    .map(|immediate| (immediate, IMMEDIATE_FIELD_WIDTH)),
+            );
+
+        let mut raw = self.instr_type() as RawInstruction;
+        let mut shift = TYPE_FIELD_WIDTH;
+        for (value, width) in fields {
+            raw |= (value & field_mask(width)) << shift;
+            shift += width;
+        }
+        raw
+    }
+
+    /// Returns whether the opcode names an instruction of its type
+    pub fn is_legal(&self) -> bool {
+        (self.opcode() as usize) < ALL_INSTR_TYPES[self.instr_type()].len()
+    }
+
+    /// Returns whether the instruction is an integer division or modulo by zero
+    /// given the current register contents
+    pub fn divides_by_zero(&self, gen_regs: &[Register], float_regs: &[Register]) -> bool {
+        match self {
+            // DIVI, MODI, DIVU, MODU
+            Instruction::Type5 {
+                opcode: 3 | 4 | 12 | 13,
+                reg_2,
+                reg_3,
+                ..
+            } => gen_regs[*reg_2].data.divides_by_zero(gen_regs[*reg_3].data),
+            // DIVF
+            Instruction::Type6 {
+                opcode: 3,
+                freg_2,
+                freg_3,
+                ..
+            } => float_regs[*freg_2]
+                .data
+                .divides_by_zero(float_regs[*freg_3].data),
+            _ => false,
+        }
+    }
+
+    /// Returns whether the instruction may redirect the program counter
+    /// (`RET`, `CALL`, and the jumps)
+    pub fn is_control_flow(&self) -> bool {
+        matches!(
+            self,
+            Instruction::Type0 { opcode: 0 } | Instruction::Type1 { .. }
+        )
+    }
+
+    /// Returns the target of a control flow instruction fetched from `pc`,
+    /// if it doesn't depend on any registers
+    pub fn direct_target(&self, pc: u32) -> Option<u32> {
+        match self {
+            // CALL and the absolute jumps
+            Instruction::Type1 {
+                opcode: 0..=6,
+                immediate,
+            } => Some(*immediate),
+            // PC relative jumps
+            Instruction::Type1 {
+                opcode: 7..=12,
+                immediate,
+            } => Some(pc.wrapping_add(sign_extend_immediate(*immediate))),
+            _ => None,
+        }
+    }
 }
 
 impl Display for Instruction {
@@ -317,148 +573,107 @@ impl Display for Instruction {
 
 /// Transform a raw u32 into an Instruction Object
 pub fn decode_raw_instr(raw: u32) -> Option<Instruction> {
-    let mut value = raw;
-    //let instruction =
-    // type field is always 3 bits
-    // get first three bits
-    let instr_type = value & MASK_3;
-    value >>= 3;
-    // switch type off of that
-    match instr_type {
-        0 => {
-            // opcode takes one bit
-            let opcode = value & MASK_1;
-            // value >>= 1;
-
-            // 28 remaining bits of padding to ignore
-
-            Some(Instruction::Type0 { opcode })
-        }
-        1 => {
-            // opcode takes four bits
-            let opcode = value & MASK_4;
-            value >>= 4;
-
-            // immediate argument takes 21 bits
-            let immediate = value & MASK_21;
-            // value >>= 21;
-            // 4 remaining bits of padding to ignore
-
-            Some(Instruction::Type1 { opcode, immediate })
-        }
-        2 => {
-            // opcode takes four bits
-            let opcode = value & MASK_4;
-            value >>= 4;
-
-            // general register 1 argument takes 4 bits
-            let reg_1 = value & MASK_4;
-            value >>= 4;
-
-            // general register 2 argument takes 4 bits
-            let reg_2 = value & MASK_4;
-            // value >>= 4;
-            // 18 remaining bits of padding to ignore
-
-            Some(Instruction::Type2 {
-                opcode,
-                reg_1: reg_1.try_into().unwrap(),
-                reg_2: reg_2.try_into().unwrap(),
-            })
-        }
-        3 => {
-            // opcode takes one bit
-            let opcode = value & MASK_1;
-            value >>= 1;
-
-            // floating point register 1 argument takes 4 bits
-            let freg_1 = value & MASK_4;
-            value >>= 4;
-
-            // floating point register 2 argument takes 4 bits
-            let freg_2 = value & MASK_4;
-            // value >>= 4;
-            // 20 remaining bits of padding to ignore
-
-            Some(Instruction::Type3 {
-                opcode,
-                freg_1: freg_1.try_into().unwrap(),
-                freg_2: freg_2.try_into().unwrap(),
-            })
-        }
-        4 => {
-            // opcode takes four bits
-            let opcode = value & MASK_4;
-            value >>= 4;
-
-            // general register argument takes 4 bits
-            let reg_1 = value & MASK_4;
-            value >>= 4;
-
-            // immediate argument takes 21 bits
-            let immediate = value & MASK_21;
-            // value >>= 21;
-            // 0 remaining bits of padding
+    let instr_type = (raw & field_mask(TYPE_FIELD_WIDTH)) as usize;
+    let Some(layout) = INSTR_LAYOUTS.get(instr_type) else {
+        error!("Invalid instruction type field: {instr_type}");
+        return None;
+    };
+
+    let mut value = raw >> TYPE_FIELD_WIDTH;
+    let mut take = |width: u32| {
+        let field = value & field_mask(width);
+        value >>= width;
+        field
+    };
+    let opcode = take(layout.opcode_width);
+    let regs: Vec<usize> = (0..layout.reg_count)
+        .map(|_| take(REG_FIELD_WIDTH) as usize)
+        .collect();
+    let immediate = if layout.has_immediate {
+        take(IMMEDIATE_FIELD_WIDTH)
+    } else {
+        0
+    };
+    // any remaining bits are padding
+
+    Instruction::from_fields(instr_type, opcode, &regs, immediate)
+}
 
-            Some(Instruction::Type4 {
-                opcode,
-                reg_1: reg_1.try_into().unwrap(),
-                immediate,
-            })
+#[cfg(test)]
+mod test {
+    use crate::instruction::instruction::{
+        decode_raw_instr, Instruction, IMMEDIATE_FIELD_WIDTH, INSTR_LAYOUTS,
+    };
+    use crate::register::register_system::{ALL_INSTR_TYPES, GEN_REG_COUNT};
+
+    /// Every opcode of every type, with a spread of register and immediate
+    /// arguments
+    fn every_instruction() -> Vec<Instruction> {
+        let max_immediate = (1 << IMMEDIATE_FIELD_WIDTH) - 1;
+        let mut instrs = vec![];
+        for (instr_type, names) in ALL_INSTR_TYPES.iter().enumerate() {
+            let layout = INSTR_LAYOUTS[instr_type];
+            for opcode in 0..names.len() as u32 {
+                for reg in 0..GEN_REG_COUNT {
+                    // distinct registers catch fields landing in the wrong slot
+                    let regs: Vec<usize> = (0..layout.reg_count)
+                        .map(|i| (reg + 5 * i) % GEN_REG_COUNT)
+                        .collect();
+                    for immediate in [0, 1, 0x1_5A5A, max_immediate] {
+                        instrs.push(
+                            Instruction::from_fields(instr_type, opcode, &regs, immediate).unwrap(),
+                        );
+                    }
+                }
+            }
         }
-        5 => {
-            // opcode takes four bits
-            let opcode = value & MASK_4;
-            value >>= 4;
-
-            // general register 1 argument takes 4 bits
-            let reg_1 = value & MASK_4;
-            value >>= 4;
-
-            // general register 2 argument takes 4 bits
-            let reg_2 = value & MASK_4;
-            value >>= 4;
-
-            // general register 2 argument takes 4 bits
-            let reg_3 = value & MASK_4;
-            // value >>= 4;
-            // 13 remaining bits of padding to ignore
+        instrs
+    }
 
-            Some(Instruction::Type5 {
-                opcode,
-                reg_1: reg_1.try_into().unwrap(),
-                reg_2: reg_2.try_into().unwrap(),
-                reg_3: reg_3.try_into().unwrap(),
-            })
+    #[test]
+    fn encoding_round_trips() {
+        for instr in every_instruction() {
+            assert_eq!(decode_raw_instr(instr.encode()), Some(instr), "{instr}");
+            assert!(instr.is_legal());
         }
-        6 => {
-            // opcode takes two bits
-            let opcode = value & MASK_2;
-            value >>= 4;
-
-            // general register 1 argument takes 4 bits
-            let freg_1 = value & MASK_4;
-            value >>= 4;
-
-            // general register 2 argument takes 4 bits
-            let freg_2 = value & MASK_4;
-            value >>= 4;
-
-            // general register 2 argument takes 4 bits
-            let freg_3 = value & MASK_4;
-            // value >>= 4;
-            // 15 remaining bits of padding to ignore
+    }
 
-            Some(Instruction::Type6 {
-                opcode,
-                freg_1: freg_1.try_into().unwrap(),
-                freg_2: freg_2.try_into().unwrap(),
-                freg_3: freg_3.try_into().unwrap(),
-            })
-        }
-        x => {
-            error!("Invalid instruction type field: {x}");
-            None
-        }
+    #[test]
+    fn encodings_match_the_isa() {
+        assert_eq!(Instruction::Type0 { opcode: 1 }.encode(), 0b1000);
+        assert_eq!(
+            Instruction::Type1 {
+                opcode: 2,
+                immediate: 0x20
+            }
+            .encode(),
+            1 | 2 << 3 | 0x20 << 7
+        );
+        assert_eq!(
+            Instruction::Type4 {
+                opcode: 2,
+                reg_1: 1,
+                immediate: 0x1F_FFFF
+            }
+            .encode(),
+            0xFFFF_F894
+        );
+        // a 2-bit opcode leaves the registers starting at bit 5
+        assert_eq!(
+            Instruction::Type6 {
+                opcode: 3,
+                freg_1: 1,
+                freg_2: 2,
+                freg_3: 3
+            }
+            .encode(),
+            6 | 3 << 3 | 1 << 5 | 2 << 9 | 3 << 13
+        );
+        // padding is ignored
+        assert_eq!(
+            decode_raw_instr(0xFFFF_FFF8),
+            Some(Instruction::Type0 { opcode: 1 })
+        );
+        assert_eq!(decode_raw_instr(0b111), None);
     }
 }
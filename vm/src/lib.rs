@@ -1,4 +1,6 @@
+pub mod config;
+pub mod debug;
 pub mod instruction;
-mod memory;
+pub mod memory;
 pub mod register;
-mod system;
+pub mod system;
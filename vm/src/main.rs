@@ -1,15 +1,38 @@
 #![warn(clippy::all, clippy::pedantic)]
 
+mod config;
+mod debug;
 mod instruction;
 mod memory;
 mod register;
 mod system;
 mod ui;
 
+use std::path::PathBuf;
+
 use anyhow::{anyhow, Result};
+use clap::Parser;
+
+use crate::config::config::MachineConfig;
+
+#[derive(Parser, Debug)]
+#[command(version, about = "Giggle-Flop GUI debugger")]
+struct GuiArgs {
+    #[arg(
+        long,
+        help = "TOML or JSON file describing the memory hierarchy and pipeline"
+    )]
+    config: Option<PathBuf>,
+}
 
 fn main() -> Result<()> {
     // NOTE: Uncomment the line below to enable logging
     // flexi_logger::Logger::try_with_str("info")?.start()?;
-    ui::ui::enter().map_err(|e| anyhow!(e))
+    let args = GuiArgs::parse();
+    let config = match args.config {
+        Some(path) => MachineConfig::from_file(&path)?,
+        None => MachineConfig::default(),
+    };
+
+    ui::ui::enter(config).map_err(|e| anyhow!(e))
 }
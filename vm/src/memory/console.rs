@@ -0,0 +1,133 @@
+#![warn(clippy::all, clippy::pedantic)]
+
+use std::collections::VecDeque;
+
+use log::{info, warn};
+
+use crate::memory::memory_block::MemBlock;
+use crate::memory::memory_system::{ADDRESS_SPACE_SIZE, MEM_BLOCK_WIDTH};
+
+/// Bit address of the first console register, at the top of the address space
+pub const CONSOLE_BASE: usize = ADDRESS_SPACE_SIZE - CONSOLE_REGS * MEM_BLOCK_WIDTH;
+/// Stores print the low byte as an ASCII character
+pub const CONSOLE_CHAR_OUT: usize = CONSOLE_BASE;
+/// Stores print the value as a decimal number
+pub const CONSOLE_INT_OUT: usize = CONSOLE_BASE + MEM_BLOCK_WIDTH;
+/// Loads take the next byte from the input buffer, or 0 if it's empty
+pub const CONSOLE_CHAR_IN: usize = CONSOLE_BASE + 2 * MEM_BLOCK_WIDTH;
+/// Loads return the number of bytes waiting in the input buffer
+pub const CONSOLE_IN_STATUS: usize = CONSOLE_BASE + 3 * MEM_BLOCK_WIDTH;
+const CONSOLE_REGS: usize = 4;
+
+/// Memory-mapped console device. Accesses bypass the caches and complete
+/// immediately
+#[derive(Debug, Clone, Default)]
+pub struct Console {
+    output: String,
+    input: VecDeque<u8>,
+}
+
+impl Console {
+    /// Indicates whether `address` belongs to one of the console's registers
+    #[must_use]
+    pub fn contains(address: usize) -> bool {
+        (CONSOLE_BASE..ADDRESS_SPACE_SIZE).contains(&address)
+    }
+
+    /// Reads the console register at `address`
+    pub fn load(&mut self, address: usize) -> MemBlock {
+        let data = match address {
+            CONSOLE_CHAR_IN => MemBlock::Unsigned32(self.input.pop_front().map_or(0, u32::from)),
+            CONSOLE_IN_STATUS => {
+                MemBlock::Unsigned32(u32::try_from(self.input.len()).unwrap_or(u32::MAX))
+            }
+            _ => {
+                warn!("Load from write-only console register 0x{address:08X}");
+                MemBlock::Unsigned32(0)
+            }
+        };
+        info!("Console load from 0x{address:08X}: {data}");
+        data
+    }
+
+    /// Writes `data` to the console register at `address`
+    pub fn store(&mut self, address: usize, data: MemBlock) {
+        info!("Console store to 0x{address:08X}: {data}");
+        match address {
+            CONSOLE_CHAR_OUT => {
+                // truncation to the low byte is intended
+                #[allow(clippy::cast_possible_truncation)]
+                self.output.push(char::from(data.force_unsigned() as u8));
+            }
+            CONSOLE_INT_OUT => {
+                let number = match data {
+                    MemBlock::Signed8(_) | MemBlock::Signed16(_) | MemBlock::Signed32(_) => {
+                        data.force_signed().to_string()
+                    }
+                    MemBlock::Float32(val) => val.to_string(),
+                    _ => data.force_unsigned().to_string(),
+                };
+                self.output.push_str(&number);
+            }
+            _ => warn!("Store to read-only console register 0x{address:08X}"),
+        }
+    }
+
+    /// Everything printed to the console so far
+    #[must_use]
+    pub fn output(&self) -> &str {
+        &self.output
+    }
+
+    /// Queues `input` to be read through `CONSOLE_CHAR_IN`
+    pub fn push_input(&mut self, input: &[u8]) {
+        self.input.extend(input);
+    }
+
+    /// Returns the number of bytes waiting in the input buffer
+    #[must_use]
+    pub fn pending_input(&self) -> usize {
+        self.input.len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::memory::console::{
+        Console, CONSOLE_BASE, CONSOLE_CHAR_IN, CONSOLE_CHAR_OUT, CONSOLE_INT_OUT,
+        CONSOLE_IN_STATUS,
+    };
+    use crate::memory::memory_block::MemBlock;
+
+    #[test]
+    fn prints_chars_and_numbers() {
+        let mut console = Console::default();
+        console.store(CONSOLE_CHAR_OUT, MemBlock::Unsigned32(u32::from(b'x')));
+        console.store(
+            CONSOLE_CHAR_OUT,
+            MemBlock::Unsigned32(0x100 | u32::from(b'=')),
+        );
+        console.store(CONSOLE_INT_OUT, MemBlock::Signed32(-42));
+        console.store(CONSOLE_INT_OUT, MemBlock::Unsigned32(7));
+        assert_eq!(console.output(), "x=-427");
+    }
+
+    #[test]
+    fn reads_input_buffer() {
+        let mut console = Console::default();
+        console.push_input(b"hi");
+        assert_eq!(console.load(CONSOLE_IN_STATUS), MemBlock::Unsigned32(2));
+        assert_eq!(
+            console.load(CONSOLE_CHAR_IN),
+            MemBlock::Unsigned32(u32::from(b'h'))
+        );
+        assert_eq!(
+            console.load(CONSOLE_CHAR_IN),
+            MemBlock::Unsigned32(u32::from(b'i'))
+        );
+        // an empty buffer reads as NUL
+        assert_eq!(console.load(CONSOLE_CHAR_IN), MemBlock::Unsigned32(0));
+        assert!(Console::contains(CONSOLE_BASE));
+        assert!(!Console::contains(CONSOLE_BASE - 32));
+    }
+}
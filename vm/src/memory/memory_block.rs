@@ -40,6 +40,7 @@ impl std::hash::Hash for MemBlock {
 }
 
 impl MemBlock {
+    #[must_use]
     pub fn to_be_bytes(self) -> [u8; 4] {
         match self {
             Self::Unsigned8(data) => {
@@ -64,6 +65,7 @@ impl MemBlock {
         }
     }
 
+    #[must_use]
     pub fn add_immediate(&mut self, immediate: u32) -> Self {
         match self {
             MemBlock::Unsigned8(data) => {
@@ -88,6 +90,7 @@ impl MemBlock {
         }
     }
 
+    #[must_use]
     pub fn get_unsigned(self) -> Option<u32> {
         match self {
             Self::Unsigned8(data) => Some(u32::from(data)),
@@ -97,6 +100,7 @@ impl MemBlock {
         }
     }
 
+    #[must_use]
     pub fn get_signed(self) -> Option<i32> {
         match self {
             Self::Signed8(data) => Some(i32::from(data)),
@@ -106,6 +110,7 @@ impl MemBlock {
         }
     }
 
+    #[must_use]
     pub fn get_float(self) -> Option<f32> {
         if let Self::Float32(data) = self {
             Some(data)
@@ -114,6 +119,7 @@ impl MemBlock {
         }
     }
 
+    #[must_use]
     pub fn force_unsigned(self) -> u32 {
         match self {
             MemBlock::Unsigned8(data) => u32::from(data),
@@ -126,6 +132,7 @@ impl MemBlock {
         }
     }
 
+    #[must_use]
     pub fn force_signed(self) -> i32 {
         match self {
             MemBlock::Unsigned8(data) => i32::from(data),
@@ -151,6 +158,7 @@ impl MemBlock {
     }
 
     // there has to be a better way to do this...look into later
+    #[must_use]
     pub fn add_register(&mut self, conts: MemBlock) -> Self {
         info!("Add register: {self} + {}", conts);
         if let Some(val) = self.get_unsigned() {
@@ -174,6 +182,7 @@ impl MemBlock {
     }
 
     // there has to be a better way to do this...look into later
+    #[must_use]
     pub fn sub_register(&mut self, conts: MemBlock) -> Self {
         info!("Subtract register: {self} - {}", conts);
         if let Some(val) = self.get_unsigned() {
@@ -197,6 +206,7 @@ impl MemBlock {
     }
 
     // there has to be a better way to do this...look into later
+    #[must_use]
     pub fn mul_register(&mut self, conts: MemBlock) -> Self {
         info!("Multiply register: {self} * {}", conts);
         if let Some(val) = self.get_unsigned() {
@@ -219,7 +229,21 @@ impl MemBlock {
         }
     }
 
+    /// Returns whether `div_register` or `mod_register` with `divisor` would
+    /// divide an integer by zero. Float division by zero is left to IEEE 754
+    #[must_use]
+    pub fn divides_by_zero(self, divisor: MemBlock) -> bool {
+        if self.get_unsigned().is_some() {
+            divisor.force_unsigned() == 0
+        } else if self.get_signed().is_some() {
+            divisor.force_signed() == 0
+        } else {
+            false
+        }
+    }
+
     // there has to be a better way to do this...look into later
+    #[must_use]
     pub fn div_register(&mut self, conts: MemBlock) -> Self {
         info!("Divide register: {self} / {}", conts);
         if let Some(val) = self.get_unsigned() {
@@ -243,6 +267,7 @@ impl MemBlock {
     }
 
     // there has to be a better way to do this...look into later
+    #[must_use]
     pub fn mod_register(&mut self, conts: MemBlock) -> Self {
         info!("Modulo register: {self} % {}", conts);
         if let Some(val) = self.get_unsigned() {
@@ -252,7 +277,7 @@ impl MemBlock {
             result
         } else if let Some(val) = self.get_signed() {
             let other = conts.force_signed();
-            let result = MemBlock::Signed32(val % other);
+            let result = MemBlock::Signed32(val.wrapping_rem(other));
             info!("Result: {result}");
             result
         } else if let Some(val) = self.get_float() {
@@ -266,6 +291,7 @@ impl MemBlock {
     }
 
     // there has to be a better way to do this...look into later
+    #[must_use]
     pub fn right_shift_register(&mut self, conts: MemBlock) -> Self {
         info!("Right shift register: {self} >> {}", conts);
         if let Some(val) = self.get_unsigned() {
@@ -290,6 +316,7 @@ impl MemBlock {
     }
 
     // there has to be a better way to do this...look into later
+    #[must_use]
     pub fn xor_register(&mut self, conts: MemBlock) -> Self {
         info!("XOR register: {self} ^ {}", conts);
         if let Some(val) = self.get_unsigned() {
@@ -314,6 +341,7 @@ impl MemBlock {
     }
 
     // there has to be a better way to do this...look into later
+    #[must_use]
     pub fn and_register(&mut self, conts: MemBlock) -> Self {
         info!("AND register: {self} & {}", conts);
         if let Some(val) = self.get_unsigned() {
@@ -338,6 +366,7 @@ impl MemBlock {
     }
 
     // there has to be a better way to do this...look into later
+    #[must_use]
     pub fn or_register(&mut self, conts: MemBlock) -> Self {
         info!("OR register: {self} | {}", conts);
         if let Some(val) = self.get_unsigned() {
@@ -2,37 +2,140 @@
 
 use std::collections::{HashMap, VecDeque};
 use std::fmt::Display;
+use std::ops::Range;
 
+use crate::config::config::LevelConfig;
 use crate::memory::memory_block::MemBlock;
 use crate::memory::memory_line::MemLine;
 use crate::memory::memory_system::{
-    LoadRequest, LoadResponse, MemRequest, MemResponse, MEM_BLOCK_WIDTH,
+    LoadRequest, LoadResponse, MemRequest, MemResponse, ReplacementPolicy, WritePolicy,
+    MEM_BLOCK_WIDTH,
 };
-use crate::system::system::Cycle;
+use crate::system::system::{Cycle, PipelineStage};
 
 use anyhow::{anyhow, Result};
 use log::{error, info};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
-#[derive(Debug, Clone, Default)]
+// Fixed so that runs using random replacement are reproducible
+const RANDOM_REPLACEMENT_SEED: u64 = 0x6769_6767_6c65;
+
+/// Book-keeping used by the replacement policies, one per line
+#[derive(Debug, Clone, Copy, Default)]
+struct LineUsage {
+    last_used: u64,
+    inserted: u64,
+}
+
+/// Performance counters for a single memory level
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LevelStats {
+    pub load_hits: usize,
+    pub load_misses: usize,
+    pub stores: usize,
+    pub invalidations: usize,
+    pub evictions: usize,
+    pub writebacks: usize, // dirty evictions
+    pub completed_reqs: usize,
+    pub wait_cycles: usize, // summed over every request queued or in service
+}
+
+impl LevelStats {
+    /// Returns the fraction of loads that hit in the level, if any were issued
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn hit_rate(&self) -> Option<f64> {
+        let loads = self.load_hits + self.load_misses;
+        (loads != 0).then(|| self.load_hits as f64 / loads as f64)
+    }
+
+    /// Returns the average number of cycles a request spent at the level
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn avg_wait_cycles(&self) -> Option<f64> {
+        (self.completed_reqs != 0).then(|| self.wait_cycles as f64 / self.completed_reqs as f64)
+    }
+}
+
+impl Display for LevelStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let hit_rate = self
+            .hit_rate()
+            .map_or_else(|| "N/A".to_string(), |rate| format!("{:.2}%", rate * 100.0));
+        let avg_wait = self
+            .avg_wait_cycles()
+            .map_or_else(|| "N/A".to_string(), |wait| format!("{wait:.2} cycles"));
+        writeln!(
+            f,
+            "Load Hits: {}, Load Misses: {}, Hit Rate: {hit_rate}",
+            self.load_hits, self.load_misses
+        )?;
+        writeln!(
+            f,
+            "Stores: {}, Invalidations: {}, Evictions: {}, Write-Backs: {}",
+            self.stores, self.invalidations, self.evictions, self.writebacks
+        )?;
+        write!(
+            f,
+            "Completed Requests: {}, Average Wait: {avg_wait}",
+            self.completed_reqs
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct MemoryLevel {
-    contents: Vec<MemLine>,
+    contents: Vec<MemLine>, // stored set by set, each set holding `ways` lines
+    usage: Vec<LineUsage>,
     pub reqs: VecDeque<MemRequest>,
     pub curr_reqs: HashMap<MemRequest, usize>,
     latency: Cycle,
     is_main: bool,
     line_len: usize,
+    ways: usize,
+    replacement: ReplacementPolicy,
+    write_policy: WritePolicy,
+    accesses: u64,
+    busy: Cycle, // cycles left servicing write-backs from the level above
+    rng: StdRng,
+    pub stats: LevelStats,
 }
 
 impl Display for MemoryLevel {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let conts = self
-            .contents
-            .iter()
-            .fold(String::new(), |accum, line| accum + &format!("{line}\n"));
+        let conts = if self.is_main {
+            self.contents
+                .iter()
+                .fold(String::new(), |accum, line| accum + &format!("{line}\n"))
+        } else {
+            self.contents.chunks(self.ways).enumerate().fold(
+                String::new(),
+                |accum, (set, lines)| {
+                    let ways = lines
+                        .iter()
+                        .enumerate()
+                        .fold(String::new(), |accum, (way, line)| {
+                            accum + &format!("    Way {way}: {line}\n")
+                        });
+                    accum + &format!("Set {set}:\n{ways}")
+                },
+            )
+        };
+        if !self.is_main {
+            writeln!(
+                f,
+                "Sets: {}, Ways: {}, Replacement: {}, Write Policy: {}",
+                self.num_sets(),
+                self.ways,
+                self.replacement,
+                self.write_policy
+            )?;
+        }
         write!(
             f,
-            "Latency: {}\nRequest Queue: {:?}\nCurrent Request: {:?}\n\nContents:\n{}",
-            self.latency, self.reqs, self.curr_reqs, conts
+            "Latency: {}\n{}\nRequest Queue: {:?}\nCurrent Request: {:?}\n\nContents:\n{}",
+            self.latency, self.stats, self.reqs, self.curr_reqs, conts
         )?;
 
         Ok(())
@@ -40,24 +143,39 @@ impl Display for MemoryLevel {
 }
 
 impl MemoryLevel {
-    /// Creates a new `MemoryLevel` instances with `n_lines` lines, each
-    /// consisting of `line_len` `MEM_BLOCK_WIDTH` bit blocks
-    pub fn new(n_lines: usize, line_len: usize, latency: Cycle, is_main: bool) -> Self {
-        assert!(n_lines != 0, "Constructing empty memory level");
+    /// Creates a new `MemoryLevel` instance as described by `config`, with
+    /// each line consisting of `line_len` `MEM_BLOCK_WIDTH` bit blocks
+    pub fn new(config: &LevelConfig, line_len: usize, is_main: bool) -> Self {
+        assert!(config.capacity != 0, "Constructing empty memory level");
+        assert!(
+            config.associativity != 0 && config.capacity % config.associativity == 0,
+            "Associativity must evenly divide the number of lines"
+        );
 
         Self {
-            contents: vec![MemLine::new(None, line_len); n_lines],
-            latency,
+            contents: vec![MemLine::new(None, line_len); config.capacity],
+            usage: vec![LineUsage::default(); config.capacity],
+            latency: config.latency,
             reqs: VecDeque::new(),
             curr_reqs: HashMap::new(),
             is_main,
             line_len,
+            ways: config.associativity,
+            replacement: config.replacement,
+            write_policy: config.write_policy,
+            accesses: 0,
+            busy: 0,
+            rng: StdRng::seed_from_u64(RANDOM_REPLACEMENT_SEED),
+            stats: LevelStats::default(),
         }
     }
 
     // for testing/ debugging
     pub fn force_store(&mut self, address: usize, data: MemBlock) {
-        let idx = self.address_index(address);
+        let Some(idx) = self.find_line(address) else {
+            error!("force_store: address {address} is not present");
+            return;
+        };
         if let Err(e) = self.contents[idx].write(address, data) {
             error!(
                 "force_store: write to {address} with {:?} failed -- error {e}",
@@ -68,34 +186,30 @@ impl MemoryLevel {
 
     // for testing/ debugging
     pub fn force_load(&self, address: usize) -> Option<MemBlock> {
-        let idx = self.address_index(address);
-        let conts = &self.contents[idx];
-        conts.get_contents(address)
+        let idx = self.find_line(address)?;
+        self.contents[idx].get_contents(address)
     }
 
     /// Issues a new load request, or checks the status of an existing (matching)
     /// load request
     pub fn load(&mut self, req: &LoadRequest) -> MemResponse {
-        // this is bad, not actual address...
-        let address = req.address % (self.contents.len() * self.line_len * MEM_BLOCK_WIDTH);
-        let line_idx = self.address_index(address);
         info!("Queue: {:?}", self.reqs);
-
-        //if !self.is_main && !self.contents[line_idx].contains_address(address) {
-        if !self.is_main && !self.contents[line_idx].contains_address(req.address) {
+        if self.find_line(req.address).is_none() {
             return MemResponse::Miss;
         }
         let mem_req = MemRequest::from(req.clone());
         match self.curr_reqs.get(&mem_req) {
             Some(0) => {
                 info!("Load request completed, request: {:?}", mem_req);
-                let data = self.contents[line_idx].clone();
-                if !data.contains_address(req.address) {
+                let Some(line_idx) = self.find_line(req.address) else {
                     info!("Cache line removed since request was issued.");
                     return MemResponse::Miss;
-                }
+                };
+                self.touch(line_idx);
+                let data = self.contents[line_idx].clone();
 
                 self.curr_reqs.remove(&mem_req);
+                self.stats.completed_reqs += 1;
                 if !self.curr_reqs.iter().any(|(_req, delay)| *delay > 0) {
                     if let Some(next_req) = self.reqs.pop_front() {
                         info!(
@@ -114,9 +228,12 @@ impl MemoryLevel {
                 info!("New load request, inserting");
                 if !self.curr_reqs.iter().any(|(_req, delay)| *delay > 0) {
                     if let Some(next_req) = self.reqs.pop_front() {
+                        // the request may already be the one waiting in the queue
+                        if next_req != mem_req && !self.reqs.contains(&mem_req) {
+                            self.reqs.push_back(mem_req);
+                            info!("Other pending requests, new load request inserted into queue");
+                        }
                         self.curr_reqs.insert(next_req, self.latency);
-                        self.reqs.push_back(mem_req);
-                        info!("Other pending requests, new load request inserted into queue");
                     } else {
                         self.curr_reqs.insert(mem_req, self.latency);
                         info!("New load request is being servied");
@@ -135,10 +252,47 @@ impl MemoryLevel {
         MemResponse::Wait
     }
 
-    /// Returns the index of the internal Vec of `MemLine`s that would contain
-    /// the supplied `address`
+    /// Returns the index of the set that would contain the supplied `address`
     pub fn address_index(&self, address: usize) -> usize {
-        (address / (self.line_len * MEM_BLOCK_WIDTH)) % self.num_lines()
+        (address / (self.line_len * MEM_BLOCK_WIDTH)) % self.num_sets()
+    }
+
+    /// Returns the range of indices into the internal Vec of `MemLine`s making
+    /// up the set that would contain the supplied `address`
+    fn set_range(&self, address: usize) -> Range<usize> {
+        let start = self.address_index(address) * self.ways;
+        start..start + self.ways
+    }
+
+    /// Returns the index of the line currently holding `address`, if any
+    fn find_line(&self, address: usize) -> Option<usize> {
+        self.set_range(address)
+            .find(|&idx| self.contents[idx].contains_address(address))
+    }
+
+    /// Picks the line to be replaced in the set that would contain `address`,
+    /// preferring empty lines over evicting valid ones
+    fn victim_line(&mut self, address: usize) -> usize {
+        let set = self.set_range(address);
+        if let Some(idx) = set
+            .clone()
+            .find(|&idx| self.contents[idx].start_address().is_none())
+        {
+            return idx;
+        }
+
+        match self.replacement {
+            ReplacementPolicy::Lru => set.min_by_key(|&idx| self.usage[idx].last_used),
+            ReplacementPolicy::Fifo => set.min_by_key(|&idx| self.usage[idx].inserted),
+            ReplacementPolicy::Random => Some(self.rng.gen_range(set)),
+        }
+        .unwrap()
+    }
+
+    /// Marks the line at `idx` as the most recently used in its set
+    fn touch(&mut self, idx: usize) {
+        self.accesses += 1;
+        self.usage[idx].last_used = self.accesses;
     }
 
     /// Removes any cache entries containing the given `address`
@@ -148,33 +302,107 @@ impl MemoryLevel {
             return;
         }
 
-        let line = self.address_index(address);
-        self.contents[line] = MemLine::new(None, self.line_len);
+        if let Some(line) = self.find_line(address) {
+            self.contents[line] = MemLine::new(None, self.line_len);
+            self.stats.invalidations += 1;
+        }
     }
 
-    /// Writes a single word to the appropriate address within the line
+    /// Indicates whether the level holds a line containing `address`
+    pub fn contains_address(&self, address: usize) -> bool {
+        self.find_line(address).is_some()
+    }
+
+    /// Returns a copy of the line containing `address`, if present
+    pub fn get_line(&self, address: usize) -> Option<MemLine> {
+        self.find_line(address)
+            .map(|idx| self.contents[idx].clone())
+    }
+
+    /// Writes a single word to the appropriate address within the line,
+    /// marking the line dirty if the level is write-back
     pub fn write_block(&mut self, address: usize, data: MemBlock) -> Result<()> {
-        let line_idx = self.address_index(address);
-        self.contents[line_idx].write(address, data)
+        let Some(line_idx) = self.find_line(address) else {
+            return Err(anyhow!("Address not contained within memory level"));
+        };
+        self.touch(line_idx);
+        self.contents[line_idx].write(address, data)?;
+        if self.is_write_back() {
+            self.contents[line_idx].set_dirty(true);
+        }
+
+        Ok(())
     }
 
-    /// Writes an entire line to the appropriate address within the line
-    /// `address` must match the starting address of the line
-    pub fn write_line(&mut self, address: usize, data: &MemLine) -> Result<()> {
-        let line_idx = self.address_index(address);
+    /// Writes an entire line to the appropriate set, replacing the line already
+    /// holding `address` or evicting one according to the replacement policy.
+    /// `address` must match the starting address of the line. Returns the
+    /// evicted line if it was dirty and must be written back
+    pub fn write_line(&mut self, address: usize, data: &MemLine) -> Result<Option<MemLine>> {
         // check start address is aligned, if provided
         if let Some(start_addr) = data.start_address() {
             if start_addr % (self.line_len * MEM_BLOCK_WIDTH) != 0 {
                 return Err(anyhow!("Invalid start address for line"));
             }
         }
-        self.contents[line_idx] = data.clone();
+        let line_idx = if let Some(idx) = self.find_line(address) {
+            idx
+        } else {
+            let idx = self.victim_line(address);
+            self.usage[idx].inserted = self.accesses + 1;
+            idx
+        };
+        self.touch(line_idx);
+        let evicted = std::mem::replace(&mut self.contents[line_idx], data.clone());
 
-        Ok(())
+        if evicted.start_address().is_none() || evicted.start_address() == data.start_address() {
+            return Ok(None);
+        }
+        self.stats.evictions += 1;
+        if evicted.is_dirty() {
+            info!("Evicting dirty line {evicted}");
+            self.stats.writebacks += 1;
+            Ok(Some(evicted))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Stalls the level's request queue for `cycles` clock cycles while it
+    /// services a write-back
+    pub fn add_busy_cycles(&mut self, cycles: Cycle) {
+        self.busy += cycles;
+    }
+
+    /// Indicates whether stores should be kept in the level until eviction
+    pub fn is_write_back(&self) -> bool {
+        !self.is_main && self.write_policy == WritePolicy::WriteBack
+    }
+
+    /// Drops the queued and in-progress requests issued by `issuer`, moving
+    /// the next queued request into service if that frees up the level
+    pub fn clear_reqs_from(&mut self, issuer: PipelineStage) {
+        self.reqs.retain(|req| req.get_issuer() != issuer);
+        self.curr_reqs.retain(|req, _| req.get_issuer() != issuer);
+        if !self.curr_reqs.values().any(|&latency| latency > 0) {
+            if let Some(next_req) = self.reqs.pop_front() {
+                self.curr_reqs.insert(next_req, self.latency);
+            }
+        }
     }
 
     /// Decrements the latency count for the pending request
     pub fn update_clock(&mut self) {
+        self.stats.wait_cycles += self.reqs.len()
+            + self
+                .curr_reqs
+                .values()
+                .filter(|&&latency| latency > 0)
+                .count();
+        if self.busy > 0 {
+            self.busy -= 1;
+            return;
+        }
         for (req, latency) in self.curr_reqs.iter_mut() {
             *latency = latency.saturating_sub(1);
         }
@@ -192,4 +420,118 @@ impl MemoryLevel {
     pub fn num_lines(&self) -> usize {
         self.contents.len()
     }
+
+    /// Returns the number of sets in the memory level
+    pub fn num_sets(&self) -> usize {
+        self.contents.len() / self.ways
+    }
+
+    /// Returns the number of lines in each set
+    pub fn associativity(&self) -> usize {
+        self.ways
+    }
+
+    /// Returns the policy used to pick which line of a full set is evicted
+    pub fn replacement_policy(&self) -> ReplacementPolicy {
+        self.replacement
+    }
+
+    /// Returns the policy used to handle stores
+    pub fn write_policy(&self) -> WritePolicy {
+        self.write_policy
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::config::config::LevelConfig;
+    use crate::memory::memory_block::MemBlock;
+    use crate::memory::memory_level::MemoryLevel;
+    use crate::memory::memory_line::MemLine;
+    use crate::memory::memory_system::{ReplacementPolicy, WritePolicy, MEM_BLOCK_WIDTH};
+
+    const LINE_LEN: usize = 4;
+    const LINE_BITS: usize = LINE_LEN * MEM_BLOCK_WIDTH;
+
+    fn get_test_level(n_lines: usize, ways: usize, replacement: ReplacementPolicy) -> MemoryLevel {
+        let config = LevelConfig {
+            associativity: ways,
+            replacement,
+            write_policy: WritePolicy::WriteBack,
+            ..LevelConfig::new(n_lines, 1)
+        };
+        MemoryLevel::new(&config, LINE_LEN, false)
+    }
+
+    fn insert_line(level: &mut MemoryLevel, address: usize) {
+        level
+            .write_line(address, &MemLine::new(Some(address), LINE_LEN))
+            .unwrap();
+    }
+
+    #[test]
+    fn conflicting_lines_share_a_set() {
+        // 2 sets of 2 ways, so lines 0, 2, and 4 all map to set 0
+        let mut level = get_test_level(4, 2, ReplacementPolicy::Lru);
+        insert_line(&mut level, 0);
+        insert_line(&mut level, 2 * LINE_BITS);
+        assert!(level.find_line(0).is_some());
+        assert!(level.find_line(2 * LINE_BITS).is_some());
+
+        insert_line(&mut level, 4 * LINE_BITS);
+        assert!(level.find_line(0).is_none());
+        assert!(level.find_line(4 * LINE_BITS).is_some());
+        // set 1 is untouched
+        assert!(level.contents[2..]
+            .iter()
+            .all(|line| line.start_address().is_none()));
+    }
+
+    #[test]
+    fn lru_evicts_least_recently_used() {
+        let mut level = get_test_level(2, 2, ReplacementPolicy::Lru);
+        insert_line(&mut level, 0);
+        insert_line(&mut level, LINE_BITS);
+        // use the first line again, so the second becomes the LRU one
+        let idx = level.find_line(0).unwrap();
+        level.touch(idx);
+
+        insert_line(&mut level, 2 * LINE_BITS);
+        assert!(level.find_line(0).is_some());
+        assert!(level.find_line(LINE_BITS).is_none());
+    }
+
+    #[test]
+    fn fifo_evicts_oldest_insertion() {
+        let mut level = get_test_level(2, 2, ReplacementPolicy::Fifo);
+        insert_line(&mut level, 0);
+        insert_line(&mut level, LINE_BITS);
+        // recent use doesn't matter for FIFO
+        let idx = level.find_line(0).unwrap();
+        level.touch(idx);
+
+        insert_line(&mut level, 2 * LINE_BITS);
+        assert!(level.find_line(0).is_none());
+        assert!(level.find_line(LINE_BITS).is_some());
+    }
+
+    #[test]
+    fn evicts_dirty_lines() {
+        let mut level = get_test_level(1, 1, ReplacementPolicy::Lru);
+        insert_line(&mut level, 0);
+        level.write_block(0, MemBlock::Unsigned32(7)).unwrap();
+
+        let evicted = level
+            .write_line(LINE_BITS, &MemLine::new(Some(LINE_BITS), LINE_LEN))
+            .unwrap()
+            .expect("Dirty line wasn't returned");
+        assert!(evicted.is_dirty());
+        assert_eq!(evicted.get_contents(0), Some(MemBlock::Unsigned32(7)));
+
+        // clean lines are dropped silently
+        let evicted = level
+            .write_line(0, &MemLine::new(Some(0), LINE_LEN))
+            .unwrap();
+        assert!(evicted.is_none());
+    }
 }
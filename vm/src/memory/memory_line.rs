@@ -12,6 +12,7 @@ use log::error;
 pub struct MemLine {
     start_addr: Option<usize>,
     data: Vec<MemBlock>,
+    dirty: bool, // modified since being brought into a write-back level
 }
 
 impl MemLine {
@@ -22,9 +23,21 @@ impl MemLine {
         Self {
             start_addr,
             data: vec![MemBlock::default(); line_len],
+            dirty: false,
         }
     }
 
+    /// Indicates whether the line has been modified without being written back
+    /// to the next memory level
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Sets or clears the line's dirty bit
+    pub fn set_dirty(&mut self, dirty: bool) {
+        self.dirty = dirty;
+    }
+
     /// Returns the address of the first block in the line
     pub fn start_address(&self) -> Option<usize> {
         self.start_addr
@@ -78,6 +91,9 @@ impl Display for MemLine {
         } else {
             write!(f, "<<No Entry>>:{blocks}")?; // Extra '<' and '>' to align with addresses
         }
+        if self.dirty {
+            write!(f, " (dirty)")?;
+        }
 
         Ok(())
     }
@@ -1,14 +1,21 @@
 #![warn(clippy::all, clippy::pedantic)]
 #![allow(clippy::module_name_repetitions)]
 use std::borrow::Cow;
+use std::collections::HashSet;
+use std::fmt::Display;
 
+use crate::config::config::LevelConfig;
+use crate::memory::console::Console;
 pub use crate::memory::memory_block::MemBlock;
+pub use crate::memory::memory_level::LevelStats;
 use crate::memory::memory_level::MemoryLevel;
 use crate::memory::memory_line::MemLine;
-use crate::system::system::{Cycle, PipelineStage};
+use crate::system::system::PipelineStage;
+use crate::system::trap::TrapCause;
 
 use anyhow::{anyhow, Result};
 use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
 
 pub const MEM_BLOCK_WIDTH: usize = 32;
 #[allow(dead_code)]
@@ -28,6 +35,48 @@ pub enum MemType {
     Float32,
 }
 
+/// Policy used to pick which line of a full cache set gets evicted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReplacementPolicy {
+    #[default]
+    Lru,
+    Fifo,
+    Random,
+}
+
+impl Display for ReplacementPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Lru => write!(f, "LRU"),
+            Self::Fifo => write!(f, "FIFO"),
+            Self::Random => write!(f, "Random"),
+        }
+    }
+}
+
+/// How a cache level handles stores
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WritePolicy {
+    /// Stores go straight through to the next level, and cached copies are
+    /// invalidated (no-allocate)
+    #[default]
+    WriteThrough,
+    /// Stores are kept in the level and written to the next level on eviction,
+    /// lines are brought in on a store miss (write-allocate)
+    WriteBack,
+}
+
+impl Display for WritePolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::WriteThrough => write!(f, "Write-Through"),
+            Self::WriteBack => write!(f, "Write-Back"),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct LoadRequest {
     pub issuer: PipelineStage,
@@ -62,12 +111,22 @@ impl From<StoreRequest> for MemRequest {
 
 impl MemRequest {
     /// Returns the address associated with a given request
+    #[must_use]
     pub fn get_address(&self) -> usize {
         match self {
             MemRequest::Load(req) => req.address,
             MemRequest::Store(req) => req.address,
         }
     }
+
+    /// Returns the pipeline stage that issued the request
+    #[must_use]
+    pub fn get_issuer(&self) -> PipelineStage {
+        match self {
+            MemRequest::Load(req) => req.issuer,
+            MemRequest::Store(req) => req.issuer,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
@@ -86,40 +145,97 @@ pub enum MemResponse {
     StoreComplete,
 }
 
+/// Identifies a memory level along a request's path
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LevelId {
+    ICache,
+    Level(usize),
+}
+
+impl Display for LevelId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ICache => write!(f, "I-cache"),
+            Self::Level(level) => write!(f, "{level}"),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Memory {
     levels: Vec<MemoryLevel>,
-    line_len: usize, // number of MEM_BLOCK_WIDTH-bit words in a cache line
+    icache: Option<MemoryLevel>, // level 0 only serves data accesses when present
+    line_len: usize,             // number of MEM_BLOCK_WIDTH-bit words in a cache line
+    counted_loads: HashSet<LoadRequest>, // in-flight loads already tallied in the stats
+    console: Console,            // mapped above main memory, bypasses the caches
 }
 
 #[allow(clippy::module_name_repetitions)]
 impl Memory {
     /// Construct a new `Memory` object, with cache lines of `line_len`
-    /// MEM_BLOCK_WIDTH-bit words, and capacities (in number of lines) and latencies
-    /// (in terms of clock cycles) specified
-    pub fn new(line_len: usize, capacities: &[usize], latencies: &[Cycle]) -> Self {
-        assert!(
-            !capacities.is_empty(),
-            "Attempted to construct empty memory"
-        );
-        assert!(
-            capacities.len() == latencies.len(),
-            "{} capacities specified, {} latencies specified",
-            capacities.len(),
-            latencies.len()
-        );
+    /// MEM_BLOCK_WIDTH-bit words, and the capacity (in number of lines), latency
+    /// (in terms of clock cycles), and associativity of each level specified.
+    /// Supplying an `icache` splits level 0 into separate instruction and data
+    /// caches
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there are no levels, lines are empty, a level's
+    /// configuration is invalid, main memory is associative or write-back, or
+    /// an `icache` is supplied without a data cache to go with it
+    pub fn new(
+        line_len: usize,
+        levels: &[LevelConfig],
+        icache: Option<&LevelConfig>,
+    ) -> Result<Self> {
+        let Some(main_config) = levels.last() else {
+            return Err(anyhow!("Attempted to construct empty memory"));
+        };
+        if line_len == 0 {
+            return Err(anyhow!(
+                "Attempted to construct memory with empty cache lines"
+            ));
+        }
+        for (level, config) in levels.iter().enumerate() {
+            config.validate(&format!("Memory level {level}"))?;
+        }
+        if main_config.associativity != 1 {
+            return Err(anyhow!("Main memory must have an associativity of 1"));
+        }
+        if main_config.write_policy == WritePolicy::WriteBack {
+            return Err(anyhow!("Main memory can't use a write-back policy"));
+        }
+        if let Some(config) = icache {
+            config.validate("I-cache")?;
+            if levels.len() < 2 {
+                return Err(anyhow!(
+                    "Attempted to construct a split I-cache without a data cache"
+                ));
+            }
+        }
 
-        let n_levels = capacities.len();
+        let n_levels = levels.len();
         let mut mem = Memory {
             levels: Vec::new(),
+            icache: icache.map(|config| {
+                info!(
+                    "Creating I-cache with {} lines ({}-way, {} replacement) and a latency of {} cycles",
+                    config.capacity, config.associativity, config.replacement, config.latency
+                );
+                MemoryLevel::new(config, line_len, false)
+            }),
             line_len,
+            counted_loads: HashSet::new(),
+            console: Console::default(),
         };
 
         let mut last_size = 0;
         let mut last_latency = 0;
-        for (level, (&size, &latency)) in capacities.iter().zip(latencies.iter()).enumerate() {
+        for (level, config) in levels.iter().enumerate() {
+            let (size, latency) = (config.capacity, config.latency);
             info!(
-                "Creating memory level {level} with {size} lines and a latency of {latency} cycles"
+                "Creating memory level {level} with {size} lines ({}-way, {} replacement) and a latency of {latency} cycles",
+                config.associativity, config.replacement
             );
             if size < last_size {
                 warn!("Decreasing memory size with increasing level: Level {}: {last_size}, Level {level}: {size}", level - 1);
@@ -128,12 +244,8 @@ impl Memory {
                 warn!("Decreasing memory latency with increasing level: Level {}: {last_latency}, Level {level}: {latency}", level - 1);
             }
 
-            mem.levels.push(MemoryLevel::new(
-                size,
-                line_len,
-                latency,
-                level == n_levels - 1,
-            ));
+            mem.levels
+                .push(MemoryLevel::new(config, line_len, level == n_levels - 1));
             last_size = size;
             last_latency = latency;
         }
@@ -143,18 +255,17 @@ impl Memory {
             mem.levels.len() - 1
         );
 
-        let main_mem = mem.levels.last_mut().unwrap();
+        let main_mem = &mut mem.levels[n_levels - 1];
         let mut start_addr = 0usize;
-        for _ in 0..*capacities.last().unwrap() {
-            main_mem
-                .write_line(start_addr, &MemLine::new(Some(start_addr), line_len))
-                .unwrap();
+        for _ in 0..main_config.capacity {
+            main_mem.write_line(start_addr, &MemLine::new(Some(start_addr), line_len))?;
             start_addr += MEM_BLOCK_WIDTH * line_len;
         }
 
-        mem
+        Ok(mem)
     }
 
+    #[must_use]
     pub fn get_line_len(&self) -> usize {
         self.line_len
     }
@@ -162,10 +273,20 @@ impl Memory {
     /// Clears *ALL* request queues and pending results
     /// Used along with a squash in the pipeline
     pub fn clear_reqs(&mut self) {
-        for level in self.levels.iter_mut() {
+        for level in self.levels.iter_mut().chain(self.icache.as_mut()) {
             level.curr_reqs.clear();
             level.reqs.clear();
         }
+        self.counted_loads.clear();
+    }
+
+    /// Drops the outstanding requests issued by `issuer`, e.g. fetches down a
+    /// mispredicted path
+    pub fn clear_reqs_from(&mut self, issuer: PipelineStage) {
+        for level in self.levels.iter_mut().chain(self.icache.as_mut()) {
+            level.clear_reqs_from(issuer);
+        }
+        self.counted_loads.retain(|req| req.issuer != issuer);
     }
 
     /// Manually set the values of an individual address to main memory
@@ -174,9 +295,23 @@ impl Memory {
         self.levels[main_level_idx].force_store(address, data);
     }
 
+    /// Manually read the current value of an individual address, bypassing
+    /// the request queues. Cached copies take precedence over main memory, as
+    /// write-back levels may hold data main memory hasn't seen yet
+    #[must_use]
+    pub fn force_load(&self, address: usize) -> Option<MemBlock> {
+        self.levels
+            .iter()
+            .find_map(|level| level.force_load(address))
+    }
+
     #[allow(dead_code)]
     // Remove if necessary
     /// Returns the number of bits in the provided memory level
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `level` isn't one of the memory's levels
     pub fn get_capacity(&self, level: usize) -> Result<usize> {
         if level >= self.levels.len() {
             Err(anyhow!("Checked capacity of invalid memory level: {level}"))
@@ -186,6 +321,10 @@ impl Memory {
     }
 
     /// Returns the latency of the provided memory level in clock cycles
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `level` isn't one of the memory's levels
     pub fn get_latency(&self, level: usize) -> Result<usize> {
         if level >= self.levels.len() {
             Err(anyhow!("Checked latency of invalid memory level: {level}"))
@@ -195,7 +334,11 @@ impl Memory {
     }
 
     // Convenience function
-    // Returns the latency of the system's main memory in terms of clock cycles
+    /// Returns the latency of the system's main memory in terms of clock cycles
+    ///
+    /// # Errors
+    ///
+    /// Doesn't return an error, as there's always a main memory level
     pub fn main_latency(&self) -> Result<usize> {
         self.get_latency(self.levels.len() - 1)
     }
@@ -203,11 +346,68 @@ impl Memory {
     #[allow(dead_code)]
     // Convenience method
     /// Returns the capacity of the system's main memory in bits
+    ///
+    /// # Errors
+    ///
+    /// Doesn't return an error, as there's always a main memory level
     pub fn main_capacity(&self) -> Result<usize> {
         self.get_capacity(self.levels.len() - 1)
     }
 
+    /// Returns the performance counters for the provided memory level
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `level` isn't one of the memory's levels
+    pub fn level_stats(&self, level: usize) -> Result<LevelStats> {
+        if level >= self.levels.len() {
+            Err(anyhow!("Checked stats of invalid memory level: {level}"))
+        } else {
+            Ok(self.levels[level].stats)
+        }
+    }
+
+    /// Returns the performance counters for the I-cache, if present
+    #[must_use]
+    pub fn icache_stats(&self) -> Option<LevelStats> {
+        self.icache.as_ref().map(|icache| icache.stats)
+    }
+
+    /// Indicates whether level 0 is split into separate instruction and data
+    /// caches
+    #[must_use]
+    pub fn has_icache(&self) -> bool {
+        self.icache.is_some()
+    }
+
+    /// Returns the memory-mapped console device
+    #[must_use]
+    pub fn console(&self) -> &Console {
+        &self.console
+    }
+
+    pub fn console_mut(&mut self) -> &mut Console {
+        &mut self.console
+    }
+
+    /// Returns the exception an access to `address` would raise, if any
+    #[must_use]
+    pub fn check_access(&self, address: usize) -> Option<TrapCause> {
+        if !address.is_multiple_of(MEM_BLOCK_WIDTH) {
+            Some(TrapCause::MisalignedAccess)
+        } else if address >= self.main_capacity().unwrap_or_default() && !Console::contains(address)
+        {
+            Some(TrapCause::AddressOutOfRange)
+        } else {
+            None
+        }
+    }
+
     /// Returns the number of lines for a given memory level
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `level` isn't one of the memory's levels
     pub fn num_lines(&self, level: usize) -> Result<usize> {
         if level >= self.levels.len() {
             Err(anyhow!(
@@ -221,12 +421,61 @@ impl Memory {
     /// Process a load request
     fn load(&mut self, req: &LoadRequest) -> Result<MemResponse> {
         info!("Processing load request: {:?}", req);
-        if req.address % MEM_BLOCK_WIDTH != 0 {
-            return Err(anyhow!("Unaligned load access: {}", req.address));
+        if let Some(cause) = self.check_access(req.address) {
+            return Err(anyhow::Error::new(cause).context(format!("Load from {}", req.address)));
+        }
+        if Console::contains(req.address) {
+            if req.issuer == PipelineStage::Fetch {
+                return Err(anyhow::Error::new(TrapCause::AddressOutOfRange)
+                    .context(format!("Instruction fetch from console {}", req.address)));
+            }
+            let mut line = MemLine::new(Some(req.address), 1);
+            line.write(req.address, self.console.load(req.address))?;
+            return Ok(MemResponse::Load(LoadResponse { data: line }));
         }
 
-        for level in 0..self.levels.len() {
-            let resp = self.levels[level].load(req);
+        let path = self.access_path(req.issuer);
+        let (hit_idx, resp) = self.load_line(req, &path)?;
+        // requests are re-issued every cycle until they complete, so only tally
+        // the first time one is seen
+        if self.counted_loads.insert(req.clone()) {
+            for &level in &path[..hit_idx] {
+                self.level_mut(level).stats.load_misses += 1;
+            }
+            self.level_mut(path[hit_idx]).stats.load_hits += 1;
+        }
+        if let MemResponse::Load(_) = resp {
+            self.counted_loads.remove(req);
+        }
+
+        Ok(resp)
+    }
+
+    /// Returns the levels a load issued by `issuer` passes through, in order.
+    /// Fetches go through the I-cache in place of level 0 if there is one
+    fn access_path(&self, issuer: PipelineStage) -> Vec<LevelId> {
+        let first_shared = usize::from(self.icache.is_some() && issuer == PipelineStage::Fetch);
+        let shared = (first_shared..self.levels.len()).map(LevelId::Level);
+        if first_shared == 0 {
+            shared.collect()
+        } else {
+            std::iter::once(LevelId::ICache).chain(shared).collect()
+        }
+    }
+
+    fn level_mut(&mut self, level: LevelId) -> &mut MemoryLevel {
+        match level {
+            LevelId::ICache => self.icache.as_mut().expect("No I-cache present"),
+            LevelId::Level(level) => &mut self.levels[level],
+        }
+    }
+
+    /// Walks down the memory levels in `path` until one holding the requested
+    /// line is found, returning that level's index within `path` along with its
+    /// response
+    fn load_line(&mut self, req: &LoadRequest, path: &[LevelId]) -> Result<(usize, MemResponse)> {
+        for (idx, &level) in path.iter().enumerate() {
+            let resp = self.level_mut(level).load(req);
             match resp {
                 MemResponse::Miss => {
                     info!("Cache miss at level {level} for request: {:?}", req);
@@ -234,12 +483,12 @@ impl Memory {
                 }
                 MemResponse::Wait => {
                     info!("Wait response at level {level}, for request: {:?}", req);
-                    return Ok(resp);
+                    return Ok((idx, resp));
                 }
                 MemResponse::Load(ref data) => {
                     info!("Data returned: {:?}, for request: {:?}", data, req);
-                    self.populate_cache(level.saturating_sub(1), &data.data)?;
-                    return Ok(resp);
+                    self.populate_cache(&path[..idx], &data.data)?;
+                    return Ok((idx, resp));
                 }
                 MemResponse::StoreComplete => {
                     error!(
@@ -256,58 +505,93 @@ impl Memory {
         unreachable!()
     }
 
-    // Because we're using a write-through no-allocate scheme, we ONLY allow stores
-    // to the main memory
-    /// Store a value in the system's main memory
+    /// Returns the level a store to `address` is written into: the first
+    /// write-back level, or main memory if every cache level is write-through
+    fn store_level(&self) -> usize {
+        self.levels
+            .iter()
+            .position(MemoryLevel::is_write_back)
+            .unwrap_or(self.levels.len() - 1)
+    }
+
+    // Write-through levels are no-allocate, so stores are only ever written to the
+    // first write-back level or main memory. Any copies in the write-through
+    // levels above it are invalidated once the store completes
+    /// Store a value in the system's memory
     fn store(&mut self, req: &StoreRequest) -> Result<MemResponse> {
         info!("Processing store request: {:?}", req);
-        if req.address % MEM_BLOCK_WIDTH != 0 {
-            return Err(anyhow!("Unaligned store access: {:?}", req));
+        if let Some(cause) = self.check_access(req.address) {
+            return Err(anyhow::Error::new(cause).context(format!("Store to {}", req.address)));
+        }
+        if Console::contains(req.address) {
+            self.console.store(req.address, req.data);
+            return Ok(MemResponse::StoreComplete);
         }
 
-        // only use request queue for main memory
-        let main_mem = self.levels.last_mut().unwrap();
+        let target = self.store_level();
+        if !self.levels[target].contains_address(req.address) {
+            // write-allocate, bring the line in before writing to it
+            info!("Store miss at write-back level {target}, allocating line");
+            let load_req = LoadRequest {
+                issuer: req.issuer,
+                address: req.address,
+                width: MemType::Unsigned32,
+            };
+            let path = self.access_path(req.issuer);
+            match self.load_line(&load_req, &path)?.1 {
+                MemResponse::Load(LoadResponse { data }) => {
+                    if !self.levels[target].contains_address(req.address) {
+                        let mut line = data;
+                        line.set_dirty(false);
+                        self.install_line(target, &line)?;
+                    }
+                }
+                resp => return Ok(resp),
+            }
+        }
+
+        // only use request queue for the level being written to
+        let store_mem = &mut self.levels[target];
         let mem_req = MemRequest::from(req.clone());
-        match main_mem.curr_reqs.get(&mem_req) {
+        match store_mem.curr_reqs.get(&mem_req) {
             Some(0) => {
-                info!("Store request completed, request: {:?}", req);
+                info!("Store request completed at level {target}, request: {req:?}");
                 // actually write the data...
-                main_mem
-                    .write_block(req.address, req.data)
-                    .expect("Write failed -- Error {e}");
+                store_mem.write_block(req.address, req.data)?;
 
                 // book-keeping on request queue
                 info!("Popping head of request queue");
-                main_mem.curr_reqs.remove(&mem_req);
-                if !main_mem.curr_reqs.iter().any(|(_req, delay)| *delay > 0) {
-                    if let Some(next_req) = main_mem.reqs.pop_front() {
-                        info!(
-                            "Moving next pending request to the head, request: {:?}",
-                            next_req
-                        );
-                        main_mem.curr_reqs.insert(next_req, main_mem.latency());
+                store_mem.curr_reqs.remove(&mem_req);
+                store_mem.stats.stores += 1;
+                store_mem.stats.completed_reqs += 1;
+                if !store_mem.curr_reqs.iter().any(|(_req, delay)| *delay > 0) {
+                    if let Some(next_req) = store_mem.reqs.pop_front() {
+                        info!("Moving next pending request to the head, request: {next_req:?}");
+                        store_mem.curr_reqs.insert(next_req, store_mem.latency());
                     }
                 }
+                self.invalidate_address(target, req.address);
                 return Ok(MemResponse::StoreComplete);
             }
             Some(delay) => {
                 info!("Request pending: {delay} cycles left");
             }
             None => {
-                if !main_mem.curr_reqs.iter().any(|(_req, delay)| *delay > 0) {
-                    if let Some(next_req) = main_mem.reqs.pop_front() {
-                        main_mem.curr_reqs.insert(next_req, main_mem.latency());
-                        main_mem.reqs.push_back(mem_req);
+                if store_mem.curr_reqs.iter().any(|(_req, delay)| *delay > 0) {
+                    if store_mem.reqs.contains(&mem_req) {
+                        info!("Store request already in queue");
                     } else {
-                        main_mem.curr_reqs.insert(mem_req, main_mem.latency());
+                        store_mem.reqs.push_back(mem_req);
+                        info!("New store request inserted into queue");
                     }
-                } else {
-                    if !main_mem.reqs.contains(&mem_req) {
-                        main_mem.reqs.push_back(mem_req);
-                        info!("New load request inserted into queue");
-                    } else {
-                        info!("Load request already in queue");
+                } else if let Some(next_req) = store_mem.reqs.pop_front() {
+                    // the request may already be the one waiting in the queue
+                    if next_req != mem_req && !store_mem.reqs.contains(&mem_req) {
+                        store_mem.reqs.push_back(mem_req);
                     }
+                    store_mem.curr_reqs.insert(next_req, store_mem.latency());
+                } else {
+                    store_mem.curr_reqs.insert(mem_req, store_mem.latency());
                 }
             }
         }
@@ -319,35 +603,91 @@ impl Memory {
     /// moving the system forward in time one step
     pub fn update_clock(&mut self) {
         // update timer for all request queues
-        for level in &mut self.levels {
+        for level in self.levels.iter_mut().chain(self.icache.as_mut()) {
             level.update_clock();
         }
     }
 
-    /// Invalidates all cache lines (in all cache levels) containing the
+    /// Invalidates all cache lines in the levels above `level` containing the
     /// given `address`
-    fn invalidate_address(&mut self, address: usize) {
-        info!("Invalidating cache entries for address 0x{:08X}", address);
+    fn invalidate_address(&mut self, level: usize, address: usize) {
+        info!("Invalidating cache entries for address 0x{address:08X}");
         // invalidate cache entries, but don't touch main memory
-        for level in 0..self.num_levels() - 1 {
+        for level in 0..level.min(self.num_levels() - 1) {
             info!("Invalidating cache level {level}");
             self.levels[level].invalidate_address(address);
         }
+        // keep fetches coherent with stores to instruction memory
+        if let Some(icache) = &mut self.icache {
+            info!("Invalidating I-cache");
+            icache.invalidate_address(address);
+        }
     }
 
-    /// Writes the line `data` to cache level 0 through cache level `start_level`
-    fn populate_cache(&mut self, start_level: usize, data: &MemLine) -> Result<()> {
+    /// Writes the line `data` to each of the levels in `path`
+    fn populate_cache(&mut self, path: &[LevelId], data: &MemLine) -> Result<()> {
+        // only the level a store was written to holds the line as dirty
+        let mut line = data.clone();
+        line.set_dirty(false);
+        let address = line.start_address().expect("Empty address field");
+        for &level in path {
+            // a level may have been refilled (and written to) while this request
+            // was waiting on a lower level, in which case its copy is newer
+            if self.level_mut(level).contains_address(address) {
+                continue;
+            }
+            info!("Populating cache level {level} with {line:?}");
+            match level {
+                // never written to, so there's nothing to write back
+                LevelId::ICache => {
+                    self.level_mut(level).write_line(address, &line)?;
+                }
+                LevelId::Level(level) => self.install_line(level, &line)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the line `data` into `level`, writing back whatever dirty line it
+    /// evicts to the level below
+    fn install_line(&mut self, level: usize, data: &MemLine) -> Result<()> {
         let address = data.start_address().expect("Empty address field");
-        for level in 0..=start_level {
-            info!("Populating cache level {level} with {:?}", data);
-            let address = address % self.get_capacity(level).unwrap();
-            self.levels[level].write_line(address, data)?;
+        if let Some(evicted) = self.levels[level].write_line(address, data)? {
+            self.write_back(level + 1, &evicted)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the evicted dirty line `data` back, starting at `level`. The line is
+    /// kept by the first write-back level (or main memory), with copies in the
+    /// write-through levels in between being updated along the way. The level
+    /// finally written to is stalled for its latency
+    fn write_back(&mut self, level: usize, data: &MemLine) -> Result<()> {
+        let address = data.start_address().expect("Empty address field");
+        let main_level = self.num_levels() - 1;
+        for level in level..=main_level {
+            let mem = &mut self.levels[level];
+            if mem.is_write_back() || level == main_level {
+                info!("Writing back line {data} to level {level}");
+                let mut line = data.clone();
+                line.set_dirty(mem.is_write_back());
+                mem.add_busy_cycles(mem.latency());
+                return self.install_line(level, &line);
+            }
+            if mem.contains_address(address) {
+                let mut line = data.clone();
+                line.set_dirty(false);
+                mem.write_line(address, &line)?;
+            }
         }
 
         Ok(())
     }
 
     /// Returns the number of memory levels, including main memory
+    #[must_use]
     pub fn num_levels(&self) -> usize {
         self.levels.len()
     }
@@ -355,6 +695,10 @@ impl Memory {
     #[allow(dead_code)]
     /// Prints the latency, current request, request queue, and contents of the
     /// given memory `level`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `level` isn't one of the memory's levels
     pub fn print_level(&self, level: usize) -> Result<()> {
         if level >= self.num_levels() {
             return Err(anyhow!("Invalid level number"));
@@ -365,7 +709,11 @@ impl Memory {
     }
 
     /// Returns a cow of the requested level's string representation
-    pub fn get_level(&self, level: usize) -> Result<Cow<MemoryLevel>> {
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `level` isn't one of the memory's levels
+    pub fn get_level(&self, level: usize) -> Result<Cow<'_, MemoryLevel>> {
         if level >= self.num_levels() {
             return Err(anyhow!("Invalid level number"));
         }
@@ -373,7 +721,21 @@ impl Memory {
         Ok(Cow::Borrowed(&self.levels[level]))
     }
 
+    /// Returns a cow of the I-cache, if present
+    #[must_use]
+    pub fn get_icache(&self) -> Option<Cow<'_, MemoryLevel>> {
+        self.icache.as_ref().map(Cow::Borrowed)
+    }
+
     /// Issue a `MemRequest` to the memory system
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a level fails to read or write the line involved
+    ///
+    /// # Panics
+    ///
+    /// Panics if a level answers a load as though it were a store
     pub fn request(&mut self, request: &MemRequest) -> Result<MemResponse> {
         info!("Issuing request to memory system: {:?}", request);
         match request {
@@ -408,7 +770,7 @@ impl Memory {
                             "Error occured during load operation -- Error {e}, Request: {:?}",
                             req
                         );
-                        panic!("Bad load");
+                        Err(e)
                     }
                 }
             }
@@ -418,7 +780,6 @@ impl Memory {
                 match resp {
                     Ok(MemResponse::StoreComplete) => {
                         info!("StoreComplete response for store request: {:?}", req);
-                        self.invalidate_address(req.address);
                         Ok(MemResponse::StoreComplete)
                     }
                     Ok(ref resp_details) => {
@@ -433,20 +794,218 @@ impl Memory {
                             "Error occurred during store operation -- Error {e}, Request: {:?}",
                             req
                         );
-                        panic!("Bad store");
+                        Err(e)
                     }
                 }
             }
         }
     }
 
+    /// Manually read the instruction word at `addr`, preferring the I-cache's
+    /// copy, then the other levels' as [`Memory::force_load`] does. Returns
+    /// `u32::MAX` if there isn't one
+    #[must_use]
     pub fn force_instr_load(&self, addr: usize) -> u32 {
-        let resp = self.levels.last().unwrap().force_load(addr);
-        if let Some(block) = resp {
-            if let Some(raw) = block.get_unsigned() {
-                return raw;
+        self.icache
+            .iter()
+            .chain(&self.levels)
+            .find_map(|level| level.force_load(addr))
+            .and_then(MemBlock::get_unsigned)
+            .unwrap_or(u32::MAX)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::config::config::LevelConfig;
+    use crate::memory::memory_system::{
+        LoadRequest, LoadResponse, MemBlock, MemRequest, MemResponse, MemType, Memory,
+        StoreRequest, WritePolicy, MEM_BLOCK_WIDTH,
+    };
+    use crate::system::system::PipelineStage;
+
+    const LINE_LEN: usize = 4;
+    const LINE_BITS: usize = LINE_LEN * MEM_BLOCK_WIDTH;
+
+    /// Returns a 2 line direct mapped cache in front of a 16 line main memory
+    fn get_test_memory() -> Memory {
+        Memory::new(
+            LINE_LEN,
+            &[LevelConfig::new(2, 1), LevelConfig::new(16, 5)],
+            None,
+        )
+        .unwrap()
+    }
+
+    /// Returns the same memory with level 0 split, adding a 2 line I-cache
+    fn get_split_memory() -> Memory {
+        Memory::new(
+            LINE_LEN,
+            &[LevelConfig::new(2, 1), LevelConfig::new(16, 5)],
+            Some(&LevelConfig::new(2, 1)),
+        )
+        .unwrap()
+    }
+
+    /// Returns the same memory with a write-back, write-allocate cache
+    fn get_write_back_memory() -> Memory {
+        let cache = LevelConfig {
+            write_policy: WritePolicy::WriteBack,
+            ..LevelConfig::new(2, 1)
+        };
+        Memory::new(LINE_LEN, &[cache, LevelConfig::new(16, 5)], None).unwrap()
+    }
+
+    fn load(issuer: PipelineStage, address: usize) -> MemRequest {
+        MemRequest::Load(LoadRequest {
+            issuer,
+            address,
+            width: MemType::Unsigned32,
+        })
+    }
+
+    fn store(address: usize, data: u32) -> MemRequest {
+        MemRequest::Store(StoreRequest {
+            issuer: PipelineStage::Memory,
+            address,
+            data: MemBlock::Unsigned32(data),
+        })
+    }
+
+    /// Re-issues `req` every cycle until it completes, as the pipeline does,
+    /// returning the response and the number of cycles waited
+    fn serve(mem: &mut Memory, req: &MemRequest) -> (MemResponse, usize) {
+        for cycles in 0..100 {
+            match mem.request(req).unwrap() {
+                MemResponse::Wait => mem.update_clock(),
+                resp => return (resp, cycles),
             }
         }
-        u32::MAX
+        panic!("Request {req:?} never completed");
+    }
+
+    #[test]
+    fn counts_level_stats() {
+        let mut mem = get_test_memory();
+        let (_, miss_cycles) = serve(&mut mem, &load(PipelineStage::Memory, 0));
+        let (_, hit_cycles) = serve(&mut mem, &load(PipelineStage::Memory, 32));
+        // same set as line 0, which gets evicted
+        serve(&mut mem, &load(PipelineStage::Memory, 2 * LINE_BITS));
+        assert_eq!((miss_cycles, hit_cycles), (5, 1));
+
+        let cache = mem.level_stats(0).unwrap();
+        assert_eq!((cache.load_hits, cache.load_misses), (1, 2));
+        assert_eq!(cache.evictions, 1);
+        assert_eq!((cache.completed_reqs, cache.wait_cycles), (1, 1));
+        let main = mem.level_stats(1).unwrap();
+        assert_eq!((main.load_hits, main.load_misses), (2, 0));
+        assert_eq!((main.completed_reqs, main.wait_cycles), (2, 10));
+        assert_eq!(main.avg_wait_cycles(), Some(5.0));
+    }
+
+    #[test]
+    fn split_icache_only_serves_fetches() {
+        let mut mem = get_split_memory();
+        serve(&mut mem, &load(PipelineStage::Fetch, 0));
+        serve(&mut mem, &load(PipelineStage::Memory, LINE_BITS));
+        serve(&mut mem, &load(PipelineStage::Fetch, 32));
+
+        // fetches fill the I-cache, data loads level 0, and both go through main
+        let icache = mem.get_icache().unwrap();
+        assert!(icache.contains_address(0));
+        assert!(!icache.contains_address(LINE_BITS));
+        let data_cache = mem.get_level(0).unwrap();
+        assert!(data_cache.contains_address(LINE_BITS));
+        assert!(!data_cache.contains_address(0));
+
+        let icache = mem.icache_stats().unwrap();
+        assert_eq!((icache.load_hits, icache.load_misses), (1, 1));
+        let data_cache = mem.level_stats(0).unwrap();
+        assert_eq!((data_cache.load_hits, data_cache.load_misses), (0, 1));
+        let main = mem.level_stats(1).unwrap();
+        assert_eq!((main.load_hits, main.load_misses), (2, 0));
+
+        // stores invalidate stale instructions so fetches see the new ones
+        serve(&mut mem, &store(32, 7));
+        assert!(!mem.get_icache().unwrap().contains_address(0));
+        assert_eq!(mem.icache_stats().unwrap().invalidations, 1);
+        let (resp, _) = serve(&mut mem, &load(PipelineStage::Fetch, 32));
+        let MemResponse::Load(LoadResponse { data }) = resp else {
+            panic!("Fetch didn't complete: {resp:?}");
+        };
+        assert_eq!(data.get_contents(32), Some(MemBlock::Unsigned32(7)));
+    }
+
+    #[test]
+    fn stores_leave_conflicting_lines_cached() {
+        let mut mem = get_test_memory();
+        // lines 0 and 2 both map to the first line of the cache
+        serve(&mut mem, &load(PipelineStage::Memory, 2 * LINE_BITS));
+        assert!(mem.get_level(0).unwrap().contains_address(2 * LINE_BITS));
+
+        // storing to line 0 goes straight through to main memory, and mustn't
+        // throw out the unrelated line 2 sitting where line 0 would go
+        serve(&mut mem, &store(0, 7));
+        assert!(mem.get_level(0).unwrap().contains_address(2 * LINE_BITS));
+        assert_eq!(mem.level_stats(0).unwrap().invalidations, 0);
+        assert_eq!(mem.force_load(0), Some(MemBlock::Unsigned32(7)));
+    }
+
+    #[test]
+    fn store_misses_allocate_without_writing_through() {
+        let mut mem = get_write_back_memory();
+        let initial = mem.get_level(1).unwrap().force_load(32);
+        let (resp, _) = serve(&mut mem, &store(32, 7));
+        assert!(matches!(resp, MemResponse::StoreComplete));
+
+        // the line is brought into the cache and written there only
+        let cache = mem.get_level(0).unwrap();
+        assert_eq!(cache.force_load(32), Some(MemBlock::Unsigned32(7)));
+        let main = mem.get_level(1).unwrap();
+        assert_eq!(main.force_load(32), initial);
+        let stats = mem.level_stats(0).unwrap();
+        assert_eq!((stats.stores, stats.load_misses), (1, 0));
+        assert_eq!(mem.level_stats(1).unwrap().stores, 0);
+
+        // both views of memory show the cached copy
+        assert_eq!(mem.force_load(32), Some(MemBlock::Unsigned32(7)));
+        assert_eq!(mem.force_instr_load(32), 7);
+    }
+
+    #[test]
+    fn evicted_dirty_lines_are_written_back() {
+        let mut mem = get_write_back_memory();
+        serve(&mut mem, &store(32, 7));
+        // line 2 maps to the same cache line as line 0
+        serve(&mut mem, &load(PipelineStage::Memory, 2 * LINE_BITS));
+
+        assert!(!mem.get_level(0).unwrap().contains_address(32));
+        let main = mem.get_level(1).unwrap();
+        assert_eq!(main.force_load(32), Some(MemBlock::Unsigned32(7)));
+        let stats = mem.level_stats(0).unwrap();
+        assert_eq!((stats.evictions, stats.writebacks), (1, 1));
+
+        // clean lines are dropped without being written back
+        serve(&mut mem, &load(PipelineStage::Memory, 0));
+        let stats = mem.level_stats(0).unwrap();
+        assert_eq!((stats.evictions, stats.writebacks), (2, 1));
+    }
+
+    #[test]
+    fn write_backs_keep_the_level_below_busy() {
+        // line 1 misses in the cache either way, after line 2 evicts line 0
+        let time_line_1 = |mut mem: Memory, dirty: bool| {
+            if dirty {
+                serve(&mut mem, &store(0, 7));
+            } else {
+                serve(&mut mem, &load(PipelineStage::Memory, 0));
+            }
+            serve(&mut mem, &load(PipelineStage::Memory, 2 * LINE_BITS));
+            serve(&mut mem, &load(PipelineStage::Memory, LINE_BITS)).1
+        };
+
+        assert_eq!(time_line_1(get_write_back_memory(), false), 5);
+        // main memory spends its latency taking the write-back first
+        assert_eq!(time_line_1(get_write_back_memory(), true), 10);
     }
 }
@@ -1,3 +1,4 @@
+pub mod console;
 mod memory_block;
 mod memory_level;
 mod memory_line;
@@ -12,6 +12,8 @@ pub const GEN_REG_COUNT: usize = 16;
 pub const FLOAT_REG_COUNT: usize = 16;
 pub const FLAG_COUNT: usize = 6;
 pub const RET_REG: usize = GEN_REG_COUNT - 1;
+// a trap handler finds the faulting PC in RET_REG, and the cause code here
+pub const TRAP_CAUSE_REG: usize = RET_REG - 1;
 
 pub const TYPE_0_INSTRS: &[&str] = &["RET", "HALT"];
 pub const TYPE_1_INSTRS: &[&str] = &[
@@ -0,0 +1,221 @@
+#![warn(clippy::all, clippy::pedantic)]
+
+use std::fmt::Display;
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+
+use crate::instruction::instruction::Instruction;
+use crate::memory::memory_system::MEM_BLOCK_WIDTH;
+
+/// Strategy the fetch stage uses to guess the outcome of control flow
+/// instructions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PredictorKind {
+    /// Always fetch the next sequential instruction
+    #[default]
+    NotTaken,
+    /// Take branches to lower addresses (loops), fall through otherwise
+    BackwardTaken,
+    /// Repeat each branch's last outcome, as recorded in the BTB
+    OneBit,
+    /// 2-bit saturating counter per BTB entry
+    TwoBit,
+}
+
+impl PredictorKind {
+    /// Returns whether the predictor keeps history in a branch target buffer
+    #[must_use]
+    pub fn uses_btb(self) -> bool {
+        matches!(self, Self::OneBit | Self::TwoBit)
+    }
+}
+
+impl Display for PredictorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotTaken => write!(f, "Static Not-Taken"),
+            Self::BackwardTaken => write!(f, "Static Backward-Taken"),
+            Self::OneBit => write!(f, "1-Bit"),
+            Self::TwoBit => write!(f, "2-Bit Saturating"),
+        }
+    }
+}
+
+impl FromStr for PredictorKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "not-taken" => Ok(Self::NotTaken),
+            "backward-taken" => Ok(Self::BackwardTaken),
+            "one-bit" => Ok(Self::OneBit),
+            "two-bit" => Ok(Self::TwoBit),
+            _ => Err(anyhow!(
+                "Unknown branch predictor {s}, expected one of not-taken, backward-taken, one-bit, or two-bit"
+            )),
+        }
+    }
+}
+
+/// A single branch target buffer entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct BtbEntry {
+    tag: usize, // full address of the branch
+    target: u32,
+    counter: u8,
+}
+
+/// Predicts the address following each fetched instruction
+#[derive(Debug, Clone)]
+pub struct BranchPredictor {
+    kind: PredictorKind,
+    btb: Vec<Option<BtbEntry>>, // direct mapped, indexed by word address
+}
+
+impl BranchPredictor {
+    #[must_use]
+    pub fn new(kind: PredictorKind, btb_entries: usize) -> Self {
+        let btb_entries = if kind.uses_btb() { btb_entries } else { 0 };
+        Self {
+            kind,
+            btb: vec![None; btb_entries],
+        }
+    }
+
+    #[must_use]
+    pub fn kind(&self) -> PredictorKind {
+        self.kind
+    }
+
+    /// Largest value a BTB entry's counter can hold
+    fn counter_max(&self) -> u8 {
+        if self.kind == PredictorKind::TwoBit {
+            3
+        } else {
+            1
+        }
+    }
+
+    fn btb_index(&self, pc: usize) -> usize {
+        (pc / MEM_BLOCK_WIDTH) % self.btb.len()
+    }
+
+    fn btb_entry(&self, pc: usize) -> Option<&BtbEntry> {
+        if self.btb.is_empty() {
+            return None;
+        }
+        self.btb[self.btb_index(pc)]
+            .as_ref()
+            .filter(|entry| entry.tag == pc)
+    }
+
+    /// Returns the predicted address of the instruction following `instr`,
+    /// fetched from `pc`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `pc` lies outside of the 32-bit address space
+    #[must_use]
+    pub fn predict(&self, pc: usize, instr: Option<Instruction>) -> u32 {
+        let fallthrough = u32::try_from(pc + MEM_BLOCK_WIDTH).unwrap();
+        match self.kind {
+            PredictorKind::NotTaken => fallthrough,
+            PredictorKind::BackwardTaken => instr
+                .and_then(|instr| instr.direct_target(u32::try_from(pc).unwrap()))
+                .filter(|target| *target as usize <= pc)
+                .unwrap_or(fallthrough),
+            PredictorKind::OneBit | PredictorKind::TwoBit => {
+                // counters at or above the midpoint predict taken
+                let threshold = self.counter_max() / 2 + 1;
+                self.btb_entry(pc)
+                    .filter(|entry| entry.counter >= threshold)
+                    .map_or(fallthrough, |entry| entry.target)
+            }
+        }
+    }
+
+    /// Trains the predictor with the resolved outcome of the control flow
+    /// instruction at `pc`
+    pub fn update(&mut self, pc: usize, taken: bool, target: u32) {
+        if self.btb.is_empty() {
+            return;
+        }
+        let counter_max = self.counter_max();
+        let idx = self.btb_index(pc);
+        match &mut self.btb[idx] {
+            Some(entry) if entry.tag == pc => {
+                if taken {
+                    entry.counter = (entry.counter + 1).min(counter_max);
+                    entry.target = target;
+                } else {
+                    entry.counter = entry.counter.saturating_sub(1);
+                }
+            }
+            // only taken branches are worth a BTB entry, start out weakly taken
+            entry if taken => {
+                *entry = Some(BtbEntry {
+                    tag: pc,
+                    target,
+                    counter: counter_max / 2 + 1,
+                });
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::instruction::instruction::Instruction;
+    use crate::system::branch_predictor::{BranchPredictor, PredictorKind};
+
+    #[test]
+    fn backward_taken_follows_loops() {
+        let predictor = BranchPredictor::new(PredictorKind::BackwardTaken, 0);
+        let backward = Instruction::Type1 {
+            opcode: 2,
+            immediate: 0x40,
+        };
+        let forward = Instruction::Type1 {
+            opcode: 2,
+            immediate: 0x200,
+        };
+        assert_eq!(predictor.predict(0x100, Some(backward)), 0x40);
+        assert_eq!(predictor.predict(0x100, Some(forward)), 0x120);
+    }
+
+    #[test]
+    fn one_bit_repeats_last_outcome() {
+        let mut predictor = BranchPredictor::new(PredictorKind::OneBit, 16);
+        assert_eq!(predictor.predict(0x100, None), 0x120);
+        predictor.update(0x100, true, 0x40);
+        assert_eq!(predictor.predict(0x100, None), 0x40);
+        predictor.update(0x100, false, 0x40);
+        assert_eq!(predictor.predict(0x100, None), 0x120);
+    }
+
+    #[test]
+    fn two_bit_tolerates_one_miss() {
+        let mut predictor = BranchPredictor::new(PredictorKind::TwoBit, 16);
+        predictor.update(0x100, true, 0x40);
+        predictor.update(0x100, true, 0x40);
+        // a single loop exit doesn't flip a strongly taken prediction
+        predictor.update(0x100, false, 0x40);
+        assert_eq!(predictor.predict(0x100, None), 0x40);
+        predictor.update(0x100, false, 0x40);
+        assert_eq!(predictor.predict(0x100, None), 0x120);
+    }
+
+    #[test]
+    fn btb_conflicts_replace_entries() {
+        let mut predictor = BranchPredictor::new(PredictorKind::OneBit, 4);
+        predictor.update(0x100, true, 0x40);
+        // 4 words further along maps to the same entry
+        predictor.update(0x180, true, 0x60);
+        assert_eq!(predictor.predict(0x100, None), 0x120);
+        assert_eq!(predictor.predict(0x180, None), 0x60);
+    }
+}
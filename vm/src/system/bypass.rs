@@ -0,0 +1,126 @@
+#![warn(clippy::all, clippy::pedantic)]
+
+use std::collections::{HashMap, HashSet};
+
+use crate::memory::memory_system::MemBlock;
+use crate::register::register_system::{Register, RegisterGroup};
+
+/// Forwards register results from the execute and memory stages to later
+/// instructions before they're written back
+#[derive(Debug, Clone, Default)]
+pub struct BypassNetwork {
+    enabled: bool,
+    // newest in-flight value of each register, absent if its newest writer
+    // hasn't produced a result yet
+    values: HashMap<(RegisterGroup, usize), MemBlock>,
+}
+
+impl BypassNetwork {
+    #[must_use]
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            values: HashMap::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// Records the newest write to `reg`, `data` being `None` if the writer's
+    /// result isn't available yet
+    pub fn record(&mut self, reg: (RegisterGroup, usize), data: Option<MemBlock>) {
+        match data {
+            Some(data) if self.enabled => {
+                self.values.insert(reg, data);
+            }
+            _ => {
+                self.values.remove(&reg);
+            }
+        }
+    }
+
+    /// Returns whether a pending read of `reg` can be satisfied by forwarding
+    #[must_use]
+    pub fn can_forward(&self, reg: &(RegisterGroup, usize)) -> bool {
+        self.enabled && self.values.contains_key(reg)
+    }
+
+    /// Returns whether a read of `reg` has to wait for it to be written back
+    #[must_use]
+    pub fn must_stall(
+        &self,
+        pending: &HashSet<(RegisterGroup, usize)>,
+        reg: &(RegisterGroup, usize),
+    ) -> bool {
+        pending.contains(reg) && !self.can_forward(reg)
+    }
+
+    /// Returns a copy of `regs` with the forwarded values of any `pending`
+    /// registers in `group` patched in
+    #[must_use]
+    pub fn apply<'a, const N: usize>(
+        &self,
+        group: RegisterGroup,
+        regs: &[Register; N],
+        pending: impl Iterator<Item = &'a (RegisterGroup, usize)>,
+    ) -> [Register; N] {
+        let mut regs = *regs;
+        for reg in pending.filter(|(reg_group, _)| *reg_group == group) {
+            if let Some(data) = self.values.get(reg) {
+                regs[reg.1] = Register { data: *data };
+            }
+        }
+        regs
+    }
+
+    pub fn clear(&mut self) {
+        self.values.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::memory::memory_system::MemBlock;
+    use crate::register::register_system::{Register, RegisterGroup};
+    use crate::system::bypass::BypassNetwork;
+
+    #[test]
+    fn forwards_only_pending_registers() {
+        let mut bypass = BypassNetwork::new(true);
+        let reg = (RegisterGroup::General, 1);
+        bypass.record(reg, Some(MemBlock::Unsigned32(7)));
+        assert!(bypass.can_forward(&reg));
+
+        let regs = [Register::default(); 4];
+        let forwarded = bypass.apply(RegisterGroup::General, &regs, [reg].iter());
+        assert_eq!(forwarded[1].data, MemBlock::Unsigned32(7));
+        // once written back, the register file is the source of truth
+        let forwarded = bypass.apply(RegisterGroup::General, &regs, [].iter());
+        assert_eq!(forwarded[1].data, regs[1].data);
+    }
+
+    #[test]
+    fn pending_writer_hides_older_result() {
+        let mut bypass = BypassNetwork::new(true);
+        let reg = (RegisterGroup::General, 1);
+        bypass.record(reg, Some(MemBlock::Unsigned32(7)));
+        // e.g. a load to the same register has passed through execute
+        bypass.record(reg, None);
+        assert!(!bypass.can_forward(&reg));
+    }
+
+    #[test]
+    fn disabled_network_forwards_nothing() {
+        let mut bypass = BypassNetwork::new(false);
+        let reg = (RegisterGroup::General, 1);
+        bypass.record(reg, Some(MemBlock::Unsigned32(7)));
+        assert!(!bypass.can_forward(&reg));
+    }
+}
@@ -0,0 +1,345 @@
+#![warn(clippy::all, clippy::pedantic)]
+#![allow(
+    clippy::module_name_repetitions,
+    clippy::missing_errors_doc,
+    clippy::must_use_candidate
+)]
+
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, Result};
+
+use crate::memory::memory_system::MEM_BLOCK_WIDTH;
+
+/// First bytes of every program container, telling it apart from a raw image
+pub const IMAGE_MAGIC: [u8; 4] = *b"GFLP";
+/// Version of the container layout, bumped whenever it changes
+pub const IMAGE_VERSION: u16 = 1;
+/// Version of the instruction set programs are assembled for
+pub const ISA_VERSION: u16 = 1;
+
+const WORD_BYTES: usize = MEM_BLOCK_WIDTH / 8;
+// Set in a segment's flags if it holds instructions
+const EXECUTABLE_FLAG: u32 = 1;
+
+/// Words to be loaded at a given address
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub address: u32, // bit address of the first word
+    pub executable: bool,
+    pub words: Vec<u32>,
+}
+
+impl Segment {
+    /// Returns the bit address just past the segment's last word
+    pub fn end(&self) -> usize {
+        self.address as usize + self.words.len() * MEM_BLOCK_WIDTH
+    }
+}
+
+/// A program ready to be loaded into memory. Stored as a container with a
+/// header, or as a legacy raw image holding nothing but words loaded from
+/// address 0
+///
+/// The container is big-endian throughout:
+///
+/// ```text
+/// magic "GFLP", version: u16, isa_version: u16, entry: u32,
+/// segment_count: u32, symbol_count: u32,
+/// segments: [address: u32, flags: u32, word_count: u32, words: [u32]],
+/// symbols: [address: u32, name_len: u16, name: UTF-8],
+/// checksum: u32 (CRC-32 of everything before it)
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProgramImage {
+    pub entry: Option<u32>, // PC to start at, the configured start PC if `None`
+    pub segments: Vec<Segment>,
+    pub symbols: BTreeMap<String, u32>, // label -> bit address
+}
+
+/// Returns the CRC-32 (as used by zip and PNG) of `bytes`
+fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0, |crc, byte| {
+        (0..8).fold(crc ^ u32::from(*byte), |crc, _| {
+            (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg())
+        })
+    })
+}
+
+/// Reads big-endian fields from the front of a container
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize, what: &str) -> Result<&'a [u8]> {
+        if self.bytes.len() < len {
+            return Err(anyhow!("Program container is cut off in its {what}"));
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u16(&mut self, what: &str) -> Result<u16> {
+        let bytes = self.take(2, what)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self, what: &str) -> Result<u32> {
+        let bytes = self.take(4, what)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
+
+impl ProgramImage {
+    /// Returns whether `bytes` hold a container rather than a raw image
+    pub fn is_container(bytes: &[u8]) -> bool {
+        bytes.starts_with(&IMAGE_MAGIC)
+    }
+
+    /// Reads a program from `bytes`, which hold either a container or a raw
+    /// image
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        if Self::is_container(bytes) {
+            Self::from_container(bytes)
+        } else {
+            Self::from_raw(bytes)
+        }
+    }
+
+    /// Reads a raw image, whose words are all loaded from address 0
+    pub fn from_raw(bytes: &[u8]) -> Result<Self> {
+        let words = bytes.chunks(WORD_BYTES);
+        if words.clone().any(|word| word.len() != WORD_BYTES) {
+            return Err(anyhow!(
+                "Program length {} bytes isn't a whole number of {MEM_BLOCK_WIDTH}-bit words",
+                bytes.len()
+            ));
+        }
+        Ok(Self {
+            entry: None,
+            segments: vec![Segment {
+                address: 0,
+                executable: true,
+                words: words
+                    .map(|word| u32::from_be_bytes([word[0], word[1], word[2], word[3]]))
+                    .collect(),
+            }],
+            symbols: BTreeMap::new(),
+        })
+    }
+
+    /// Reads and checks a container written by [`ProgramImage::to_bytes`]
+    pub fn from_container(bytes: &[u8]) -> Result<Self> {
+        let Some((contents, checksum)) = bytes.split_last_chunk::<4>() else {
+            return Err(anyhow!("Program container is cut off in its header"));
+        };
+        let (expected, actual) = (u32::from_be_bytes(*checksum), crc32(contents));
+        if expected != actual {
+            return Err(anyhow!(
+                "Program container is corrupt: its checksum is 0x{expected:08X} but its \
+                 contents add up to 0x{actual:08X}"
+            ));
+        }
+
+        let mut reader = Reader { bytes: contents };
+        reader.take(IMAGE_MAGIC.len(), "header")?;
+        let version = reader.u16("header")?;
+        if version != IMAGE_VERSION {
+            return Err(anyhow!(
+                "Program container version {version} isn't supported, only version \
+                 {IMAGE_VERSION} is"
+            ));
+        }
+        let isa_version = reader.u16("header")?;
+        if isa_version != ISA_VERSION {
+            return Err(anyhow!(
+                "Program was assembled for ISA version {isa_version}, but this machine \
+                 implements version {ISA_VERSION}"
+            ));
+        }
+        let entry = reader.u32("header")?;
+        let segment_count = reader.u32("header")?;
+        let symbol_count = reader.u32("header")?;
+
+        let mut segments = vec![];
+        for _ in 0..segment_count {
+            let address = reader.u32("segments")?;
+            let flags = reader.u32("segments")?;
+            let word_count = reader.u32("segments")?;
+            let words = (0..word_count)
+                .map(|_| reader.u32("segments"))
+                .collect::<Result<_>>()?;
+            segments.push(Segment {
+                address,
+                executable: flags & EXECUTABLE_FLAG != 0,
+                words,
+            });
+        }
+
+        let mut symbols = BTreeMap::new();
+        for _ in 0..symbol_count {
+            let address = reader.u32("symbols")?;
+            let len = reader.u16("symbols")?;
+            let name = std::str::from_utf8(reader.take(len.into(), "symbols")?)
+                .map_err(|_| anyhow!("Program container has a symbol name that isn't UTF-8"))?;
+            symbols.insert(name.to_string(), address);
+        }
+        if !reader.bytes.is_empty() {
+            return Err(anyhow!(
+                "Program container has {} unexpected bytes after its symbols",
+                reader.bytes.len()
+            ));
+        }
+
+        Ok(Self {
+            entry: Some(entry),
+            segments,
+            symbols,
+        })
+    }
+
+    /// Returns the program as a container
+    ///
+    /// # Panics
+    ///
+    /// Panics if the program has more segments, symbols, or words than the
+    /// container's 32-bit counts can hold
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let count = |len: usize| u32::try_from(len).expect("Too many items for a container");
+        let mut bytes = IMAGE_MAGIC.to_vec();
+        bytes.extend(IMAGE_VERSION.to_be_bytes());
+        bytes.extend(ISA_VERSION.to_be_bytes());
+        bytes.extend(self.entry.unwrap_or(0).to_be_bytes());
+        bytes.extend(count(self.segments.len()).to_be_bytes());
+        bytes.extend(count(self.symbols.len()).to_be_bytes());
+        for segment in &self.segments {
+            let flags = if segment.executable {
+                EXECUTABLE_FLAG
+            } else {
+                0
+            };
+            bytes.extend(segment.address.to_be_bytes());
+            bytes.extend(flags.to_be_bytes());
+            bytes.extend(count(segment.words.len()).to_be_bytes());
+            bytes.extend(segment.words.iter().flat_map(|word| word.to_be_bytes()));
+        }
+        for (name, address) in &self.symbols {
+            let len = u16::try_from(name.len()).expect("Symbol name too long for a container");
+            bytes.extend(address.to_be_bytes());
+            bytes.extend(len.to_be_bytes());
+            bytes.extend(name.as_bytes());
+        }
+        bytes.extend(crc32(&bytes).to_be_bytes());
+        bytes
+    }
+
+    /// Checks that every segment is word aligned, doesn't overlap another,
+    /// and fits in `capacity` bits of memory, along with the entry point
+    pub fn validate(&self, capacity: usize) -> Result<()> {
+        let mut segments: Vec<&Segment> = self.segments.iter().collect();
+        segments.sort_by_key(|segment| segment.address);
+        for (idx, segment) in segments.iter().enumerate() {
+            if !(segment.address as usize).is_multiple_of(MEM_BLOCK_WIDTH) {
+                return Err(anyhow!(
+                    "Segment at 0x{:08X} is not aligned to a {MEM_BLOCK_WIDTH}-bit word",
+                    segment.address
+                ));
+            }
+            if segment.end() > capacity {
+                return Err(anyhow!(
+                    "Segment at 0x{:08X} is too large to fit in main memory: it ends at bit \
+                     {} but memory holds {capacity}",
+                    segment.address,
+                    segment.end()
+                ));
+            }
+            if let Some(next) = segments.get(idx + 1) {
+                if segment.end() > next.address as usize {
+                    return Err(anyhow!(
+                        "Segments at 0x{:08X} and 0x{:08X} overlap",
+                        segment.address,
+                        next.address
+                    ));
+                }
+            }
+        }
+
+        if let Some(entry) = self.entry {
+            let in_code = self.segments.iter().any(|segment| {
+                segment.executable
+                    && (segment.address as usize..segment.end()).contains(&(entry as usize))
+            });
+            if !in_code {
+                return Err(anyhow!(
+                    "Entry point 0x{entry:08X} isn't inside a segment of instructions"
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::system::image::{crc32, ProgramImage, Segment};
+
+    fn image() -> ProgramImage {
+        ProgramImage {
+            entry: Some(32),
+            segments: vec![
+                Segment {
+                    address: 0,
+                    executable: true,
+                    words: vec![0x8, 0x45],
+                },
+                Segment {
+                    address: 128,
+                    executable: false,
+                    words: vec![7],
+                },
+            ],
+            symbols: [("start".to_string(), 32)].into(),
+        }
+    }
+
+    #[test]
+    fn round_trips_containers() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+
+        let bytes = image().to_bytes();
+        assert!(ProgramImage::is_container(&bytes));
+        assert_eq!(ProgramImage::parse(&bytes).unwrap(), image());
+        image().validate(256).unwrap();
+
+        let mut corrupt = bytes.clone();
+        corrupt[20] ^= 1;
+        assert!(ProgramImage::parse(&corrupt)
+            .unwrap_err()
+            .to_string()
+            .contains("checksum"));
+        assert!(ProgramImage::parse(&bytes[..10]).is_err());
+    }
+
+    #[test]
+    fn accepts_raw_images() {
+        let image = ProgramImage::parse(&[0, 0, 0, 8, 0, 0, 0, 0x45]).unwrap();
+        assert_eq!(image.entry, None);
+        assert_eq!(image.segments[0].words, vec![0x8, 0x45]);
+        assert!(ProgramImage::parse(&[0, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn validates_layout() {
+        assert!(image().validate(128).is_err());
+
+        let mut overlapping = image();
+        overlapping.segments[1].address = 32;
+        assert!(overlapping.validate(256).is_err());
+
+        let mut bad_entry = image();
+        bad_entry.entry = Some(128);
+        assert!(bad_entry.validate(256).is_err());
+    }
+}
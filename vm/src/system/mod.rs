@@ -1 +1,6 @@
+pub mod branch_predictor;
+pub mod bypass;
+pub mod image;
+pub mod stats;
 pub mod system;
+pub mod trap;
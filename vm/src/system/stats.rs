@@ -0,0 +1,97 @@
+#![warn(clippy::all, clippy::pedantic)]
+
+use std::fmt::Display;
+
+use crate::memory::memory_system::LevelStats;
+use crate::system::system::Cycle;
+
+/// Pipeline-wide performance counters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SystemStats {
+    pub instructions_retired: usize,
+    pub forwarded_operands: usize,
+    pub branches: usize, // resolved control flow instructions
+    pub mispredictions: usize,
+    pub squashed_instructions: usize,
+    pub squash_penalty_cycles: usize, // spent fetching down mispredicted paths, not waiting on decode
+    pub exceptions: usize,
+}
+
+/// Snapshot of the system's performance counters, printed at halt
+#[derive(Debug, Clone, PartialEq)]
+pub struct PerfReport {
+    pub clock: Cycle,
+    pub system: SystemStats,
+    pub icache: Option<LevelStats>, // only present with a split L1
+    pub levels: Vec<LevelStats>,    // ordered from L1 to main memory
+}
+
+impl PerfReport {
+    /// Returns the average number of clock cycles per retired instruction
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn cpi(&self) -> Option<f64> {
+        let retired = self.system.instructions_retired;
+        (retired != 0).then(|| self.clock as f64 / retired as f64)
+    }
+
+    /// Returns the fraction of control flow instructions whose next address
+    /// was predicted correctly
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn prediction_accuracy(&self) -> Option<f64> {
+        let SystemStats {
+            branches,
+            mispredictions,
+            ..
+        } = self.system;
+        (branches != 0).then(|| branches.saturating_sub(mispredictions) as f64 / branches as f64)
+    }
+}
+
+impl Display for PerfReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let cpi = self
+            .cpi()
+            .map_or_else(|| "N/A".to_string(), |cpi| format!("{cpi:.3}"));
+        writeln!(f, "Clock Cycles: {}", self.clock)?;
+        writeln!(
+            f,
+            "Instructions Retired: {}",
+            self.system.instructions_retired
+        )?;
+        writeln!(f, "CPI: {cpi}")?;
+        writeln!(f, "Forwarded Operands: {}", self.system.forwarded_operands)?;
+        let accuracy = self.prediction_accuracy().map_or_else(
+            || "N/A".to_string(),
+            |accuracy| format!("{:.2}%", accuracy * 100.0),
+        );
+        writeln!(f, "Branches: {}", self.system.branches)?;
+        writeln!(
+            f,
+            "Mispredictions: {} (accuracy: {accuracy})",
+            self.system.mispredictions
+        )?;
+        writeln!(
+            f,
+            "Squashed Instructions: {} (penalty: {} cycles)",
+            self.system.squashed_instructions, self.system.squash_penalty_cycles
+        )?;
+        writeln!(f, "Exceptions: {}", self.system.exceptions)?;
+        if let Some(stats) = &self.icache {
+            writeln!(f, "\nInstruction Cache:\n{stats}")?;
+        }
+        for (level, stats) in self.levels.iter().enumerate() {
+            if level == self.levels.len() - 1 {
+                writeln!(f, "\nMain Memory (Level {level}):")?;
+            } else if level == 0 && self.icache.is_some() {
+                writeln!(f, "\nData Cache (Level 0):")?;
+            } else {
+                writeln!(f, "\nCache Level {level}:")?;
+            }
+            writeln!(f, "{stats}")?;
+        }
+
+        Ok(())
+    }
+}
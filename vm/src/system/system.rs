@@ -1,16 +1,26 @@
 use std::collections::HashSet;
-use std::path::PathBuf;
+use std::path::Path;
 
+use anyhow::{Context, Result};
 use log::{error, info};
 
-use crate::instruction::instruction::{decode_raw_instr, Instruction, RawInstruction};
+use crate::config::config::MachineConfig;
+use crate::instruction::instruction::{
+    decode_raw_instr, sign_extend_immediate, Instruction, RawInstruction,
+};
 use crate::memory::memory_system::{
     LoadRequest, LoadResponse, MemRequest, MemResponse, MemType, Memory, StoreRequest,
     MEM_BLOCK_WIDTH,
 };
 use crate::register::register_system::{
     get_comparison_flags, FlagIndex, Register, RegisterGroup, RegisterSet, FLAG_COUNT, RET_REG,
+    TRAP_CAUSE_REG,
 };
+use crate::system::branch_predictor::BranchPredictor;
+use crate::system::bypass::BypassNetwork;
+use crate::system::image::ProgramImage;
+use crate::system::stats::{PerfReport, SystemStats};
+use crate::system::trap::{Trap, TrapCause};
 
 use crate::memory::memory_system::MemBlock;
 
@@ -25,6 +35,8 @@ pub enum SystemMessage {
     #[default]
     InstructionCompleted,
     InstructionPending,
+    // raised by the oldest instruction in flight
+    Exception(Trap),
     // fill in others as needed
 }
 
@@ -59,6 +71,9 @@ pub enum PipelineInstructionResult {
     Flag {
         flags: [Option<bool>; FLAG_COUNT],
     },
+    Exception {
+        cause: TrapCause, // raised once the instruction reaches writeback
+    },
     Empty, // indicate an operation was completed, but there's no data to show for it (e.g.
            // a store to memory)
 }
@@ -67,10 +82,13 @@ pub enum PipelineInstructionResult {
 pub struct FetchState {
     pub src_addr: Option<usize>,
     pub raw_instr: Option<u32>,
+    pub predicted_pc: Option<u32>,
+    pub fetch_busy_at: Option<Cycle>, // `fetch_busy_cycles` when fetch moved on to `predicted_pc`
 }
 
 pub struct System {
     pub clock: usize,
+    config: MachineConfig,
     pub memory_system: Memory,
     pub registers: RegisterSet,
     should_use_pipeline: bool,
@@ -81,73 +99,124 @@ pub struct System {
     pub memory: PipelineStageStatus,
     pub writeback: PipelineStageStatus,
     pub pending_reg: HashSet<(RegisterGroup, usize)>,
+    pub bypass: BypassNetwork,
+    pub predictor: BranchPredictor,
+    pub stats: SystemStats,
+    pub fault: Option<Trap>,  // unhandled exception the system stopped on
+    fetch_busy_cycles: Cycle, // spent fetching, rather than holding an instruction for decode
+}
+
+// For debugging purposes
+impl Default for System {
+    fn default() -> Self {
+        Self::new(MachineConfig::default()).unwrap()
+    }
 }
 
 impl System {
-    // For debugging purposes
-    pub fn default() -> Self {
-        Self {
+    /// Builds a new system as described by `config`
+    pub fn new(config: MachineConfig) -> Result<Self> {
+        config.validate()?;
+        let memory_system = Memory::new(config.line_len, &config.levels, config.icache.as_ref())
+            .context("Failed to construct the memory system")?;
+        let mut registers = RegisterSet::new();
+        registers.program_counter = config.start_pc;
+
+        Ok(Self {
             clock: 0,
             pending_reg: HashSet::new(),
-            memory_system: Memory::new(4, &[64, 64, 256], &[1, 10, 100]),
-            should_use_pipeline: true,
-            registers: RegisterSet::new(),
+            bypass: BypassNetwork::new(config.forwarding),
+            predictor: BranchPredictor::new(
+                config.branch_predictor.kind,
+                config.branch_predictor.btb_entries,
+            ),
+            memory_system,
+            should_use_pipeline: config.pipeline,
+            registers,
             fetch: FetchState::default(),
             decode: PipelineStageStatus::Noop,
             execute: PipelineStageStatus::Noop,
             memory: PipelineStageStatus::Noop,
             writeback: PipelineStageStatus::Noop,
-        }
+            stats: SystemStats::default(),
+            fault: None,
+            fetch_busy_cycles: 0,
+            config,
+        })
     }
 
-    pub fn reset(&mut self) {
-        let n_levels = self.memory_system.num_levels();
-
-        let mut capacities = Vec::new();
-        let mut latencies = Vec::new();
-        for level in 0..n_levels {
-            capacities.push(self.memory_system.num_lines(level).unwrap());
-            latencies.push(self.memory_system.get_latency(level).unwrap());
-        }
+    /// Returns the machine description the system was built from
+    pub fn config(&self) -> &MachineConfig {
+        &self.config
+    }
 
+    pub fn reset(&mut self) {
         self.clock = 0;
         self.pending_reg.clear();
-        self.memory_system =
-            Memory::new(self.memory_system.get_line_len(), &capacities, &latencies);
+        // the config was validated on construction, so this can't fail
+        self.memory_system = Memory::new(
+            self.config.line_len,
+            &self.config.levels,
+            self.config.icache.as_ref(),
+        )
+        .unwrap();
         self.registers = RegisterSet::new();
+        self.registers.program_counter = self.config.start_pc;
         self.fetch = FetchState::default();
         self.decode = PipelineStageStatus::Noop;
         self.execute = PipelineStageStatus::Noop;
         self.memory = PipelineStageStatus::Noop;
         self.writeback = PipelineStageStatus::Noop;
+        self.bypass = BypassNetwork::new(self.config.forwarding);
+        self.predictor = BranchPredictor::new(
+            self.config.branch_predictor.kind,
+            self.config.branch_predictor.btb_entries,
+        );
+        self.stats = SystemStats::default();
+        self.fault = None;
+        self.fetch_busy_cycles = 0;
     }
 
-    pub fn load_program(&mut self, path: PathBuf) {
-        info!("Loading program file {:?}", path);
-        let program = std::fs::read(&path).unwrap();
-        info!("Loaded: {:?}", program);
-
-        // check the length
-        let program_len = program.len() * 8;
-        let mem_len = self.memory_system.main_capacity().unwrap();
-        if program_len > mem_len {
-            error!(
-                "Program {} is too large to fit in main memory: {program_len} > {mem_len}",
-                path.display()
-            );
-            panic!("Program too large");
+    /// Collects the pipeline and per memory level performance counters
+    pub fn report(&self) -> PerfReport {
+        PerfReport {
+            clock: self.clock,
+            system: self.stats,
+            icache: self.memory_system.icache_stats(),
+            levels: (0..self.memory_system.num_levels())
+                .map(|level| self.memory_system.level_stats(level).unwrap())
+                .collect(),
         }
+    }
 
-        for (i, instr) in program.windows(4).step_by(4).enumerate() {
-            if instr.len() != 4 {
-                error!("Program length isn't an integer multiple of 32 bits");
-                panic!("Invalid program length");
+    /// Loads the program at `path`, which holds either a program container or
+    /// a legacy raw image
+    pub fn load_program(&mut self, path: &Path) -> Result<()> {
+        info!("Loading program file {}", path.display());
+        let bytes = std::fs::read(path)
+            .with_context(|| format!("Failed to read program file {}", path.display()))?;
+        let image = ProgramImage::parse(&bytes)
+            .with_context(|| format!("Invalid program file {}", path.display()))?;
+        self.load_image(&image)
+            .with_context(|| format!("Can't load program file {}", path.display()))
+    }
+
+    /// Stores every segment of `image` in main memory, starting execution
+    /// at its entry point if it has one
+    pub fn load_image(&mut self, image: &ProgramImage) -> Result<()> {
+        image.validate(self.memory_system.main_capacity()?)?;
+        for segment in &image.segments {
+            for (i, word) in segment.words.iter().enumerate() {
+                let data = MemBlock::Unsigned32(*word);
+                self.memory_system
+                    .force_store(segment.address as usize + i * MEM_BLOCK_WIDTH, data);
             }
-            let bytes = [instr[0], instr[1], instr[2], instr[3]];
-            let data = MemBlock::Unsigned32(u32::from_be_bytes(bytes));
-            self.memory_system.force_store(i * MEM_BLOCK_WIDTH, data);
+        }
+        if let Some(entry) = image.entry {
+            self.registers.program_counter = entry;
         }
         info!("Done");
+        Ok(())
     }
 
     fn run_no_pipeline(&mut self) -> SystemMessage {
@@ -171,11 +240,19 @@ impl System {
                     self.fetch.raw_instr = Some(raw);
                     raw
                 } else {
-                    info!(
-                        "NoPipeline: Got unnexpected data for instruction fetch, passing on a noop"
+                    error!("NoPipeline: Got unnexpected data for instruction fetch");
+                    return self.raise_trap(
+                        TrapCause::IllegalInstruction,
+                        self.registers.program_counter,
                     );
-                    todo!()
                 }
+            } else if let Some(cause) = raw_resp
+                .as_ref()
+                .err()
+                .and_then(|e| e.downcast_ref::<TrapCause>())
+            {
+                error!("NoPipeline: Instruction fetch failed: {cause}");
+                return self.raise_trap(*cause, self.registers.program_counter);
             } else {
                 info!(
                     "NoPipeline: Recieved resp from memory system {:?}",
@@ -186,13 +263,27 @@ impl System {
         };
         info!("NoPipeline: Fetched {raw_instr}");
 
-        let decoded_instr = if let Some(instr) = decode_raw_instr(raw_instr) {
-            instr
-        } else {
-            error!("NoPipeline: Failed to decode raw instruction {raw_instr}, passing on a NOOP");
-            todo!()
-        };
+        let decoded_instr =
+            if let Some(instr) = decode_raw_instr(raw_instr).filter(Instruction::is_legal) {
+                instr
+            } else {
+                error!("NoPipeline: Failed to decode raw instruction {raw_instr}");
+                return self.raise_trap(
+                    TrapCause::IllegalInstruction,
+                    self.registers.program_counter,
+                );
+            };
         info!("NoPipeline: Decoded instruction to {:?}", decoded_instr);
+        // check for exceptions up front so no partial results are committed
+        if let Some(cause) = decoded_instr
+            .get_mem_req(Some(PipelineStage::Execute), &self.registers.general)
+            .and_then(|req| self.memory_system.check_access(req.get_address()))
+        {
+            return self.raise_trap(cause, self.registers.program_counter);
+        }
+        if decoded_instr.divides_by_zero(&self.registers.general, &self.registers.float) {
+            return self.raise_trap(TrapCause::DivideByZero, self.registers.program_counter);
+        }
         match decoded_instr {
             Instruction::Type0 { opcode } => {
                 info!("NoPipeline: Got Type 0 instruction, opcode: {opcode}");
@@ -312,7 +403,10 @@ impl System {
                         info!("NoPipeline: IJE instruction");
                         if self.registers.status.get(FlagIndex::EQ as usize) {
                             info!("NoPipeline: Jumping");
-                            self.registers.program_counter += immediate;
+                            self.registers.program_counter = self
+                                .registers
+                                .program_counter
+                                .wrapping_add(sign_extend_immediate(immediate));
                             self.fetch = FetchState::default();
                             return SystemMessage::InstructionCompleted;
                         } else {
@@ -324,7 +418,10 @@ impl System {
                         info!("NoPipeline: IJNE instruction");
                         if !self.registers.status.get(FlagIndex::EQ as usize) {
                             info!("NoPipeline: Jumping");
-                            self.registers.program_counter += immediate;
+                            self.registers.program_counter = self
+                                .registers
+                                .program_counter
+                                .wrapping_add(sign_extend_immediate(immediate));
                             self.fetch = FetchState::default();
                             return SystemMessage::InstructionCompleted;
                         } else {
@@ -336,7 +433,10 @@ impl System {
                         info!("NoPipeline: IJGT instruction");
                         if self.registers.status.get(FlagIndex::GT as usize) {
                             info!("NoPipeline: Jumping");
-                            self.registers.program_counter += immediate;
+                            self.registers.program_counter = self
+                                .registers
+                                .program_counter
+                                .wrapping_add(sign_extend_immediate(immediate));
                             self.fetch = FetchState::default();
                             return SystemMessage::InstructionCompleted;
                         } else {
@@ -348,7 +448,10 @@ impl System {
                         info!("NoPipeline: IJLT instruction");
                         if self.registers.status.get(FlagIndex::LT as usize) {
                             info!("NoPipeline: Jumping");
-                            self.registers.program_counter += immediate;
+                            self.registers.program_counter = self
+                                .registers
+                                .program_counter
+                                .wrapping_add(sign_extend_immediate(immediate));
                             self.fetch = FetchState::default();
                             return SystemMessage::InstructionCompleted;
                         } else {
@@ -362,7 +465,10 @@ impl System {
                             || self.registers.status.get(FlagIndex::GT as usize)
                         {
                             info!("NoPipeline: Jumping");
-                            self.registers.program_counter += immediate;
+                            self.registers.program_counter = self
+                                .registers
+                                .program_counter
+                                .wrapping_add(sign_extend_immediate(immediate));
                             self.fetch = FetchState::default();
                             return SystemMessage::InstructionCompleted;
                         } else {
@@ -376,7 +482,10 @@ impl System {
                             || self.registers.status.get(FlagIndex::LT as usize)
                         {
                             info!("NoPipeline: Jumping");
-                            self.registers.program_counter += immediate;
+                            self.registers.program_counter = self
+                                .registers
+                                .program_counter
+                                .wrapping_add(sign_extend_immediate(immediate));
                             self.fetch = FetchState::default();
                             return SystemMessage::InstructionCompleted;
                         } else {
@@ -851,7 +960,9 @@ impl System {
                         "NoPipeline: Adding immediate {} to register {}",
                         immediate, reg_1
                     );
-                    let data = self.registers.general[reg_1].data.add_immediate(immediate);
+                    let data = self.registers.general[reg_1]
+                        .data
+                        .add_immediate(sign_extend_immediate(immediate));
                     self.registers.general[reg_1] = Register { data };
                 }
                 _ => {
@@ -1090,6 +1201,7 @@ impl System {
         );
         match (self.fetch.raw_instr, decode_blocked) {
             (None, _) => {
+                self.fetch_busy_cycles += 1;
                 // If no current instruction, send load to cache with PC as address
                 let fetch_addr = self.registers.program_counter as usize;
                 let req = MemRequest::Load(LoadRequest {
@@ -1140,10 +1252,17 @@ impl System {
                                 }
                             };
                             error!("GOT HERE: {decode_blocked}");
+                            // predecode the instruction to pick where to fetch from next
+                            let predicted_pc =
+                                self.predictor.predict(fetch_addr, decode_raw_instr(raw));
+                            info!("Pipeline::Fetch: Predicted next PC: {predicted_pc}");
+                            self.registers.program_counter = predicted_pc;
                             if decode_blocked {
                                 info!("Pipeline::Fetch: Fetched instruction, decode is blocked, saving for next cycle");
                                 self.fetch.raw_instr = Some(raw);
                                 self.fetch.src_addr = Some(fetch_addr);
+                                self.fetch.predicted_pc = Some(predicted_pc);
+                                self.fetch.fetch_busy_at = Some(self.fetch_busy_cycles);
                                 PipelineStageStatus::Noop
                             } else {
                                 let fetched =
@@ -1152,6 +1271,8 @@ impl System {
                                         raw_instr: Some(raw),
                                         decode_instr: None,
                                         instr_result: PipelineInstructionResult::Empty,
+                                        predicted_pc: Some(predicted_pc),
+                                        fetch_busy_at: Some(self.fetch_busy_cycles),
                                     });
                                 info!("Pipeline::Fetch: Passing on raw instruction: {:?}", fetched);
                                 fetched
@@ -1174,8 +1295,25 @@ impl System {
                         PipelineStageStatus::Stall
                     }
                     Err(e) => {
-                        error!("Pipeline::Fetch: Got error {e} from memory subsystem, translating into NOOP");
-                        PipelineStageStatus::Noop
+                        if let (Some(cause), false) =
+                            (e.downcast_ref::<TrapCause>(), decode_blocked)
+                        {
+                            // the fetch is retried until decode can take the exception
+                            error!("Pipeline::Fetch: Fetch from {fetch_addr} raised {cause}, passing on an exception");
+                            PipelineStageStatus::Instruction(PipelineInstruction {
+                                src_addr: Some(fetch_addr),
+                                raw_instr: None,
+                                decode_instr: None,
+                                instr_result: PipelineInstructionResult::Exception {
+                                    cause: *cause,
+                                },
+                                predicted_pc: None,
+                                fetch_busy_at: None,
+                            })
+                        } else {
+                            error!("Pipeline::Fetch: Got error {e} from memory subsystem, translating into NOOP");
+                            PipelineStageStatus::Noop
+                        }
                     }
                 }
             }
@@ -1186,13 +1324,16 @@ impl System {
                 );
                 let raw_instr = self.fetch.raw_instr;
                 let src_addr = self.fetch.src_addr;
-                self.fetch.raw_instr = None;
-                self.fetch.src_addr = None;
+                let predicted_pc = self.fetch.predicted_pc;
+                let fetch_busy_at = self.fetch.fetch_busy_at;
+                self.fetch = FetchState::default();
                 PipelineStageStatus::Instruction(PipelineInstruction {
                     raw_instr,
                     src_addr,
                     decode_instr: None,
                     instr_result: PipelineInstructionResult::Empty,
+                    predicted_pc,
+                    fetch_busy_at,
                 })
             }
             (Some(instr), true) => {
@@ -1205,6 +1346,7 @@ impl System {
         }
     }
 
+    #[allow(clippy::too_many_lines)]
     fn pipeline_decode(&mut self, exec_blocked: bool) -> PipelineStageStatus {
         info!(
             "Pipeline::Decode: In decode stage, current instruction: {:?}, exec blocked: {}",
@@ -1216,18 +1358,24 @@ impl System {
             PipelineStageStatus::Instruction(ref mut instruction)
                 if instruction.decode_instr.is_none() =>
             {
-                if let Some(raw) = instruction.raw_instr {
+                if let Some(cause) = instruction.trap_cause() {
+                    info!("Pipeline::Decode: Passing on {cause} exception");
+                } else if let Some(raw) = instruction.raw_instr {
                     // split instruction into fields
-                    if let Some(instr) = decode_raw_instr(raw) {
+                    if let Some(instr) = decode_raw_instr(raw).filter(Instruction::is_legal) {
                         let src_regs = instr.get_src_regs();
-                        pending_regs = src_regs.iter().any(|src| self.pending_reg.contains(src));
+                        pending_regs = src_regs
+                            .iter()
+                            .any(|src| self.bypass.must_stall(&self.pending_reg, src));
                         info!("Pipeline::Decode: Pending source registers: {pending_regs}");
                         if !pending_regs {
                             instruction.decode_instr = Some(instr);
                         }
                     } else {
-                        error!("Pipeline::Decode: Failed to decode raw instruction {raw}, passing on a NOOP");
-                        self.decode = PipelineStageStatus::Noop;
+                        error!("Pipeline::Decode: Failed to decode raw instruction {raw}, raising an exception");
+                        instruction.instr_result = PipelineInstructionResult::Exception {
+                            cause: TrapCause::IllegalInstruction,
+                        };
                     }
                 } else {
                     error!(
@@ -1241,6 +1389,14 @@ impl System {
                     "Pipeline::Decode: Current instruction already decoded: {:?}",
                     instr
                 );
+                // a forwarded operand may have been replaced by a newer writer
+                // that hasn't produced its result yet, so check again
+                if let Some(decoded) = instr.decode_instr {
+                    pending_regs = decoded
+                        .get_src_regs()
+                        .iter()
+                        .any(|src| self.bypass.must_stall(&self.pending_reg, src));
+                }
             }
             PipelineStageStatus::Stall => {
                 // if Noop/Stall, do nothing
@@ -1275,6 +1431,14 @@ impl System {
                     self.decode
                 };
                 if let PipelineStageStatus::Instruction(instr) = completed_instr {
+                    // any source still pending at this point is being forwarded
+                    if let Some(decoded) = instr.decode_instr {
+                        self.stats.forwarded_operands += decoded
+                            .get_src_regs()
+                            .iter()
+                            .filter(|src| self.pending_reg.contains(src))
+                            .count();
+                    }
                     if let Some(reg) = instr.get_dest_reg() {
                         info!(
                             "Pipeline::Decode: Inserting {:?} into pending registers",
@@ -1316,11 +1480,30 @@ impl System {
             error!("Pipeline::Execute: Unable to find address for instruction ");
             0
         };
+        // operands still in flight are read from the bypass network
+        let mut general = self.bypass.apply(
+            RegisterGroup::General,
+            &self.registers.general,
+            self.pending_reg.iter(),
+        );
+        let mut float = self.bypass.apply(
+            RegisterGroup::FloatingPoint,
+            &self.registers.float,
+            self.pending_reg.iter(),
+        );
         // execute appears to pass along a more "filled in" instruction object, look into this...
         match self.execute {
             PipelineStageStatus::Instruction(ref mut instr) => {
                 info!("Pipeline::Execute: Have current instruction: {:?}", instr);
-                if let Some(ref mut instruction) = instr.decode_instr {
+                if instr
+                    .decode_instr
+                    .is_some_and(|instruction| instruction.divides_by_zero(&general, &float))
+                {
+                    error!("Pipeline::Execute: Division by zero, raising an exception");
+                    instr.instr_result = PipelineInstructionResult::Exception {
+                        cause: TrapCause::DivideByZero,
+                    };
+                } else if let Some(ref mut instruction) = instr.decode_instr {
                     match instruction {
                         Instruction::Type0 { opcode } => {
                             info!("Pipeline::Execute: Type 0 instruction");
@@ -1328,8 +1511,7 @@ impl System {
                                 // RET
                                 0 => {
                                     info!("RET instruction, setting branch result");
-                                    let addr =
-                                        self.registers.general[RET_REG].data.force_unsigned();
+                                    let addr = general[RET_REG].data.force_unsigned();
                                     instr.instr_result =
                                         PipelineInstructionResult::Branch { new_pc: addr }
                                 }
@@ -1430,7 +1612,9 @@ impl System {
                                         if self.registers.status.get(FlagIndex::EQ as usize) {
                                             info!("IJE Instruction...EQ flag is set");
                                             PipelineInstructionResult::Branch {
-                                                new_pc: src_addr + *immediate,
+                                                new_pc: src_addr.wrapping_add(
+                                                    sign_extend_immediate(*immediate),
+                                                ),
                                             }
                                         } else {
                                             info!("IJE Instruction...EQ flag is not set");
@@ -1443,7 +1627,9 @@ impl System {
                                         if !self.registers.status.get(FlagIndex::EQ as usize) {
                                             info!("IJNE Instruction...EQ flag is not set");
                                             PipelineInstructionResult::Branch {
-                                                new_pc: src_addr + *immediate,
+                                                new_pc: src_addr.wrapping_add(
+                                                    sign_extend_immediate(*immediate),
+                                                ),
                                             }
                                         } else {
                                             info!("IJNE Instruction...EQ flag is set");
@@ -1456,7 +1642,9 @@ impl System {
                                         if self.registers.status.get(FlagIndex::GT as usize) {
                                             info!("IJNE Instruction...GT flag is set");
                                             PipelineInstructionResult::Branch {
-                                                new_pc: src_addr + *immediate,
+                                                new_pc: src_addr.wrapping_add(
+                                                    sign_extend_immediate(*immediate),
+                                                ),
                                             }
                                         } else {
                                             info!("IJNE Instruction...GT flag is not set");
@@ -1469,7 +1657,9 @@ impl System {
                                         if self.registers.status.get(FlagIndex::LT as usize) {
                                             info!("IJLE Instruction...LT flag is set");
                                             PipelineInstructionResult::Branch {
-                                                new_pc: src_addr + *immediate,
+                                                new_pc: src_addr.wrapping_add(
+                                                    sign_extend_immediate(*immediate),
+                                                ),
                                             }
                                         } else {
                                             info!("IJLE Instruction...LT flag is not set");
@@ -1484,7 +1674,9 @@ impl System {
                                         {
                                             info!("IJGTE Instruction...EQ or GT flag is set");
                                             PipelineInstructionResult::Branch {
-                                                new_pc: src_addr + *immediate,
+                                                new_pc: src_addr.wrapping_add(
+                                                    sign_extend_immediate(*immediate),
+                                                ),
                                             }
                                         } else {
                                             info!("IJGTE Instruction...EQ and GT flag are not set");
@@ -1499,7 +1691,9 @@ impl System {
                                         {
                                             info!("IJLTE Instruction...EQ or LT flag is set");
                                             PipelineInstructionResult::Branch {
-                                                new_pc: src_addr + *immediate,
+                                                new_pc: src_addr.wrapping_add(
+                                                    sign_extend_immediate(*immediate),
+                                                ),
                                             }
                                         } else {
                                             info!("IJLTE Instruction...EQ and LT flag are not set");
@@ -1519,10 +1713,7 @@ impl System {
                         } => match opcode {
                             0..=2 => {
                                 info!("Pipeline::Execute: Comparing general registers {reg_1} and {reg_2}");
-                                let flags = get_comparison_flags(
-                                    self.registers.general[*reg_1],
-                                    self.registers.general[*reg_2],
-                                );
+                                let flags = get_comparison_flags(general[*reg_1], general[*reg_2]);
                                 instr.instr_result = PipelineInstructionResult::Flag { flags };
                             }
                             _ => {
@@ -1535,10 +1726,7 @@ impl System {
                             freg_2,
                         } => {
                             info!("Pipeline::Execute: Comparing floating point registers {freg_1} and {freg_2}");
-                            let flags = get_comparison_flags(
-                                self.registers.float[*freg_1],
-                                self.registers.float[*freg_2],
-                            );
+                            let flags = get_comparison_flags(float[*freg_1], float[*freg_2]);
                             instr.instr_result = PipelineInstructionResult::Flag { flags };
                         }
                         Instruction::Type4 {
@@ -1551,9 +1739,9 @@ impl System {
                                     "Pipeline::Execute: Adding immediate {} to register {}",
                                     *immediate, *reg_1
                                 );
-                                let data = self.registers.general[*reg_1]
+                                let data = general[*reg_1]
                                     .data
-                                    .add_immediate(*immediate);
+                                    .add_immediate(sign_extend_immediate(*immediate));
                                 instr.instr_result = PipelineInstructionResult::Register {
                                     reg_group: RegisterGroup::General,
                                     dest_reg: *reg_1,
@@ -1574,9 +1762,8 @@ impl System {
                             match opcode {
                                 // ADDI
                                 0 => {
-                                    let data = self.registers.general[*reg_2]
-                                        .data
-                                        .add_register(self.registers.general[*reg_3].data);
+                                    let data =
+                                        general[*reg_2].data.add_register(general[*reg_3].data);
                                     info!(
                                         "Pipeline::Execute: Adding register {} to register {}",
                                         *reg_2, *reg_3
@@ -1589,9 +1776,8 @@ impl System {
                                 }
                                 // SUBI
                                 1 => {
-                                    let data = self.registers.general[*reg_2]
-                                        .data
-                                        .sub_register(self.registers.general[*reg_3].data);
+                                    let data =
+                                        general[*reg_2].data.sub_register(general[*reg_3].data);
                                     info!(
                                         "Pipeline::Execute: Subtracting register {} from register {}",
                                         *reg_3, *reg_2
@@ -1604,9 +1790,8 @@ impl System {
                                 }
                                 // MULI
                                 2 => {
-                                    let data = self.registers.general[*reg_2]
-                                        .data
-                                        .mul_register(self.registers.general[*reg_3].data);
+                                    let data =
+                                        general[*reg_2].data.mul_register(general[*reg_3].data);
                                     info!(
                                         "Pipeline::Execute: Multiplying register {} with register {}",
                                         *reg_2, *reg_3
@@ -1619,9 +1804,8 @@ impl System {
                                 }
                                 // DIVI
                                 3 => {
-                                    let data = self.registers.general[*reg_2]
-                                        .data
-                                        .div_register(self.registers.general[*reg_3].data);
+                                    let data =
+                                        general[*reg_2].data.div_register(general[*reg_3].data);
                                     info!(
                                         "Pipeline::Execute: Dividing register {} by register {}",
                                         *reg_2, *reg_3
@@ -1634,9 +1818,8 @@ impl System {
                                 }
                                 // MODI
                                 4 => {
-                                    let data = self.registers.general[*reg_2]
-                                        .data
-                                        .mod_register(self.registers.general[*reg_3].data);
+                                    let data =
+                                        general[*reg_2].data.mod_register(general[*reg_3].data);
                                     info!(
                                         "Pipeline::Execute: Modulo register {} by register {}",
                                         *reg_2, *reg_3
@@ -1649,9 +1832,9 @@ impl System {
                                 }
                                 // RBSI
                                 5 => {
-                                    let data = self.registers.general[*reg_2]
+                                    let data = general[*reg_2]
                                         .data
-                                        .right_shift_register(self.registers.general[*reg_3].data);
+                                        .right_shift_register(general[*reg_3].data);
                                     info!(
                                         "Pipeline::Execute: Right bit shift register {} by register {}",
                                         *reg_2, *reg_3
@@ -1664,9 +1847,8 @@ impl System {
                                 }
                                 // XORI
                                 6 => {
-                                    let data = self.registers.general[*reg_2]
-                                        .data
-                                        .xor_register(self.registers.general[*reg_3].data);
+                                    let data =
+                                        general[*reg_2].data.xor_register(general[*reg_3].data);
                                     info!(
                                         "Pipeline::Execute: XOR register {} with register {}",
                                         *reg_2, *reg_3
@@ -1679,9 +1861,8 @@ impl System {
                                 }
                                 // ANDI
                                 7 => {
-                                    let data = self.registers.general[*reg_2]
-                                        .data
-                                        .and_register(self.registers.general[*reg_3].data);
+                                    let data =
+                                        general[*reg_2].data.and_register(general[*reg_3].data);
                                     info!(
                                         "Pipeline::Execute: AND register {} with register {}",
                                         *reg_2, *reg_3
@@ -1694,9 +1875,8 @@ impl System {
                                 }
                                 // ORI
                                 8 => {
-                                    let data = self.registers.general[*reg_2]
-                                        .data
-                                        .or_register(self.registers.general[*reg_3].data);
+                                    let data =
+                                        general[*reg_2].data.or_register(general[*reg_3].data);
                                     info!(
                                         "Pipeline::Execute: OR register {} with register {}",
                                         *reg_2, *reg_3
@@ -1709,9 +1889,8 @@ impl System {
                                 }
                                 // ADDU
                                 9 => {
-                                    let data = self.registers.general[*reg_2]
-                                        .data
-                                        .add_register(self.registers.general[*reg_3].data);
+                                    let data =
+                                        general[*reg_2].data.add_register(general[*reg_3].data);
                                     info!(
                                         "Pipeline::Execute: Add register {} with register {}",
                                         *reg_2, *reg_3
@@ -1724,9 +1903,8 @@ impl System {
                                 }
                                 // SUBU
                                 10 => {
-                                    let data = self.registers.general[*reg_2]
-                                        .data
-                                        .sub_register(self.registers.general[*reg_3].data);
+                                    let data =
+                                        general[*reg_2].data.sub_register(general[*reg_3].data);
                                     info!(
                                         "Pipeline::Execute: Subtract register {} from register {}",
                                         *reg_3, *reg_2
@@ -1739,9 +1917,8 @@ impl System {
                                 }
                                 // MULU
                                 11 => {
-                                    let data = self.registers.general[*reg_2]
-                                        .data
-                                        .mul_register(self.registers.general[*reg_3].data);
+                                    let data =
+                                        general[*reg_2].data.mul_register(general[*reg_3].data);
                                     info!(
                                         "Pipeline::Execute: Multiply register {} with register {}",
                                         *reg_2, *reg_3
@@ -1754,9 +1931,8 @@ impl System {
                                 }
                                 // DIVU
                                 12 => {
-                                    let data = self.registers.general[*reg_2]
-                                        .data
-                                        .div_register(self.registers.general[*reg_3].data);
+                                    let data =
+                                        general[*reg_2].data.div_register(general[*reg_3].data);
                                     info!(
                                         "Pipeline::Execute: Divide register {} by register {}",
                                         *reg_2, *reg_3
@@ -1769,9 +1945,8 @@ impl System {
                                 }
                                 // MODU
                                 13 => {
-                                    let data = self.registers.general[*reg_2]
-                                        .data
-                                        .mod_register(self.registers.general[*reg_3].data);
+                                    let data =
+                                        general[*reg_2].data.mod_register(general[*reg_3].data);
                                     info!(
                                         "Pipeline::Execute: Mod register {} by register {}",
                                         *reg_2, *reg_3
@@ -1797,9 +1972,8 @@ impl System {
                             match opcode {
                                 // ADDF
                                 0 => {
-                                    let data = self.registers.float[*freg_2]
-                                        .data
-                                        .add_register(self.registers.float[*freg_3].data);
+                                    let data =
+                                        float[*freg_2].data.add_register(float[*freg_3].data);
                                     info!(
                                         "Pipeline::Execute: Add register {} with register {}",
                                         *freg_2, *freg_3
@@ -1812,9 +1986,8 @@ impl System {
                                 }
                                 // SUBF
                                 1 => {
-                                    let data = self.registers.float[*freg_2]
-                                        .data
-                                        .sub_register(self.registers.float[*freg_3].data);
+                                    let data =
+                                        float[*freg_2].data.sub_register(float[*freg_3].data);
                                     info!(
                                         "Pipeline::Execute: Subtracting register {} from register {}",
                                         *freg_3, *freg_2
@@ -1827,9 +2000,8 @@ impl System {
                                 }
                                 // MULF
                                 2 => {
-                                    let data = self.registers.float[*freg_2]
-                                        .data
-                                        .mul_register(self.registers.float[*freg_3].data);
+                                    let data =
+                                        float[*freg_2].data.mul_register(float[*freg_3].data);
                                     info!(
                                         "Pipeline::Execute: Multiplying register {} with register {}",
                                         *freg_2, *freg_3
@@ -1842,9 +2014,8 @@ impl System {
                                 }
                                 // DIVF
                                 3 => {
-                                    let data = self.registers.float[*freg_2]
-                                        .data
-                                        .div_register(self.registers.float[*freg_3].data);
+                                    let data =
+                                        float[*freg_2].data.div_register(float[*freg_3].data);
                                     info!(
                                         "Pipeline::Execute: Dividing register {} by register {}",
                                         *freg_2, *freg_3
@@ -1862,6 +2033,8 @@ impl System {
                             }
                         }
                     }
+                } else if let Some(cause) = instr.trap_cause() {
+                    info!("Pipeline::Execute: Passing on {cause} exception");
                 } else {
                     error!("Pipeline::Execute: Received non-decoded instruction in execute stage");
                     panic!("Non-decoded instruction encountered in execute stage");
@@ -1884,6 +2057,14 @@ impl System {
             PipelineStageStatus::Noop
         } else {
             let completed_instr = self.execute;
+            // only publish the result once the instruction moves on, as a blocked
+            // instruction is re-executed and may read its own destination
+            if let PipelineStageStatus::Instruction(instr) = completed_instr {
+                if let Some(reg) = instr.get_dest_reg() {
+                    self.bypass.record(reg, instr.forwardable_result());
+                }
+                self.resolve_next_pc(&instr);
+            }
             info!("Pipeline::Execute: Calling decode with memory blocked = {mem_blocked}, saving result to execute's state");
             self.execute = self.pipeline_decode(mem_blocked);
             if completed_instr == PipelineStageStatus::Stall {
@@ -1983,6 +2164,7 @@ impl System {
                                         dest_reg,
                                         data,
                                     };
+                                self.bypass.record((reg_group, dest_reg), Some(data));
                                 info!("Pipeline::Memory: Calling execute stage unblocked");
                                 self.memory = self.pipeline_execute(false);
                                 info!(
@@ -1999,7 +2181,17 @@ impl System {
                             }
                             Err(e) => {
                                 error!("Pipeline::Memory: Request returned error: {e}");
-                                panic!("Pipeline::Memory: Error returned from memory system: {e}");
+                                let Some(cause) = e.downcast_ref::<TrapCause>() else {
+                                    panic!(
+                                        "Pipeline::Memory: Error returned from memory system: {e}"
+                                    );
+                                };
+                                let mut completed_instr = instr;
+                                completed_instr.instr_result =
+                                    PipelineInstructionResult::Exception { cause: *cause };
+                                info!("Pipeline::Memory: Calling execute stage unblocked");
+                                self.memory = self.pipeline_execute(false);
+                                PipelineStageStatus::Instruction(completed_instr)
                             }
                         }
                     } else {
@@ -2012,6 +2204,11 @@ impl System {
                         self.memory = self.pipeline_execute(false);
                         PipelineStageStatus::Instruction(completed_instr)
                     }
+                } else if let Some(cause) = instr.trap_cause() {
+                    info!("Pipeline::Memory: Passing on {cause} exception");
+                    let completed_instr = instr;
+                    self.memory = self.pipeline_execute(false);
+                    PipelineStageStatus::Instruction(completed_instr)
                 } else {
                     error!("Pipeline::Memory: Recieved non-decoded instruction in pipeline memory stage");
                     panic!("Pipeline::Memory: Recieved non-decoded instruction in pipeline memory stage");
@@ -2056,7 +2253,7 @@ impl System {
                         info!("Pipeline::Writeback: Writing result to register");
                         self.registers.write_normal(data, reg_group, dest_reg);
                         info!("Pipeline::Writeback: Updating pending registers");
-                        if self.pending_reg.remove(&(reg_group, dest_reg)) {
+                        if self.release_pending_reg((reg_group, dest_reg)) {
                             error!(
                                 "Pipeline::Writeback: Register group {}, number {} cleared from pending",
                                 reg_group, dest_reg
@@ -2070,12 +2267,8 @@ impl System {
                             "Pipeline::Writeback: Instruction has branch result. New PC: {}",
                             new_pc
                         );
-                        // need to write return address to R15 here in case of call, remove from
-                        // pending registers?
-                        self.registers.program_counter = new_pc;
-                        info!("Pipeline::Writeback: Branch instruction, squashing the rest of the pipeline");
-                        // breaking stuff here, causes fetch to skip over an instruction????
-                        self.squash();
+                        // the fetch stage was already redirected when the branch
+                        // was resolved in execute
                     }
                     PipelineInstructionResult::JumpSubRoutine {
                         new_pc,
@@ -2085,13 +2278,10 @@ impl System {
                             "Pipeline::Writeback: Instruction has JSR result. New PC: {}, Return Register Value: {}",
                             new_pc, ret_reg_val
                         );
-                        self.registers.program_counter = new_pc;
                         let addr_data = MemBlock::Unsigned32(ret_reg_val);
                         self.registers
                             .write_normal(addr_data, RegisterGroup::General, RET_REG);
-                        info!("Pipeline::Writeback: Jump Subroutine instruction, squashing the rest of the pipeline");
-                        self.squash();
-                        self.pending_reg.remove(&(RegisterGroup::General, RET_REG));
+                        self.release_pending_reg((RegisterGroup::General, RET_REG));
                     }
                     PipelineInstructionResult::Flag { flags } => {
                         error!(
@@ -2104,12 +2294,19 @@ impl System {
                                 self.registers.write_status(idx, *flag)
                             }
                         }
-                        self.pending_reg.remove(&(RegisterGroup::Flag, 0));
+                        self.release_pending_reg((RegisterGroup::Flag, 0));
                     }
                     PipelineInstructionResult::Empty => {
                         info!("Pipeline::Writeback: Instruction has empty result, doing nothing");
                     }
+                    PipelineInstructionResult::Exception { cause } => {
+                        // neither the faulting instruction nor anything behind it
+                        // retires
+                        let pc = u32::try_from(instr.src_addr.unwrap_or_default()).unwrap();
+                        return self.raise_trap(cause, pc);
+                    }
                 }
+                self.stats.instructions_retired += 1;
             }
             PipelineStageStatus::Stall => {
                 // if Noop/Stall, do nothing
@@ -2145,10 +2342,18 @@ impl System {
 
     pub fn step(&mut self) -> SystemMessage {
         info!("Starting a system step");
+        if let Some(trap) = self.fault {
+            info!("Stopped on exception: {trap}");
+            return SystemMessage::Exception(trap);
+        }
         let msg = if self.should_use_pipeline() {
             self.pipeline_run()
         } else {
-            self.run_no_pipeline()
+            let msg = self.run_no_pipeline();
+            if let SystemMessage::Halt | SystemMessage::InstructionCompleted = msg {
+                self.stats.instructions_retired += 1;
+            }
+            msg
         };
         info!("Updating the memory system's clock");
         self.memory_system.update_clock();
@@ -2157,13 +2362,94 @@ impl System {
         msg
     }
 
-    fn squash(&mut self) {
-        self.memory = PipelineStageStatus::Noop;
-        self.execute = PipelineStageStatus::Noop;
+    /// Checks the address fetched after `instr` against the one it actually
+    /// continues at, training the branch predictor and squashing the
+    /// instructions behind it on a misprediction
+    fn resolve_next_pc(&mut self, instr: &PipelineInstruction) {
+        let (Some(decoded), Some(src_addr), Some(predicted_pc)) =
+            (instr.decode_instr, instr.src_addr, instr.predicted_pc)
+        else {
+            return;
+        };
+        let fallthrough = u32::try_from(src_addr + MEM_BLOCK_WIDTH).unwrap();
+        let next_pc = match instr.instr_result {
+            PipelineInstructionResult::Branch { new_pc }
+            | PipelineInstructionResult::JumpSubRoutine { new_pc, .. } => new_pc,
+            _ => fallthrough,
+        };
+        if decoded.is_control_flow() {
+            self.stats.branches += 1;
+            self.predictor
+                .update(src_addr, next_pc != fallthrough, next_pc);
+        }
+        if next_pc != predicted_pc {
+            info!("Pipeline::Execute: Mispredicted {decoded}, predicted {predicted_pc}, redirecting to {next_pc}");
+            self.stats.mispredictions += 1;
+            // every cycle spent fetching since then went down the wrong path.
+            // Cycles fetch sat waiting on decode would've been spent anyway
+            if let Some(fetch_busy_at) = instr.fetch_busy_at {
+                self.stats.squash_penalty_cycles += self.fetch_busy_cycles - fetch_busy_at;
+            }
+            self.squash(next_pc);
+        }
+    }
+
+    /// Discards the instructions in decode and fetch, and resumes fetching
+    /// from `new_pc`. Older instructions are left to complete
+    fn squash(&mut self, new_pc: u32) {
+        if let PipelineStageStatus::Instruction(_) = self.decode {
+            self.stats.squashed_instructions += 1;
+        }
+        if self.fetch.raw_instr.is_some() {
+            self.stats.squashed_instructions += 1;
+        }
         self.decode = PipelineStageStatus::Noop;
         self.fetch = FetchState::default();
-        self.memory_system.clear_reqs();
+        self.memory_system.clear_reqs_from(PipelineStage::Fetch);
+        self.registers.program_counter = new_pc;
+    }
+
+    /// Raises an exception for the instruction at `pc`, discarding everything
+    /// in flight. Execution continues at the trap handler if one is
+    /// configured, otherwise the system stops
+    fn raise_trap(&mut self, cause: TrapCause, pc: u32) -> SystemMessage {
+        let trap = Trap {
+            cause,
+            pc,
+            handler: self.config.trap_handler,
+        };
+        error!("Raising exception: {trap}");
+        self.stats.exceptions += 1;
+        for stage in [self.decode, self.execute, self.memory] {
+            if let PipelineStageStatus::Instruction(_) = stage {
+                self.stats.squashed_instructions += 1;
+            }
+        }
+        if self.fetch.raw_instr.is_some() && self.should_use_pipeline() {
+            self.stats.squashed_instructions += 1;
+        }
+        self.fetch = FetchState::default();
+        self.decode = PipelineStageStatus::Noop;
+        self.execute = PipelineStageStatus::Noop;
+        self.memory = PipelineStageStatus::Noop;
+        self.writeback = PipelineStageStatus::Noop;
         self.pending_reg.clear();
+        self.bypass.clear();
+        self.memory_system.clear_reqs();
+
+        if let Some(handler) = trap.handler {
+            self.registers.general[RET_REG] = Register {
+                data: MemBlock::Unsigned32(pc),
+            };
+            self.registers.general[TRAP_CAUSE_REG] = Register {
+                data: MemBlock::Unsigned32(cause.code()),
+            };
+            self.registers.program_counter = handler;
+        } else {
+            self.registers.program_counter = pc;
+            self.fault = Some(trap);
+        }
+        SystemMessage::Exception(trap)
     }
 
     fn should_use_pipeline(&self) -> bool {
@@ -2174,6 +2460,24 @@ impl System {
         self.should_use_pipeline = !self.should_use_pipeline;
     }
 
+    pub fn toggle_forwarding(&mut self) {
+        self.bypass.set_enabled(!self.bypass.is_enabled());
+    }
+
+    /// Returns whether an instruction still in the memory or execute stage
+    /// will write to `reg`
+    fn has_pending_writer(&self, reg: (RegisterGroup, usize)) -> bool {
+        [self.memory, self.execute].iter().any(|stage| {
+            matches!(stage, PipelineStageStatus::Instruction(instr) if instr.get_dest_reg() == Some(reg))
+        })
+    }
+
+    /// Clears `reg` from the pending registers once its last in-flight writer
+    /// has been written back
+    fn release_pending_reg(&mut self, reg: (RegisterGroup, usize)) -> bool {
+        !self.has_pending_writer(reg) && self.pending_reg.remove(&reg)
+    }
+
     pub fn get_display_instr_addr(&self) -> Option<usize> {
         if let PipelineStageStatus::Instruction(PipelineInstruction {
             src_addr: Some(src_addr),
@@ -2226,6 +2530,8 @@ pub struct PipelineInstruction {
     raw_instr: Option<RawInstruction>, // the instruction as stored in memory
     decode_instr: Option<Instruction>, // the decoded instruction
     instr_result: PipelineInstructionResult, // the result of executing this instruction
+    predicted_pc: Option<u32>, // address the fetch stage continued at after this instruction
+    fetch_busy_at: Option<Cycle>, // `fetch_busy_cycles` when fetch moved on to `predicted_pc`
 }
 impl PipelineInstruction {
     /// Returns the target register group and number, if applicable
@@ -2257,4 +2563,312 @@ impl PipelineInstruction {
             Some(Instruction::Type6 { freg_1, .. }) => Some((RegisterGroup::FloatingPoint, freg_1)),
         }
     }
+
+    /// Returns the exception the instruction raised, if any
+    pub fn trap_cause(&self) -> Option<TrapCause> {
+        if let PipelineInstructionResult::Exception { cause } = self.instr_result {
+            Some(cause)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the register result, if one has been computed yet
+    pub fn forwardable_result(&self) -> Option<MemBlock> {
+        match self.instr_result {
+            PipelineInstructionResult::Register { data, .. } => Some(data),
+            PipelineInstructionResult::JumpSubRoutine { ret_reg_val, .. } => {
+                Some(MemBlock::Unsigned32(ret_reg_val))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::config::config::MachineConfig;
+    use crate::instruction::instruction::{Instruction, IMMEDIATE_FIELD_WIDTH};
+    use crate::memory::console::{CONSOLE_CHAR_IN, CONSOLE_INT_OUT};
+    use crate::memory::memory_system::{MemBlock, MEM_BLOCK_WIDTH};
+    use crate::register::register_system::{RET_REG, TRAP_CAUSE_REG};
+    use crate::system::system::{System, SystemMessage};
+    use crate::system::trap::{Trap, TrapCause};
+
+    const HALT: u32 = 1 << 3;
+    const DIVI_R1_R2_R3: u32 = 5 | (3 << 3) | (1 << 7) | (2 << 11) | (3 << 15);
+    const ILLEGAL: u32 = 7;
+
+    /// Encodes `LD32 R1, address`
+    fn ld32(address: u32) -> u32 {
+        4 | (2 << 3) | (1 << 7) | (address << 11)
+    }
+
+    /// Encodes `ST32 R1, address`
+    fn st32(address: u32) -> u32 {
+        4 | (8 << 3) | (1 << 7) | (address << 11)
+    }
+
+    /// Encodes `ADDIM R<reg>, immediate`
+    fn addim(reg: usize, immediate: u32) -> u32 {
+        Instruction::Type4 {
+            opcode: 9,
+            reg_1: reg,
+            immediate,
+        }
+        .encode()
+    }
+
+    /// Encodes the three register instruction `opcode` (e.g. 9 for `ADDU`)
+    fn type5(opcode: u32, reg_1: usize, reg_2: usize, reg_3: usize) -> u32 {
+        Instruction::Type5 {
+            opcode,
+            reg_1,
+            reg_2,
+            reg_3,
+        }
+        .encode()
+    }
+
+    /// Counts R1 down from 3, adding 5 to R2 each time around
+    fn countdown_loop() -> Vec<u32> {
+        vec![
+            addim(1, 3),
+            addim(3, 1),
+            addim(2, 5),
+            type5(10, 1, 1, 3), // SUBU R1, R1, R3
+            Instruction::Type2 {
+                opcode: 2, // CMP32 R1, R0
+                reg_1: 1,
+                reg_2: 0,
+            }
+            .encode(),
+            Instruction::Type1 {
+                opcode: 2, // JNE to the ADDIM
+                immediate: 2 * 32,
+            }
+            .encode(),
+            HALT,
+        ]
+    }
+
+    fn load(system: &mut System, program: &[u32]) {
+        for (i, raw) in program.iter().enumerate() {
+            system
+                .memory_system
+                .force_store(i * MEM_BLOCK_WIDTH, MemBlock::Unsigned32(*raw));
+        }
+    }
+
+    /// Runs `program` until the system halts or stops on an exception
+    fn run(config: MachineConfig, program: &[u32]) -> (System, SystemMessage) {
+        let mut system = System::new(config).unwrap();
+        load(&mut system, program);
+        for _ in 0..10_000 {
+            match system.step() {
+                SystemMessage::Halt => return (system, SystemMessage::Halt),
+                SystemMessage::Exception(trap) if trap.handler.is_none() => {
+                    return (system, SystemMessage::Exception(trap));
+                }
+                _ => {}
+            }
+        }
+        panic!("Program didn't finish");
+    }
+
+    fn configs() -> [MachineConfig; 2] {
+        [
+            MachineConfig::default(),
+            MachineConfig {
+                pipeline: false,
+                ..MachineConfig::default()
+            },
+        ]
+    }
+
+    #[test]
+    fn counts_retired_instructions() {
+        let reports = configs().map(|config| {
+            let (system, msg) = run(config, &countdown_loop());
+            assert_eq!(msg, SystemMessage::Halt);
+            assert_eq!(system.registers.general[2].data, MemBlock::Unsigned32(15));
+            system.report()
+        });
+
+        // the pipeline changes how long the program takes, not what it runs
+        let [pipelined, unpipelined] = &reports;
+        assert_eq!(pipelined.system.instructions_retired, 2 + 3 * 4 + 1);
+        assert_eq!(
+            pipelined.system.instructions_retired,
+            unpipelined.system.instructions_retired
+        );
+        for report in &reports {
+            #[allow(clippy::cast_precision_loss)]
+            let cpi = report.clock as f64 / report.system.instructions_retired as f64;
+            assert!((report.cpi().unwrap() - cpi).abs() < f64::EPSILON);
+        }
+    }
+
+    #[test]
+    fn addim_sign_extends_negative_immediates() {
+        // -1 as the assembler encodes it, in the 21-bit immediate field
+        let minus_one = (1 << IMMEDIATE_FIELD_WIDTH) - 1;
+        let program = [addim(1, 5), addim(1, minus_one), addim(2, minus_one), HALT];
+        for config in configs() {
+            let (system, msg) = run(config, &program);
+            assert_eq!(msg, SystemMessage::Halt);
+            assert_eq!(system.registers.general[1].data, MemBlock::Unsigned32(4));
+            assert_eq!(
+                system.registers.general[2].data,
+                MemBlock::Unsigned32(u32::MAX)
+            );
+        }
+    }
+
+    #[test]
+    fn addim_sign_extends_high_addresses() {
+        // the assembler rejects these, as the top bit of the field is the sign
+        let program = [addim(1, 0x10_0000), addim(2, 0xF_FFFF), HALT];
+        for config in configs() {
+            let (system, msg) = run(config, &program);
+            assert_eq!(msg, SystemMessage::Halt);
+            assert_eq!(
+                system.registers.general[1].data,
+                MemBlock::Unsigned32(0xFFF0_0000)
+            );
+            assert_eq!(
+                system.registers.general[2].data,
+                MemBlock::Unsigned32(0xF_FFFF)
+            );
+        }
+    }
+
+    #[test]
+    fn forwarding_removes_stalls() {
+        // each instruction needs the result of the one before it
+        let program = [
+            addim(1, 3),
+            type5(9, 2, 1, 1), // ADDU R2, R1, R1
+            type5(9, 3, 2, 2), // ADDU R3, R2, R2
+            type5(9, 4, 3, 1), // ADDU R4, R3, R1
+            HALT,
+        ];
+        let [stalling, forwarding] = [false, true].map(|forwarding| {
+            let config = MachineConfig {
+                forwarding,
+                ..MachineConfig::default()
+            };
+            let (system, msg) = run(config, &program);
+            assert_eq!(msg, SystemMessage::Halt);
+            system
+        });
+
+        assert_eq!(stalling.registers.general[4].data, MemBlock::Unsigned32(15));
+        assert_eq!(stalling.registers.general, forwarding.registers.general);
+        assert_eq!(stalling.registers.status, forwarding.registers.status);
+        assert_eq!(stalling.stats.forwarded_operands, 0);
+        assert!(forwarding.stats.forwarded_operands > 0);
+        assert!(forwarding.clock < stalling.clock);
+    }
+
+    #[test]
+    fn mispredictions_squash_the_wrong_path() {
+        let program = [
+            addim(1, 1),
+            Instruction::Type2 {
+                opcode: 2, // CMP32 R1, R0
+                reg_1: 1,
+                reg_2: 0,
+            }
+            .encode(),
+            Instruction::Type1 {
+                opcode: 2, // JNE over the ADDIM, predicted not taken
+                immediate: 4 * 32,
+            }
+            .encode(),
+            addim(2, 99),
+            HALT,
+        ];
+        let (system, msg) = run(MachineConfig::default(), &program);
+        assert_eq!(msg, SystemMessage::Halt);
+        assert_eq!(system.registers.general[2].data, MemBlock::Unsigned32(0));
+        assert_eq!(system.stats.instructions_retired, 4);
+        assert_eq!(system.stats.mispredictions, 1);
+        assert_eq!(system.stats.squashed_instructions, 1);
+        // only the cycles spent fetching the ADDIM count
+        assert_eq!(system.stats.squash_penalty_cycles, 2);
+    }
+
+    #[test]
+    fn unhandled_exception_stops_the_system() {
+        for config in configs() {
+            let (mut system, msg) = run(config, &[DIVI_R1_R2_R3, HALT]);
+            let trap = Trap {
+                cause: TrapCause::DivideByZero,
+                pc: 0,
+                handler: None,
+            };
+            assert_eq!(msg, SystemMessage::Exception(trap));
+            // stays stopped until reset
+            assert_eq!(system.step(), SystemMessage::Exception(trap));
+            assert_eq!(system.stats.exceptions, 1);
+            assert_eq!(system.stats.instructions_retired, 0);
+        }
+    }
+
+    #[test]
+    fn memory_faults() {
+        for config in configs() {
+            for (address, cause) in [
+                (3, TrapCause::MisalignedAccess),
+                (32_768, TrapCause::AddressOutOfRange),
+            ] {
+                let (_, msg) = run(config.clone(), &[ld32(address), HALT]);
+                assert_eq!(
+                    msg,
+                    SystemMessage::Exception(Trap {
+                        cause,
+                        pc: 0,
+                        handler: None
+                    })
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn exceptions_vector_to_handler() {
+        for config in configs() {
+            let config = MachineConfig {
+                trap_handler: Some(0x80),
+                ..config
+            };
+            // the division would raise a second exception if it weren't squashed
+            let program = [ILLEGAL, DIVI_R1_R2_R3, ILLEGAL, ILLEGAL, HALT];
+            let (system, msg) = run(config, &program);
+            assert_eq!(msg, SystemMessage::Halt);
+            assert_eq!(system.stats.exceptions, 1);
+            assert_eq!(
+                system.registers.general[RET_REG].data,
+                MemBlock::Unsigned32(0)
+            );
+            assert_eq!(
+                system.registers.general[TRAP_CAUSE_REG].data,
+                MemBlock::Unsigned32(TrapCause::IllegalInstruction.code())
+            );
+        }
+    }
+
+    #[test]
+    fn console_echoes_input() {
+        let char_in = u32::try_from(CONSOLE_CHAR_IN).unwrap();
+        let int_out = u32::try_from(CONSOLE_INT_OUT).unwrap();
+        for config in configs() {
+            let mut system = System::new(config).unwrap();
+            system.memory_system.console_mut().push_input(b"*");
+            load(&mut system, &[ld32(char_in), st32(int_out), HALT]);
+            while system.step() != SystemMessage::Halt {}
+            assert_eq!(system.memory_system.console().output(), "42");
+        }
+    }
 }
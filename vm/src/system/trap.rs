@@ -0,0 +1,86 @@
+#![warn(clippy::all, clippy::pedantic)]
+
+use std::fmt::Display;
+
+/// Reasons an instruction can raise an exception, along with the code a
+/// trap handler finds in `TRAP_CAUSE_REG`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TrapCause {
+    /// Undecodable instruction, or an opcode its type doesn't define
+    IllegalInstruction = 1,
+    /// Instruction fetch or data access not aligned to a 32-bit word
+    MisalignedAccess = 2,
+    /// Integer division or modulo by zero
+    DivideByZero = 3,
+    /// Instruction fetch or data access beyond the end of main memory
+    AddressOutOfRange = 4,
+}
+
+impl TrapCause {
+    #[must_use]
+    pub fn code(self) -> u32 {
+        self as u32
+    }
+}
+
+impl Display for TrapCause {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IllegalInstruction => write!(f, "Illegal instruction"),
+            Self::MisalignedAccess => write!(f, "Misaligned access"),
+            Self::DivideByZero => write!(f, "Divide by zero"),
+            Self::AddressOutOfRange => write!(f, "Address out of range"),
+        }
+    }
+}
+
+// lets the memory system report a cause through `anyhow`
+impl std::error::Error for TrapCause {}
+
+/// A precise exception raised by the instruction at `pc`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Trap {
+    pub cause: TrapCause,
+    pub pc: u32,
+    pub handler: Option<u32>, // address execution was vectored to, if any
+}
+
+impl Display for Trap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} (cause {}) at 0x{:08X}",
+            self.cause,
+            self.cause.code(),
+            self.pc
+        )?;
+        if let Some(handler) = self.handler {
+            write!(f, ", vectored to handler at 0x{handler:08X}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::system::trap::{Trap, TrapCause};
+
+    #[test]
+    fn cause_survives_anyhow() {
+        let err = anyhow::Error::new(TrapCause::MisalignedAccess).context("Load request");
+        assert_eq!(
+            err.downcast_ref::<TrapCause>(),
+            Some(&TrapCause::MisalignedAccess)
+        );
+    }
+
+    #[test]
+    fn trap_display() {
+        let trap = Trap {
+            cause: TrapCause::DivideByZero,
+            pc: 0x40,
+            handler: None,
+        };
+        assert_eq!(trap.to_string(), "Divide by zero (cause 3) at 0x00000040");
+    }
+}
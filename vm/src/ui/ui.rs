@@ -1,25 +1,32 @@
 use iced::event::Event;
 use iced::widget::scrollable::Properties;
 use iced::widget::{button, checkbox, pane_grid, Button, Column, PaneGrid, Text};
-use iced::widget::{column, container, pick_list, row, scrollable, text, Scrollable};
+use iced::widget::{column, container, pick_list, row, scrollable, text, text_input, Scrollable};
 use iced::window;
 use iced::{event, Alignment, Color, Command, Element, Length, Subscription, Theme};
-use log::info;
+use log::{error, info};
 use std::collections::HashSet;
 use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::OnceLock;
 
 use once_cell::sync::Lazy;
 use strum::IntoEnumIterator;
 
+use crate::config::config::MachineConfig;
+use crate::debug::debug_info::DebugInfo;
 use crate::instruction::instruction::{decode_raw_instr, Instruction};
-use crate::memory::memory_system::{MemBlock, MEM_BLOCK_WIDTH};
+use crate::memory::memory_system::MEM_BLOCK_WIDTH;
 use crate::register::register_system::RegisterGroup;
 use crate::system::system::{System, SystemMessage};
 
 static SCROLLABLE_ID: Lazy<scrollable::Id> = Lazy::new(scrollable::Id::unique);
+// iced constructs the UI state through `Default`, so the machine description is
+// stashed here before starting the event loop
+static MACHINE_CONFIG: OnceLock<MachineConfig> = OnceLock::new();
 
-pub fn enter() -> iced::Result {
+pub fn enter(config: MachineConfig) -> iced::Result {
+    MACHINE_CONFIG.get_or_init(|| config);
     iced::program("Giggle-Flop", GiggleFlopUI::update, GiggleFlopUI::view)
         //.load(|| window::change_mode(window::Id::MAIN, Mode::Fullscreen))
         .subscription(GiggleFlopUI::subscription)
@@ -29,8 +36,8 @@ pub fn enter() -> iced::Result {
 
 struct GiggleFlopUI {
     system: System,
-    memory_levels: Vec<usize>,
-    current_memory_level: usize,
+    memory_levels: Vec<MemoryView>,
+    current_memory_level: MemoryView,
     run: bool, // run without stopping after every clock cycle
     register_groups: Vec<RegisterGroup>,
     current_register_group: RegisterGroup,
@@ -39,12 +46,18 @@ struct GiggleFlopUI {
     focus: Option<pane_grid::Pane>,
     use_pipeline: bool,
     breakpoints: HashSet<u32>,
+    console_input: String,            // line being typed into the console pane
+    debug_info: Option<DebugInfo>,    // from the `.dbg` file next to the loaded program
+    show_source: bool,                // show the source rather than the disassembly
+    breakpoint_input: String,         // label or line being typed in to break at
+    breakpoint_error: Option<String>, // why the last one typed in couldn't be set
+    load_error: Option<String>,       // why the last program couldn't be loaded
 }
 
 #[derive(Debug, Clone)]
 enum Message {
     Scrolled(scrollable::Viewport),
-    SelectMemoryLevel(usize),
+    SelectMemoryLevel(MemoryView),
     SelectRegisterGroup(RegisterGroup),
     AdvanceClock,
     RunProgram,
@@ -52,11 +65,33 @@ enum Message {
     LineClicked(u32),
     EventOccurred(Event),
     // UsePipeline(bool),
+    UseForwarding(bool),
+    ConsoleInputChanged(String),
+    SubmitConsoleInput,
+    ShowSource(bool),
+    BreakpointInputChanged(String),
+    SubmitBreakpoint,
     // maybe delete
     Clicked(pane_grid::Pane),
     Resized(pane_grid::ResizeEvent),
 }
 
+/// Memory level selectable in the memory view
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MemoryView {
+    ICache,
+    Level(usize),
+}
+
+impl std::fmt::Display for MemoryView {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ICache => write!(f, "I-Cache"),
+            Self::Level(level) => write!(f, "{level}"),
+        }
+    }
+}
+
 #[derive(Clone, Copy)]
 struct Pane {
     // Add data in here as necessary...
@@ -70,8 +105,15 @@ impl Pane {
 
 impl GiggleFlopUI {
     fn new() -> Self {
-        let system = System::default();
-        let memory_levels = (0..system.memory_system.num_levels()).collect();
+        let config = MACHINE_CONFIG.get().cloned().unwrap_or_default();
+        let system = System::new(config).expect("Machine config was already validated");
+        let memory_levels = system
+            .memory_system
+            .has_icache()
+            .then_some(MemoryView::ICache)
+            .into_iter()
+            .chain((0..system.memory_system.num_levels()).map(MemoryView::Level))
+            .collect();
         let register_groups = {
             let mut groups = Vec::new();
             for group in RegisterGroup::iter() {
@@ -80,11 +122,12 @@ impl GiggleFlopUI {
             groups
         };
         let (panes, _) = pane_grid::State::new(Pane::new());
+        let use_pipeline = system.config().pipeline;
 
         // Create these by reading from memory?
         GiggleFlopUI {
             memory_levels,
-            current_memory_level: system.memory_system.num_levels() - 1,
+            current_memory_level: MemoryView::Level(system.memory_system.num_levels() - 1),
             run: false,
             register_groups,
             current_register_group: RegisterGroup::General,
@@ -92,8 +135,52 @@ impl GiggleFlopUI {
             panes,
             focus: None,
             system,
-            use_pipeline: true,
+            use_pipeline,
             breakpoints: HashSet::new(),
+            console_input: String::new(),
+            debug_info: None,
+            show_source: false,
+            breakpoint_input: String::new(),
+            breakpoint_error: None,
+            load_error: None,
+        }
+    }
+
+    /// Loads the program at `path`, along with the debug info the assembler
+    /// wrote next to it, if any
+    fn load_program(&mut self, path: PathBuf) {
+        let debug_info_path = DebugInfo::sidecar_path(&path);
+        self.debug_info = if debug_info_path.is_file() {
+            DebugInfo::from_file(&debug_info_path)
+                .map_err(|e| error!("{e:#}"))
+                .ok()
+        } else {
+            None
+        };
+        self.show_source = self.debug_info.is_some();
+        self.load_error = self
+            .system
+            .load_program(&path)
+            .map_err(|e| {
+                error!("{e:#}");
+                format!("{e:#}")
+            })
+            .err();
+    }
+
+    /// Returns the address of the instruction to highlight, which is the
+    /// oldest one in the pipeline
+    fn display_instr_addr(&self) -> u32 {
+        self.system
+            .get_display_instr_addr()
+            .map_or(self.system.registers.program_counter, |addr| {
+                u32::try_from(addr).unwrap()
+            })
+    }
+
+    fn toggle_breakpoint(&mut self, addr: u32) {
+        if !self.breakpoints.remove(&addr) {
+            self.breakpoints.insert(addr);
         }
     }
 
@@ -107,7 +194,7 @@ impl GiggleFlopUI {
                 self.current_scroll_offset = viewport.relative_offset();
             }
             Message::SelectMemoryLevel(level) => {
-                if level < self.system.memory_system.num_levels() {
+                if self.memory_levels.contains(&level) {
                     self.current_memory_level = level;
                 }
             }
@@ -117,9 +204,18 @@ impl GiggleFlopUI {
             Message::AdvanceClock => {
                 let mut cont = true;
                 while cont {
-                    if let SystemMessage::Halt = self.system.step() {
-                        info!("Got halt message");
-                        self.run = false;
+                    match self.system.step() {
+                        SystemMessage::Halt => {
+                            info!("Got halt message");
+                            info!("Performance report:\n{}", self.system.report());
+                            self.run = false;
+                        }
+                        SystemMessage::Exception(trap) if trap.handler.is_none() => {
+                            error!("Stopped on an unhandled exception: {trap}");
+                            self.run = false;
+                        }
+                        SystemMessage::Exception(trap) => info!("Exception: {trap}"),
+                        _ => {}
                     }
                     let effective_pc = if let Some(addr) = self.system.get_display_instr_addr() {
                         u32::try_from(addr).unwrap()
@@ -142,9 +238,18 @@ impl GiggleFlopUI {
                 self.run = !self.run;
                 let mut cont = true;
                 while cont {
-                    if let SystemMessage::Halt = self.system.step() {
-                        info!("Got halt message");
-                        self.run = false;
+                    match self.system.step() {
+                        SystemMessage::Halt => {
+                            info!("Got halt message");
+                            info!("Performance report:\n{}", self.system.report());
+                            self.run = false;
+                        }
+                        SystemMessage::Exception(trap) if trap.handler.is_none() => {
+                            error!("Stopped on an unhandled exception: {trap}");
+                            self.run = false;
+                        }
+                        SystemMessage::Exception(trap) => info!("Exception: {trap}"),
+                        _ => {}
                     }
                     let effective_pc = if let Some(addr) = self.system.get_display_instr_addr() {
                         u32::try_from(addr).unwrap()
@@ -165,85 +270,55 @@ impl GiggleFlopUI {
             }
             Message::EventOccurred(event) => {
                 if let Event::Window(_id, window::Event::FileDropped(path)) = event {
-                    self.system.load_program(path);
+                    self.load_program(path);
                 }
                 // NOTE: Check for other file events, maybe some different actions for them?
                 // e.g. hover, hover left, etc.
             }
             Message::LoadProgram => {
                 self.system.reset();
-                self.system
-                    .load_program(PathBuf::from_str("test_bin").unwrap());
-                /*
-                 * Matrix Multiply Benchmark Init
-                 */
-                let mut addr = 2432;
-                let data = MemBlock::Unsigned32(15);
-                self.system.memory_system.force_store(addr, data);
-                addr += MEM_BLOCK_WIDTH;
-                let data = MemBlock::Unsigned32(15);
-                self.system.memory_system.force_store(addr, data);
-                addr += MEM_BLOCK_WIDTH;
-                let data = MemBlock::Unsigned32(15);
-                self.system.memory_system.force_store(addr, data);
-                addr += MEM_BLOCK_WIDTH;
-                for _ in 0..15 {
-                    for _ in 0..15 {
-                        let data = MemBlock::Unsigned32(2);
-                        self.system.memory_system.force_store(addr, data);
-                        addr += MEM_BLOCK_WIDTH;
-                    }
-                }
-
-                for _ in 0..15 {
-                    for _ in 0..15 {
-                        let data = MemBlock::Unsigned32(2);
-                        self.system.memory_system.force_store(addr, data);
-                        addr += MEM_BLOCK_WIDTH;
-                    }
+                self.load_program(PathBuf::from_str("test_bin").unwrap());
+            }
+            Message::UseForwarding(enabled) => {
+                if enabled != self.system.bypass.is_enabled() {
+                    self.system.toggle_forwarding();
                 }
-                /*
-                 * Sorting Benchmark Init
-                 */
-                // let len = 100;
-                // let mut addr = 1152;
-                // for val in (0..len).rev() {
-                //     // store the value
-                //     let data = MemBlock::Unsigned32(val);
-                //     self.system.memory_system.force_store(addr, data);
-                //     // store the next pointer
-                //     addr += MEM_BLOCK_WIDTH;
-                //     let next = if val > 0 {
-                //         MemBlock::Unsigned32(addr as u32 + MEM_BLOCK_WIDTH as u32 * 4)
-                //     } else {
-                //         MemBlock::Unsigned32(0)
-                //     };
-                //     self.system.memory_system.force_store(addr, next);
-                //     addr += MEM_BLOCK_WIDTH * 4;
-                // }
-                /*
-                 * Linked List Sum Benchmark Init
-                 */
-                // let len = 100;
-                // let mut addr = 1152;
-                // for val in (0..len).rev() {
-                //     // store the value
-                //     let data = MemBlock::Unsigned32(val);
-                //     self.system.memory_system.force_store(addr, data);
-                //     // store the next pointer
-                //     addr += MEM_BLOCK_WIDTH;
-                //     let next = if val > 0 {
-                //         MemBlock::Unsigned32(addr as u32 + MEM_BLOCK_WIDTH as u32 * 4)
-                //     } else {
-                //         MemBlock::Unsigned32(0)
-                //     };
-                //     self.system.memory_system.force_store(addr, next);
-                //     addr += MEM_BLOCK_WIDTH * 4;
-                // }
+            }
+            Message::ConsoleInputChanged(input) => {
+                self.console_input = input;
+            }
+            Message::SubmitConsoleInput => {
+                let mut line = std::mem::take(&mut self.console_input);
+                line.push('\n');
+                self.system
+                    .memory_system
+                    .console_mut()
+                    .push_input(line.as_bytes());
             }
             Message::LineClicked(addr) => {
-                if !self.breakpoints.remove(&addr) {
-                    self.breakpoints.insert(addr);
+                self.toggle_breakpoint(addr);
+            }
+            Message::ShowSource(enabled) => {
+                self.show_source = enabled && self.debug_info.is_some();
+            }
+            Message::BreakpointInputChanged(input) => {
+                self.breakpoint_input = input;
+            }
+            Message::SubmitBreakpoint => {
+                let location = std::mem::take(&mut self.breakpoint_input);
+                let resolved = match self.debug_info {
+                    Some(ref debug_info) => debug_info.resolve_breakpoint(&location),
+                    None => Err(anyhow::anyhow!(
+                        "Breaking at labels and lines needs the program's debug info. \
+                         Assemble it with --debug-info"
+                    )),
+                };
+                match resolved {
+                    Ok(addr) => {
+                        self.toggle_breakpoint(addr);
+                        self.breakpoint_error = None;
+                    }
+                    Err(e) => self.breakpoint_error = Some(e.to_string()),
                 }
             }
             Message::Clicked(pane) => {
@@ -274,11 +349,33 @@ impl GiggleFlopUI {
                     .on_press(Message::LoadProgram)
             };
             let clock_text = format!("Clock: {}", self.system.clock);
+            let report = self.system.report();
+            let perf_text = format!(
+                "Retired: {}, CPI: {}, Mispredictions: {} ({}), Exceptions: {}",
+                report.system.instructions_retired,
+                report
+                    .cpi()
+                    .map_or_else(|| "N/A".to_string(), |cpi| format!("{cpi:.3}")),
+                report.system.mispredictions,
+                self.system.predictor.kind(),
+                report.system.exceptions
+            );
             Scrollable::with_direction(
-                row![text(clock_text), step_button(), run_button(), load_button(),]
-                    .align_items(Alignment::Center)
-                    .padding([0, 0, 0, 0])
-                    .spacing(20),
+                row![
+                    text(clock_text),
+                    text(perf_text),
+                    step_button(),
+                    run_button(),
+                    load_button(),
+                    checkbox("Forwarding", self.system.bypass.is_enabled())
+                        .on_toggle(Message::UseForwarding),
+                    checkbox("Source", self.show_source).on_toggle(Message::ShowSource),
+                    text(self.load_error.as_deref().unwrap_or_default())
+                        .color(Color::from_rgb(1.0, 0.0, 0.0)),
+                ]
+                .align_items(Alignment::Center)
+                .padding([0, 0, 0, 0])
+                .spacing(20),
                 {
                     let properties = Properties::new()
                         .width(10)
@@ -351,6 +448,47 @@ impl GiggleFlopUI {
         container(content).padding(20).center_x().center_y().into()
     }
 
+    fn get_console_element(&self) -> Element<Message> {
+        let scrollable_content: Element<Message> = Element::from({
+            Scrollable::with_direction(
+                row![
+                    text(self.system.memory_system.console().output()),
+                    text(" ".repeat(8)) // padding so scrollbar doesn't cover text
+                ],
+                {
+                    let properties = Properties::new()
+                        .width(10)
+                        .margin(0)
+                        .scroller_width(10)
+                        .alignment(scrollable::Alignment::End);
+
+                    scrollable::Direction::Both {
+                        horizontal: properties,
+                        vertical: properties,
+                    }
+                },
+            )
+            .width(Length::Fill)
+            .height(Length::Fill)
+        });
+
+        let input = text_input("Console input", &self.console_input)
+            .on_input(Message::ConsoleInputChanged)
+            .on_submit(Message::SubmitConsoleInput);
+        let pending = text(format!(
+            "{} bytes buffered",
+            self.system.memory_system.console().pending_input()
+        ));
+
+        let content: Element<Message> =
+            column![scrollable_content, row![input, pending].spacing(10)]
+                .align_items(Alignment::Start)
+                .spacing(10)
+                .into();
+
+        container(content).padding(20).center_x().center_y().into()
+    }
+
     fn get_register_element(&self) -> Element<Message> {
         let scrollable_content: Element<Message> = Element::from({
             Scrollable::with_direction(
@@ -404,12 +542,11 @@ impl GiggleFlopUI {
             Scrollable::with_direction(
                 row![
                     column![
-                        text(
-                            self.system
-                                .memory_system
-                                .get_level(self.current_memory_level)
-                                .unwrap()
-                        ),
+                        text(match self.current_memory_level {
+                            MemoryView::ICache => self.system.memory_system.get_icache().unwrap(),
+                            MemoryView::Level(level) =>
+                                self.system.memory_system.get_level(level).unwrap(),
+                        }),
                         text("")
                     ] // padding
                     .align_items(Alignment::Center)
@@ -451,7 +588,8 @@ impl GiggleFlopUI {
         container(content).padding(20).center_x().center_y().into()
     }
 
-    fn get_instruction_element(&self) -> Element<Message> {
+    /// Lists the disassembled instructions around the program counter
+    fn get_disassembly_column(&self) -> Column<Message> {
         let curr_pc = self.system.registers.program_counter as usize;
         let lookahead = MEM_BLOCK_WIDTH * 10;
         let raw_instrs: Vec<(usize, Option<Instruction>)> = (curr_pc.saturating_sub(lookahead)
@@ -495,6 +633,53 @@ impl GiggleFlopUI {
                 .padding(0);
             column = column.push(button);
         }
+        column
+    }
+
+    /// Lists the source of the file holding the current instruction, with the
+    /// address each line was assembled to. Clicking a line breaks at it
+    fn get_source_column(&self, debug_info: &DebugInfo) -> Option<Column<Message>> {
+        let curr_line = debug_info.line_at(self.display_instr_addr());
+        let file = curr_line.map_or(0, |mapping| mapping.file);
+        let source = debug_info.files.get(file)?;
+
+        let mut column = Column::new();
+        for (line, source_text) in (1..).zip(&source.lines) {
+            let addr = debug_info
+                .lines
+                .iter()
+                .find(|mapping| mapping.file == file && mapping.line == line)
+                .map(|mapping| mapping.address);
+            let addr_text = addr.map_or_else(|| " ".repeat(12), |addr| format!("0x{addr:08X}: "));
+            let mut text = Text::new(format!("{addr_text}{line:>4}  {source_text}"));
+            if curr_line.is_some_and(|mapping| mapping.file == file && mapping.line == line) {
+                text = text.color(Color::from_rgb(0.0, 1.0, 0.0));
+            }
+
+            let mut button = Button::new(text)
+                .style(
+                    if addr.is_some_and(|addr| self.breakpoints.contains(&addr)) {
+                        style::breakpoint_button
+                    } else {
+                        style::regular_button
+                    },
+                )
+                .padding(0);
+            if let Some(addr) = addr {
+                button = button.on_press(Message::LineClicked(addr));
+            }
+            column = column.push(button);
+        }
+        Some(column)
+    }
+
+    fn get_instruction_element(&self) -> Element<Message> {
+        let source_column = self
+            .debug_info
+            .as_ref()
+            .filter(|_| self.show_source)
+            .and_then(|debug_info| self.get_source_column(debug_info));
+        let column = source_column.unwrap_or_else(|| self.get_disassembly_column());
 
         let scrollable_content: Element<Message> = Element::from({
             Scrollable::with_direction(
@@ -517,10 +702,18 @@ impl GiggleFlopUI {
             .id(SCROLLABLE_ID.clone())
             .on_scroll(Message::Scrolled)
         });
-        let content: Element<Message> = column![scrollable_content]
-            .align_items(Alignment::Start)
-            .spacing(10)
-            .into();
+        let breakpoint_input =
+            text_input("Break at label, line, or file:line", &self.breakpoint_input)
+                .on_input(Message::BreakpointInputChanged)
+                .on_submit(Message::SubmitBreakpoint);
+        let breakpoint_error = text(self.breakpoint_error.as_deref().unwrap_or_default())
+            .color(Color::from_rgb(1.0, 0.0, 0.0));
+
+        let content: Element<Message> =
+            column![scrollable_content, breakpoint_input, breakpoint_error]
+                .align_items(Alignment::Start)
+                .spacing(10)
+                .into();
 
         container(content).padding(20).center_x().center_y().into()
     }
@@ -636,7 +829,30 @@ impl GiggleFlopUI {
         .on_resize(10, Message::Resized);
 
         let pipeline_pane: Element<Message> = container(pipeline_block)
-            .width(Length::Fill)
+            .width(Length::FillPortion(3))
+            .height(Length::Fill)
+            .padding(10)
+            .into();
+
+        let console_block = PaneGrid::new(&self.panes, |_id, _pane, _is_maximized| {
+            let title = row!["Console"].spacing(5);
+
+            let title_bar = pane_grid::TitleBar::new(title)
+                .padding(10)
+                .style(style::title_bar);
+
+            pane_grid::Content::new(self.get_console_element())
+                .title_bar(title_bar)
+                .style(style::pane)
+        })
+        .width(Length::Fill)
+        .height(Length::Fill)
+        .spacing(10)
+        .on_click(Message::Clicked)
+        .on_resize(10, Message::Resized);
+
+        let console_pane: Element<Message> = container(console_block)
+            .width(Length::FillPortion(2))
             .height(Length::Fill)
             .padding(10)
             .into();
@@ -644,7 +860,7 @@ impl GiggleFlopUI {
         column![
             config_pane,
             row![instruction_pane, register_pane, memory_pane],
-            pipeline_pane
+            row![pipeline_pane, console_pane]
         ]
         .height(Length::Fill)
         .into()